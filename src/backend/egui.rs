@@ -1,70 +1,162 @@
 use eframe::egui;
 use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
 use std::path::PathBuf;
-use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::core::mermaid::preprocess_mermaid_for_egui;
+use crate::core::diagram_tools::preprocess_tool_blocks_for_egui;
+use crate::core::highlight::fence_as_markdown_code;
+use crate::core::io::read_markdown;
+use crate::core::history::History;
+use crate::core::markdown::{
+    expand_code_tabs, expand_toc_markers, html_to_plain_text, link_references, parse_markdown,
+    strip_html_comments,
+};
+use crate::core::mermaid::{diagram_file_to_png_data_uri, diagram_files, is_diagram_file, preprocess_mermaid_for_egui};
+use crate::core::recent::{add_recent_file, pick_markdown_file, recent_files};
+use crate::core::search::{fuzzy_rank, matching_toc_entries, search_plain};
+use crate::core::svg_cache::{SvgCache, SVG_SOURCES};
+use crate::core::tab::Tab;
+use crate::core::meta::DocMeta;
+use crate::core::stats::{self, DocStats};
 use crate::core::toc::{self, TocEntry};
+use crate::core::watcher::{DirWatchGuard, FileEvent};
+use crate::core::window::{WindowOptions, DEFAULT_SIZE, MIN_SIZE};
+use crate::core::RenderSettings;
 
-pub fn run(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    let canonical_file = std::fs::canonicalize(&file_path)
-        .unwrap_or_else(|_| {
-            std::env::current_dir()
-                .map(|cwd| cwd.join(&file_path))
-                .unwrap_or_else(|_| file_path.clone())
-        });
-    let base_dir = canonical_file.parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    let raw_markdown = std::fs::read_to_string(&file_path)
-        .unwrap_or_else(|e| format!("# Error\nCould not read `{}`: {}", file_path.display(), e));
-
-    let toc_entries = toc::extract_toc(&raw_markdown);
-    let markdown = preprocess_mermaid_for_egui(&raw_markdown);
-    let markdown = resolve_local_image_paths(&markdown, &base_dir);
-    let (has_preamble, sections) = split_by_headings(&markdown);
-
-    let watcher_rx = crate::core::watcher::watch_file(&file_path)?;
+pub fn run(
+    files: Vec<PathBuf>,
+    settings: &RenderSettings,
+    start_line: Option<usize>,
+    window: WindowOptions,
+    dir_watch: Option<DirWatchGuard>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = settings.clone();
+    let mut tabs = files.into_iter()
+        .map(|file_path| Document::open(file_path, &settings))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut doc = tabs.remove(0);
+    doc.scroll_to_section = start_line.map(|line| section_for_source_line(&doc.source_sections, line));
 
-    let (icon_rgba, icon_w, icon_h) = crate::core::icon::load_icon_rgba();
+    let (width, height) = window.size.unwrap_or(DEFAULT_SIZE);
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size([width as f32, height as f32])
+        .with_min_inner_size([MIN_SIZE.0 as f32, MIN_SIZE.1 as f32])
+        .with_maximized(window.maximized)
+        .with_fullscreen(window.fullscreen)
+        .with_title(window_title(&doc.tab.file_path));
+    match crate::core::icon::load_icon_rgba() {
+        Ok((rgba, width, height)) => viewport = viewport.with_icon(egui::IconData { rgba, width, height }),
+        Err(e) => crate::vlog!("egui: window icon not loaded: {}", e),
+    }
 
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1100.0, 900.0])
-            .with_title(format!("mdr - {}", file_path.display()))
-            .with_icon(egui::IconData {
-                rgba: icon_rgba,
-                width: icon_w,
-                height: icon_h,
-            }),
+        viewport,
+        // eframe restores the last session's geometry over the builder above;
+        // flags given for this run shouldn't replace it
+        persist_window: !window.is_explicit(),
         ..Default::default()
     };
 
-    let file_path_clone = file_path.clone();
     eframe::run_native(
         "mdr",
         options,
-        Box::new(move |_cc| {
+        Box::new(move |cc| {
+            if window.is_explicit() {
+                apply_window_options(&cc.egui_ctx, window);
+            }
+            let always_on_top = window.always_on_top
+                || cc.storage.and_then(|storage| storage.get_string(ALWAYS_ON_TOP_KEY)).is_some_and(|value| value == "true");
+            if always_on_top {
+                cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::WindowLevel::AlwaysOnTop));
+            }
+            let section_markers = cc.storage
+                .and_then(|storage| storage.get_string(SECTION_MARKERS_KEY))
+                .is_some_and(|value| value == "true");
+            let focus_mode = settings.focus_mode
+                || cc.storage.and_then(|storage| storage.get_string(FOCUS_MODE_KEY)).is_some_and(|value| value == "true");
+            let split_ratio = cc.storage
+                .and_then(|storage| storage.get_string(SPLIT_RATIO_KEY))
+                .and_then(|value| value.parse::<f32>().ok())
+                .map(|ratio| ratio.clamp(MIN_SPLIT_RATIO, 1.0 - MIN_SPLIT_RATIO))
+                .unwrap_or(DEFAULT_SPLIT_RATIO);
             Ok(Box::new(MdrApp {
-                markdown,
-                sections,
-                has_preamble,
-                caches: Vec::new(),
-                file_path: file_path_clone,
-                base_dir,
-                watcher_rx,
-                toc_entries,
-                scroll_to_section: None,
+                doc,
+                tabs,
+                active_tab: 0,
+                history: History::default(),
+                follow: settings.follow,
+                settings,
                 search_active: false,
                 search_query: String::new(),
                 search_section_matches: Vec::new(),
                 current_match: 0,
+                search_toc: false,
+                search_toc_matches: Vec::new(),
+                show_source: false,
+                split_ratio,
+                source_scroll_to: None,
+                palette_open: false,
+                palette_query: String::new(),
+                palette_selected: 0,
+                status: None,
+                zoom: None,
+                always_on_top,
+                section_markers,
+                focus_mode,
+                dir_watch,
+                recent_files: recent_files(),
             }))
         }),
     )
     .map_err(|e| e.to_string().into())
 }
 
+/// Resize, maximize or fullscreen the window as asked on the command line,
+/// undoing the geometry eframe restored from the last session.
+fn apply_window_options(ctx: &egui::Context, window: WindowOptions) {
+    if let Some((width, height)) = window.size {
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width as f32, height as f32)));
+    }
+    ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(window.maximized));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(window.fullscreen));
+}
+
+fn window_title(file_path: &std::path::Path) -> String {
+    format!("mdr - {}", file_path.display())
+}
+
+/// Turn the file content into display sections.
+/// In raw mode each heading-delimited chunk of source is shown as a highlighted
+/// markdown code block, so TOC navigation still lands on the right heading.
+fn prepare_sections(content: &str, base_dir: &std::path::Path, settings: &RenderSettings) -> (bool, Vec<String>) {
+    if settings.raw {
+        let (has_preamble, sections) = split_by_headings(content);
+        return (has_preamble, sections.iter().map(|s| fence_as_markdown_code(s)).collect());
+    }
+    let markdown = strip_html_comments(content);
+    let markdown = link_references(&markdown, settings.repo_url.as_deref());
+    let markdown = expand_code_tabs(&markdown, settings.tab_width);
+    let markdown = expand_toc_markers(&markdown, false, settings);
+    let markdown = preprocess_mermaid_for_egui(&markdown, settings, base_dir);
+    let markdown = preprocess_tool_blocks_for_egui(&markdown, &settings.diagram_tools);
+    let markdown = resolve_local_image_paths(&markdown, base_dir);
+    split_by_headings(&markdown)
+}
+
+/// Index of the section containing 1-based source `line`, clamped to the last section.
+/// Sections are heading-delimited, so this is as precise as egui scrolling gets.
+fn section_for_source_line(source_sections: &[String], line: usize) -> usize {
+    let mut first_line = 1;
+    for (i, section) in source_sections.iter().enumerate() {
+        first_line += section.lines().count();
+        if line < first_line {
+            return i;
+        }
+    }
+    source_sections.len().saturating_sub(1)
+}
+
 /// Split markdown into sections at heading boundaries.
 /// Returns (has_preamble, sections) where has_preamble is true if there's
 /// content before the first heading (which means headings start at index 1).
@@ -99,41 +191,283 @@ fn split_by_headings(markdown: &str) -> (bool, Vec<String>) {
     (has_preamble, sections)
 }
 
-struct MdrApp {
-    markdown: String,
+/// How long transient status messages stay visible.
+const STATUS_DURATION: Duration = Duration::from_secs(3);
+
+/// Storage key for the source/preview split ratio.
+const SPLIT_RATIO_KEY: &str = "source_split_ratio";
+/// Storage key for whether the window stays above others.
+const ALWAYS_ON_TOP_KEY: &str = "always_on_top";
+/// Storage key for whether heading markers are drawn beside the scrollbar.
+const SECTION_MARKERS_KEY: &str = "section_markers";
+/// Storage key for whether sections other than the one being read are dimmed.
+const FOCUS_MODE_KEY: &str = "focus_mode";
+/// Opacity of the dimmed sections in focus mode.
+const UNFOCUSED_OPACITY: f32 = 0.35;
+/// Fraction of the content area given to the source pane by default.
+const DEFAULT_SPLIT_RATIO: f32 = 0.5;
+/// Neither pane may shrink below this fraction of the content area.
+const MIN_SPLIT_RATIO: f32 = 0.15;
+
+/// An open file and everything rendered from it.
+struct Document {
     sections: Vec<String>,
+    /// Raw source split at the same heading boundaries as `sections`.
+    source_sections: Vec<String>,
     has_preamble: bool,
     caches: Vec<CommonMarkCache>,
-    file_path: PathBuf,
-    base_dir: PathBuf,
-    watcher_rx: Receiver<()>,
+    /// Measured heights of the preview and source sections, for skipping those out of view.
+    preview_heights: SectionHeights,
+    source_heights: SectionHeights,
+    tab: Tab,
     toc_entries: Vec<TocEntry>,
+    stats: DocStats,
+    /// File age and last commit, with `--show-meta`.
+    meta: Option<DocMeta>,
     scroll_to_section: Option<usize>,
+    source_top_section: usize,
+    preview_top_section: usize,
+}
+
+impl Document {
+    fn open(file_path: PathBuf, settings: &RenderSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let raw_markdown = read_markdown(&file_path)
+            .unwrap_or_else(|e| format!("# Error\nCould not read `{}`: {}", file_path.display(), e));
+        let tab = Tab::open(file_path, settings)?;
+
+        let mut doc = Document {
+            sections: Vec::new(),
+            source_sections: Vec::new(),
+            has_preamble: false,
+            caches: Vec::new(),
+            preview_heights: SectionHeights::default(),
+            source_heights: SectionHeights::default(),
+            tab,
+            toc_entries: Vec::new(),
+            stats: DocStats::default(),
+            meta: None,
+            scroll_to_section: None,
+            source_top_section: 0,
+            preview_top_section: 0,
+        };
+        doc.load(&raw_markdown, settings);
+        Ok(doc)
+    }
+
+    /// 1-based source line where the section at the top of the preview starts.
+    fn top_line(&self) -> usize {
+        1 + self.source_sections.iter()
+            .take(self.preview_top_section)
+            .map(|section| section.lines().count())
+            .sum::<usize>()
+    }
+
+    fn load(&mut self, content: &str, settings: &RenderSettings) {
+        self.toc_entries = toc::extract_toc(content, settings);
+        self.stats = stats::document_stats(content);
+        self.meta = settings.show_meta.then(|| DocMeta::gather(&self.tab.file_path));
+        self.tab.watcher.watch_dependencies(&diagram_files(content, &self.tab.base_dir));
+        let (has_preamble, sections) = prepare_sections(content, &self.tab.base_dir, settings);
+        self.has_preamble = has_preamble;
+        self.sections = sections;
+        self.source_sections = split_by_headings(content).1;
+        self.caches.clear();
+        self.preview_heights = SectionHeights::default();
+        self.source_heights = SectionHeights::default();
+    }
+
+    /// Re-read the file from disk and rebuild the document from it.
+    fn reload(&mut self, settings: &RenderSettings) {
+        if let Ok(content) = read_markdown(&self.tab.file_path) {
+            self.load(&content, settings);
+        }
+    }
+
+    /// Reload if the watcher saw the file change or come back. A removed file
+    /// keeps its last content until it is back.
+    fn check_file(&mut self, settings: &RenderSettings) {
+        if self.tab.watcher.poll() == Some(FileEvent::Changed) {
+            self.reload(settings);
+        }
+    }
+
+    /// Section the TOC entry at `index` jumps to: the one its line is in. That is
+    /// the section a heading starts, or for a bold line listed by `--toc-heuristic`,
+    /// the section around it.
+    fn toc_section(&self, index: usize) -> usize {
+        section_for_source_line(&self.source_sections, self.toc_entries[index].line)
+    }
+}
+
+struct MdrApp {
+    /// The document being shown.
+    doc: Document,
+    /// The other open files, in tab order with the active one taken out.
+    tabs: Vec<Document>,
+    /// Position of `doc` in the tab strip.
+    active_tab: usize,
+    /// Places left by TOC, palette and tab jumps, for Alt+Left / Alt+Right.
+    history: History<(PathBuf, usize)>,
+    settings: RenderSettings,
+    /// Keep the preview pinned to the bottom while the file grows (until scrolled away).
+    follow: bool,
     search_active: bool,
     search_query: String,
     search_section_matches: Vec<usize>,
     current_match: usize,
+    /// Also match TOC entries: their links are highlighted in the sidebar and
+    /// the search starts at the first matching heading.
+    search_toc: bool,
+    search_toc_matches: Vec<usize>,
+    /// Ctrl+E: show the read-only source next to the preview.
+    show_source: bool,
+    /// Width of the source pane as a fraction of the content area (persisted).
+    split_ratio: f32,
+    source_scroll_to: Option<usize>,
+    /// Ctrl+P heading palette: query and the highlighted candidate.
+    palette_open: bool,
+    palette_query: String,
+    palette_selected: usize,
+    /// Transient message shown in the status bar, with when it was set.
+    status: Option<(String, Instant)>,
+    /// Pan/zoom viewer for a clicked image or diagram.
+    zoom: Option<ZoomView>,
+    /// Ctrl+Shift+T: keep the window above others (persisted).
+    always_on_top: bool,
+    /// Ctrl+M: mark each heading's position beside the preview scrollbar (persisted).
+    section_markers: bool,
+    /// Ctrl+D: dim every section but the one at the top of the preview (persisted).
+    focus_mode: bool,
+    /// `--watch-dir`: the directory whose most recently changed file is shown.
+    dir_watch: Option<DirWatchGuard>,
+    /// Files for the Open menu, most recent first.
+    recent_files: Vec<PathBuf>,
 }
 
 impl eframe::App for MdrApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Check for file changes
-        if self.watcher_rx.try_recv().is_ok() {
-            while self.watcher_rx.try_recv().is_ok() {}
-            if let Ok(content) = std::fs::read_to_string(&self.file_path) {
-                self.toc_entries = toc::extract_toc(&content);
-                self.markdown = preprocess_mermaid_for_egui(&content);
-                self.markdown = resolve_local_image_paths(&self.markdown, &self.base_dir);
-                let (has_preamble, sections) = split_by_headings(&self.markdown);
-                self.has_preamble = has_preamble;
-                self.sections = sections;
-                self.caches.clear();
-            }
+        // Ctrl+Tab / Ctrl+Shift+Tab cycle through the open files, Ctrl+W closes one
+        let (prev_tab, next_tab, close_tab) = ctx.input_mut(|i| {
+            let prev = i.consume_key(egui::Modifiers::CTRL | egui::Modifiers::SHIFT, egui::Key::Tab);
+            let next = i.consume_key(egui::Modifiers::CTRL, egui::Key::Tab);
+            (prev, next, i.consume_key(egui::Modifiers::CTRL, egui::Key::W))
+        });
+        let tab_count = self.tabs.len() + 1;
+        if prev_tab {
+            self.switch_tab(ctx, (self.active_tab + tab_count - 1) % tab_count);
+        }
+        if next_tab {
+            self.switch_tab(ctx, (self.active_tab + 1) % tab_count);
+        }
+        if close_tab {
+            self.close_tab(ctx, self.active_tab);
+        }
+
+        // Alt+Left / Alt+Right and the mouse back/forward buttons retrace jumps
+        let (back, forward) = ctx.input_mut(|i| {
+            let back = i.consume_key(egui::Modifiers::ALT, egui::Key::ArrowLeft)
+                || i.pointer.button_pressed(egui::PointerButton::Extra1);
+            let forward = i.consume_key(egui::Modifiers::ALT, egui::Key::ArrowRight)
+                || i.pointer.button_pressed(egui::PointerButton::Extra2);
+            (back, forward)
+        });
+        if back || forward {
+            self.navigate(ctx, back);
+        }
+        if !self.tabs.is_empty() {
+            self.show_tab_strip(ctx);
+        }
+
+        // Inactive tabs are reloaded when they are switched to
+        for tab in &mut self.tabs {
+            tab.check_file(&self.settings);
+        }
+
+        // Check for file changes; Ctrl+R reloads even if the watcher missed one
+        self.doc.check_file(&self.settings);
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::R)) {
+            self.doc.reload(&self.settings);
+            self.status = Some(("Reloaded".to_string(), Instant::now()));
+        }
+        if let Some(path) = self.dir_watch.as_ref().and_then(DirWatchGuard::changed_file) {
+            self.show_file(ctx, path);
+        }
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL | egui::Modifiers::SHIFT, egui::Key::T)) {
+            self.always_on_top = !self.always_on_top;
+            let level = if self.always_on_top { egui::WindowLevel::AlwaysOnTop } else { egui::WindowLevel::Normal };
+            ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(level));
+            let state = if self.always_on_top { "on" } else { "off" };
+            self.status = Some((format!("Always on top: {}", state), Instant::now()));
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::M)) {
+            self.section_markers = !self.section_markers;
+            let state = if self.section_markers { "on" } else { "off" };
+            self.status = Some((format!("Section markers: {}", state), Instant::now()));
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::D)) {
+            self.focus_mode = !self.focus_mode;
+            let state = if self.focus_mode { "on" } else { "off" };
+            self.status = Some((format!("Focus mode: {}", state), Instant::now()));
         }
 
         // Ensure we have enough caches
-        while self.caches.len() < self.sections.len() {
-            self.caches.push(CommonMarkCache::default());
+        while self.doc.caches.len() < self.doc.sections.len() {
+            self.doc.caches.push(CommonMarkCache::default());
+        }
+
+        // Ctrl+Shift+C copies the rendered HTML, Ctrl+Alt+C the plain text.
+        // egui turns any Ctrl+C into a Copy event, so check the extra modifier on that.
+        let copy_request = ctx.input(|i| {
+            let copy = i.events.iter().any(|e| matches!(e, egui::Event::Copy));
+            (copy && (i.modifiers.shift || i.modifiers.alt)).then_some(i.modifiers.shift)
+        });
+        if let Some(as_html) = copy_request {
+            if let Ok(content) = read_markdown(&self.doc.tab.file_path) {
+                let html = parse_markdown(&content, &self.settings);
+                let message = if as_html {
+                    ctx.copy_text(html);
+                    "Copied document as HTML"
+                } else {
+                    ctx.copy_text(html_to_plain_text(&html));
+                    "Copied document as plain text"
+                };
+                self.status = Some((message.to_string(), Instant::now()));
+            }
+        }
+        if let Some((message, at)) = &self.status {
+            if at.elapsed() < STATUS_DURATION {
+                egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+                    ui.label(message.as_str());
+                });
+            } else {
+                self.status = None;
+            }
+        }
+
+        // Handle Ctrl+E to toggle the source pane
+        if ctx.input(|i| i.key_pressed(egui::Key::E) && i.modifiers.ctrl && !i.modifiers.shift) {
+            self.show_source = !self.show_source;
+            if self.show_source {
+                self.source_scroll_to = Some(self.doc.preview_top_section);
+            }
+        }
+
+        // Handle Ctrl+P for the heading palette
+        if ctx.input(|i| i.key_pressed(egui::Key::P) && i.modifiers.ctrl) {
+            self.palette_open = !self.palette_open;
+            self.palette_query.clear();
+            self.palette_selected = 0;
+        }
+        if self.palette_open {
+            self.show_palette(ctx);
+        }
+
+        // The zoom viewer takes Esc before search does
+        if let Some(zoom) = &mut self.zoom {
+            if !zoom.show(ctx) {
+                self.zoom = None;
+            }
         }
 
         // Handle Ctrl+F for search
@@ -141,13 +475,22 @@ impl eframe::App for MdrApp {
             self.search_active = !self.search_active;
             if !self.search_active {
                 self.search_query.clear();
-                self.search_section_matches.clear();
+                self.update_search_matches();
             }
         }
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) && self.search_active {
             self.search_active = false;
             self.search_query.clear();
-            self.search_section_matches.clear();
+            self.update_search_matches();
+        }
+
+        if self.doc.tab.watcher.is_removed() {
+            egui::TopBottomPanel::top("removed_banner").show(ctx, |ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} was removed. Showing the last version until it is back.", self.doc.tab.title()),
+                );
+            });
         }
 
         // Search bar panel
@@ -156,21 +499,11 @@ impl eframe::App for MdrApp {
                 ui.horizontal(|ui| {
                     ui.label("Search:");
                     let response = ui.text_edit_singleline(&mut self.search_query);
-                    if response.changed() {
-                        // Update matches
-                        self.search_section_matches.clear();
-                        self.current_match = 0;
-                        if !self.search_query.is_empty() {
-                            let query_lower = self.search_query.to_lowercase();
-                            for (i, section) in self.sections.iter().enumerate() {
-                                if section.to_lowercase().contains(&query_lower) {
-                                    self.search_section_matches.push(i);
-                                }
-                            }
-                            if !self.search_section_matches.is_empty() {
-                                self.scroll_to_section = Some(self.search_section_matches[0]);
-                            }
-                        }
+                    let toc_toggled = ui.checkbox(&mut self.search_toc, "TOC")
+                        .on_hover_text("Also match table of contents entries")
+                        .changed();
+                    if response.changed() || toc_toggled {
+                        self.update_search_matches();
                     }
                     // Request focus on first show
                     if response.gained_focus() || ctx.input(|i| i.key_pressed(egui::Key::F) && i.modifiers.ctrl) {
@@ -192,27 +525,34 @@ impl eframe::App for MdrApp {
                             } else {
                                 self.current_match - 1
                             };
-                            self.scroll_to_section = Some(self.search_section_matches[self.current_match]);
+                            self.doc.scroll_to_section = Some(self.search_section_matches[self.current_match]);
                         }
                     }
                     if ui.button("\u{25BC}").clicked() || (ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift) && self.search_active) {
                         if !self.search_section_matches.is_empty() {
                             self.current_match = (self.current_match + 1) % self.search_section_matches.len();
-                            self.scroll_to_section = Some(self.search_section_matches[self.current_match]);
+                            self.doc.scroll_to_section = Some(self.search_section_matches[self.current_match]);
                         }
                     }
                     if ui.button("\u{2715}").clicked() {
                         self.search_active = false;
                         self.search_query.clear();
-                        self.search_section_matches.clear();
+                        self.update_search_matches();
                     }
                 });
             });
         }
 
         // TOC sidebar
-        let has_preamble = self.has_preamble;
-        let scroll_target = &mut self.scroll_to_section;
+        let has_preamble = self.doc.has_preamble;
+        let mut toc_jump = None;
+        // Heading of the section at the top of the preview (as of the last frame)
+        let top_line = self.doc.top_line();
+        let shown = toc::shown_entries(&self.doc.toc_entries, self.settings.toc_depth);
+        let active = toc::active_entry(&self.doc.toc_entries, top_line)
+            .and_then(|i| toc::shown_position(&shown, i))
+            .map(|position| shown[position]);
+        let toc_matches = &self.search_toc_matches;
 
         egui::SidePanel::left("toc_panel")
             .default_width(220.0)
@@ -220,55 +560,746 @@ impl eframe::App for MdrApp {
                 ui.heading("Table of Contents");
                 ui.separator();
                 egui::ScrollArea::vertical().show(ui, |ui| {
-                    for (i, entry) in self.toc_entries.iter().enumerate() {
+                    for &i in &shown {
+                        let entry = &self.doc.toc_entries[i];
                         let indent = ((entry.level as f32 - 1.0) * 12.0).max(0.0);
                         ui.horizontal(|ui| {
                             ui.add_space(indent);
-                            let text = match entry.level {
+                            let mut text = match entry.level {
                                 1 => egui::RichText::new(&entry.text).strong(),
                                 2 => egui::RichText::new(&entry.text).strong().size(13.0),
                                 3 => egui::RichText::new(&entry.text).size(13.0),
                                 _ => egui::RichText::new(&entry.text).size(12.0).weak(),
                             };
+                            if active == Some(i) {
+                                text = text.strong().background_color(ui.visuals().selection.bg_fill);
+                            } else if toc_matches.contains(&i) {
+                                text = text.background_color(ui.visuals().warn_fg_color.gamma_multiply(0.35));
+                            }
                             if ui.link(text).clicked() {
-                                // Map TOC index to section index
-                                let section_idx = if has_preamble { i + 1 } else { i };
-                                *scroll_target = Some(section_idx);
+                                toc_jump = Some(self.doc.toc_section(i));
                             }
                         });
                     }
                 });
             });
+        if let Some(section) = toc_jump {
+            self.jump_to_section(section);
+        }
 
         // Main content - render each section with scroll anchors
-        let scroll_to = self.scroll_to_section.take();
-
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                for (i, section) in self.sections.iter().enumerate() {
-                    // Place an invisible anchor widget before the section
-                    let response = ui.allocate_response(
-                        egui::vec2(0.0, 0.0),
-                        egui::Sense::hover(),
-                    );
-
-                    // If this is the target section, scroll to the anchor
-                    if scroll_to == Some(i) {
-                        response.scroll_to_me(Some(egui::Align::TOP));
-                    }
+        let scroll_to = self.doc.scroll_to_section.take();
+        let source_scroll_to = self.source_scroll_to.take().or(scroll_to);
 
-                    // Render the section
-                    let anchor_id = ui.id().with(format!("section_{}", i));
-                    ui.push_id(anchor_id, |ui| {
-                        CommonMarkViewer::new()
-                            .show(ui, &mut self.caches[i], section);
+        // Scroll positions and folded sections are remembered per tab
+        let tab_id = egui::Id::new(&self.doc.tab.file_path);
+        let mut zoom_request = None;
+        egui::CentralPanel::default().show(ctx, |ui| ui.push_id(tab_id, |ui| {
+            if self.show_source {
+                let total_width = ui.available_width();
+                let source_sections = &self.doc.source_sections;
+                let source_heights = &mut self.doc.source_heights;
+                let panel = egui::SidePanel::left("source_panel")
+                    .resizable(true)
+                    .default_width(total_width * self.split_ratio)
+                    .width_range(total_width * MIN_SPLIT_RATIO..=total_width * (1.0 - MIN_SPLIT_RATIO))
+                    .show_inside(ui, |ui| {
+                        show_sections(ui, "source_scroll", source_sections.len(), source_heights, source_scroll_to, false, |ui, i| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut source_sections[i].as_str())
+                                    .code_editor()
+                                    .frame(false)
+                                    .desired_width(f32::INFINITY),
+                            );
+                        })
                     });
+                self.split_ratio = (panel.response.rect.width() / total_width)
+                    .clamp(MIN_SPLIT_RATIO, 1.0 - MIN_SPLIT_RATIO);
+
+                // Scrolling the source pane drives the preview
+                let top = panel.inner.top;
+                if panel.inner.hovered && top != self.doc.source_top_section {
+                    self.doc.scroll_to_section = Some(top);
+                    ctx.request_repaint();
+                }
+                self.doc.source_top_section = top;
+            }
+
+            // Focus mode dims every section but the one holding the active heading
+            let focused = self.focus_mode
+                .then(|| section_for_source_line(&self.doc.source_sections, toc::focus_range(&self.doc.toc_entries, top_line).start));
+            let sections = &self.doc.sections;
+            let caches = &mut self.doc.caches;
+            let heights = &mut self.doc.preview_heights;
+            let view = show_sections(ui, "preview_scroll", sections.len(), heights, scroll_to, self.follow, |ui, i| {
+                if focused.is_some_and(|section| section != i) {
+                    ui.set_opacity(UNFOCUSED_OPACITY);
+                }
+                if let Some(uri) = show_markdown(ui, &mut caches[i], &sections[i]) {
+                    zoom_request = Some(uri);
                 }
             });
-        });
+            let top = view.top;
+            if self.section_markers {
+                if let Some(section) = show_section_markers(ui, &view, &self.doc.toc_entries, has_preamble) {
+                    self.doc.scroll_to_section = Some(section);
+                    ctx.request_repaint();
+                }
+            }
+
+            // Scrolling the preview drives the source pane
+            if self.show_source && view.hovered && top != self.doc.preview_top_section {
+                self.source_scroll_to = Some(top);
+                ctx.request_repaint();
+            }
+            self.doc.preview_top_section = top;
+        }));
+        if let Some(uri) = zoom_request {
+            self.zoom = Some(ZoomView::new(uri));
+        }
+
+        // Word count and reading time in the bottom-right corner, under the file's age,
+        // with a button that opens the file in the user's editor (also Ctrl+Shift+E)
+        // and a menu to open another file (Ctrl+O) or a recent one
+        let mut edit = ctx.input(|i| i.key_pressed(egui::Key::E) && i.modifiers.ctrl && i.modifiers.shift);
+        let mut pick_file = ctx.input(|i| i.key_pressed(egui::Key::O) && i.modifiers.ctrl);
+        let mut open_recent = None;
+        egui::Area::new(egui::Id::new("doc_stats"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-16.0, -8.0))
+            .show(ctx, |ui| {
+                if let Some(meta) = &self.doc.meta {
+                    ui.label(egui::RichText::new(meta.to_string()).small().weak());
+                }
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(self.doc.stats.to_string()).small().weak());
+                    edit |= ui.small_button("✏ Edit").on_hover_text("Open in your editor (Ctrl+Shift+E)").clicked();
+                    ui.menu_button("📂 Open", |ui| {
+                        pick_file |= ui.button("Open file… (Ctrl+O)").clicked();
+                        if !self.recent_files.is_empty() {
+                            ui.separator();
+                            ui.label(egui::RichText::new("Recent files").small().weak());
+                        }
+                        for file in &self.recent_files {
+                            let name = file.file_name().map_or_else(|| file.display().to_string(), |name| name.to_string_lossy().into_owned());
+                            if ui.button(name).on_hover_text(file.display().to_string()).clicked() {
+                                open_recent = Some(file.clone());
+                            }
+                        }
+                    });
+                });
+            });
+        // A cancelled dialog just leaves the current file up
+        if let Some(file) = open_recent.or_else(|| pick_file.then(pick_markdown_file).flatten()) {
+            self.open_file(ctx, file);
+        }
+        if edit {
+            let message = match crate::core::editor::spawn(self.settings.editor.as_deref(), &self.doc.tab.file_path, self.doc.top_line()) {
+                Ok(()) => "Opened in the editor".to_string(),
+                Err(e) => format!("Could not open the editor: {}", e),
+            };
+            self.status = Some((message, Instant::now()));
+        }
 
         ctx.request_repaint_after(std::time::Duration::from_millis(500));
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(SPLIT_RATIO_KEY, self.split_ratio.to_string());
+        storage.set_string(ALWAYS_ON_TOP_KEY, self.always_on_top.to_string());
+        storage.set_string(SECTION_MARKERS_KEY, self.section_markers.to_string());
+        storage.set_string(FOCUS_MODE_KEY, self.focus_mode.to_string());
+    }
+}
+
+impl MdrApp {
+    /// Where the preview is: the file and the section at its top.
+    fn place(&self) -> (PathBuf, usize) {
+        (self.doc.tab.file_path.clone(), self.doc.preview_top_section)
+    }
+
+    /// Scroll to `section`, remembering the current place for Alt+Left.
+    fn jump_to_section(&mut self, section: usize) {
+        self.history.record(self.place());
+        self.doc.scroll_to_section = Some(section);
+    }
+
+    /// Go back (or forward) to a place recorded by a jump. Places in tabs that
+    /// have since been closed are skipped.
+    fn navigate(&mut self, ctx: &egui::Context, back: bool) {
+        loop {
+            let target = if back { self.history.back(self.place()) } else { self.history.forward(self.place()) };
+            let Some((file_path, section)) = target else {
+                return;
+            };
+            if file_path != self.doc.tab.file_path {
+                let Some(index) = self.open_documents().position(|doc| doc.tab.file_path == file_path) else {
+                    continue;
+                };
+                self.show_tab(ctx, index);
+            }
+            self.doc.scroll_to_section = Some(section);
+            return;
+        }
+    }
+
+    /// Every open document, in tab strip order.
+    fn open_documents(&self) -> impl Iterator<Item = &Document> {
+        self.tabs[..self.active_tab].iter()
+            .chain(std::iter::once(&self.doc))
+            .chain(&self.tabs[self.active_tab..])
+    }
+
+    /// Switch to the tab at `index` in the tab strip, as a jump that Alt+Left undoes.
+    fn switch_tab(&mut self, ctx: &egui::Context, index: usize) {
+        if index == self.active_tab || index > self.tabs.len() {
+            return;
+        }
+        self.history.record(self.place());
+        self.show_tab(ctx, index);
+    }
+
+    fn show_tab(&mut self, ctx: &egui::Context, index: usize) {
+        // `tabs` holds every tab but the active one, so positions past it shift by one
+        let next = self.tabs.remove(if index < self.active_tab { index } else { index - 1 });
+        let previous = std::mem::replace(&mut self.doc, next);
+        self.tabs.insert(self.active_tab - usize::from(index < self.active_tab), previous);
+        self.active_tab = index;
+        self.tab_changed(ctx);
+    }
+
+    /// Switch to the tab showing `path`, opening one for it at the end of the
+    /// strip if needed (`--watch-dir`, Ctrl+O).
+    fn show_file(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let open = self.open_documents().position(|doc| doc.tab.file_path.canonicalize().ok().as_ref() == Some(&path));
+        let index = match open {
+            Some(index) => index,
+            None => match Document::open(path.clone(), &self.settings) {
+                Ok(doc) => {
+                    // Every position is before the end, so the new tab is the last one
+                    self.tabs.push(doc);
+                    self.tabs.len()
+                }
+                Err(e) => {
+                    crate::vlog!("egui: could not open {}: {}", path.display(), e);
+                    return;
+                }
+            },
+        };
+        if index != self.active_tab {
+            self.switch_tab(ctx, index);
+            self.status = Some((format!("Showing {}", path.display()), Instant::now()));
+        }
+    }
+
+    /// Show `file`, picked with Ctrl+O or from the recent files, in a tab of its own
+    /// unless it is already open.
+    fn open_file(&mut self, ctx: &egui::Context, file: PathBuf) {
+        let file = file.canonicalize().unwrap_or(file);
+        add_recent_file(&file);
+        self.recent_files = recent_files();
+        self.show_file(ctx, file);
+    }
+
+    /// Close the tab at `index`, dropping its watcher. Closing the last tab closes the window.
+    fn close_tab(&mut self, ctx: &egui::Context, index: usize) {
+        if self.tabs.is_empty() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return;
+        }
+        if index != self.active_tab {
+            self.tabs.remove(if index < self.active_tab { index } else { index - 1 });
+            if index < self.active_tab {
+                self.active_tab -= 1;
+            }
+            return;
+        }
+        // The tab to the right takes its place, or the one to the left at the end
+        if self.active_tab == self.tabs.len() {
+            self.active_tab -= 1;
+        }
+        self.doc = self.tabs.remove(self.active_tab);
+        self.tab_changed(ctx);
+    }
+
+    fn tab_changed(&mut self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(window_title(&self.doc.tab.file_path)));
+        self.palette_open = false;
+        self.source_scroll_to = Some(self.doc.preview_top_section);
+        self.update_search_matches();
+    }
+
+    /// Draw the tab strip shown when more than one file is open.
+    fn show_tab_strip(&mut self, ctx: &egui::Context) {
+        let titles: Vec<String> = self.open_documents().map(|doc| doc.tab.title()).collect();
+        let mut switch_to = None;
+        let mut close = None;
+        egui::TopBottomPanel::top("tab_strip").show(ctx, |ui| {
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (i, title) in titles.iter().enumerate() {
+                        if ui.selectable_label(i == self.active_tab, title).clicked() {
+                            switch_to = Some(i);
+                        }
+                        if ui.small_button("\u{2715}").on_hover_text("Close (Ctrl+W)").clicked() {
+                            close = Some(i);
+                        }
+                        ui.separator();
+                    }
+                });
+            });
+        });
+        if let Some(i) = switch_to {
+            self.switch_tab(ctx, i);
+        }
+        if let Some(i) = close {
+            self.close_tab(ctx, i);
+        }
+    }
+
+    /// Recompute the sections (and, with `search_toc`, TOC entries) matching the
+    /// search query and scroll to the first hit, preferring a matching heading.
+    fn update_search_matches(&mut self) {
+        self.search_section_matches.clear();
+        self.search_toc_matches.clear();
+        self.current_match = 0;
+        if self.search_query.is_empty() {
+            return;
+        }
+        for (i, section) in self.doc.sections.iter().enumerate() {
+            if !search_plain(section.lines(), &self.search_query).is_empty() {
+                self.search_section_matches.push(i);
+            }
+        }
+        if self.search_toc {
+            self.search_toc_matches = matching_toc_entries(&self.doc.toc_entries, &self.search_query);
+            let heading_sections: Vec<usize> = self.search_toc_matches.iter().map(|&i| self.doc.toc_section(i)).collect();
+            self.search_section_matches.extend(&heading_sections);
+            self.search_section_matches.sort_unstable();
+            self.search_section_matches.dedup();
+            if let Some(first) = heading_sections.first() {
+                self.current_match = self.search_section_matches.iter().position(|s| s == first).unwrap_or(0);
+            }
+        }
+        if let Some(&section) = self.search_section_matches.get(self.current_match) {
+            self.doc.scroll_to_section = Some(section);
+        }
+    }
+
+    /// Draw the heading palette and handle its keys: arrows move the selection,
+    /// Enter jumps to the heading, Esc dismisses.
+    fn show_palette(&mut self, ctx: &egui::Context) {
+        let candidates = fuzzy_rank(&self.palette_query, self.doc.toc_entries.iter().map(|e| e.text.as_str()));
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        if up {
+            self.palette_selected = self.palette_selected.saturating_sub(1);
+        }
+        if down {
+            self.palette_selected = (self.palette_selected + 1).min(candidates.len().saturating_sub(1));
+        }
+        let mut jump_to = enter.then(|| candidates.get(self.palette_selected).copied()).flatten();
+
+        egui::Window::new("Go to heading")
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+            .fixed_size([420.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.palette_query)
+                        .hint_text("Go to heading…")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.palette_selected = 0;
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    if candidates.is_empty() {
+                        ui.label(egui::RichText::new("No matching headings").weak());
+                    }
+                    for (rank, &i) in candidates.iter().enumerate() {
+                        let entry = &self.doc.toc_entries[i];
+                        let label = format!("{}{}", "  ".repeat((entry.level as usize).saturating_sub(1)), entry.text);
+                        let item = ui.selectable_label(rank == self.palette_selected, label);
+                        if rank == self.palette_selected && (up || down) {
+                            item.scroll_to_me(None);
+                        }
+                        if item.clicked() {
+                            jump_to = Some(i);
+                        }
+                    }
+                });
+            });
+
+        if let Some(i) = jump_to {
+            self.jump_to_section(self.doc.toc_section(i));
+        }
+        if jump_to.is_some() || escape {
+            self.palette_open = false;
+        }
+    }
+}
+
+/// Pan/zoom viewer for an image or diagram, opened by clicking it.
+struct ZoomView {
+    uri: String,
+    /// The SVG the image was rasterized from, rasterized again as the zoom
+    /// changes so diagrams stay sharp.
+    svg: Option<Arc<str>>,
+    /// Part of the image in view, in image points (see `egui::Scene`).
+    scene_rect: egui::Rect,
+    /// Texture rasterized from `svg`, the scale asked for, and the image size in points.
+    sharp: Option<(egui::TextureHandle, f32, egui::Vec2)>,
+}
+
+impl ZoomView {
+    fn new(uri: String) -> Self {
+        let svg = SVG_SOURCES.get(&uri);
+        Self { uri, svg, scene_rect: egui::Rect::ZERO, sharp: None }
+    }
+
+    /// Draw the viewer window. Returns false once it has been closed.
+    fn show(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = !ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape));
+        if self.sharp.is_none() {
+            self.rasterize(ctx, ctx.pixels_per_point());
+        }
+        egui::Window::new("Zoom")
+            .open(&mut open)
+            .collapsible(false)
+            .default_rect(ctx.content_rect().shrink(40.0))
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new("Scroll to zoom, drag to pan, double-click to fit, Esc to close").small().weak());
+                let view_size = ui.available_size();
+                let response = egui::Scene::new()
+                    .zoom_range(0.05..=16.0)
+                    .show(ui, &mut self.scene_rect, |ui| match &self.sharp {
+                        Some((texture, _, size)) => ui.add(egui::Image::new((texture.id(), *size))),
+                        None => ui.add(egui::Image::from_uri(&self.uri).fit_to_original_size(1.0)),
+                    })
+                    .response;
+                if response.double_clicked() {
+                    // An empty rect makes the scene fit the image again
+                    self.scene_rect = egui::Rect::ZERO;
+                }
+                let zoom = (view_size / self.scene_rect.size()).min_elem();
+                self.rasterize(ctx, zoom * ctx.pixels_per_point());
+            });
+        open
+    }
+
+    /// Rasterize the SVG at `scale` unless the last rasterization is close enough.
+    fn rasterize(&mut self, ctx: &egui::Context, scale: f32) {
+        let Some(svg) = &self.svg else {
+            return;
+        };
+        if !scale.is_finite() {
+            return;
+        }
+        let scale = scale.clamp(0.5, 8.0);
+        if self.sharp.as_ref().is_some_and(|(_, last, _)| (0.75..=1.5).contains(&(scale / last))) {
+            return;
+        }
+        match crate::core::mermaid::rasterize_svg(svg, scale) {
+            Ok((pixmap, used_scale)) => {
+                let pixels = [pixmap.width() as usize, pixmap.height() as usize];
+                let image = egui::ColorImage::from_rgba_premultiplied(pixels, pixmap.data());
+                let texture = ctx.load_texture("zoom_view", image, egui::TextureOptions::LINEAR);
+                let size = egui::vec2(pixels[0] as f32, pixels[1] as f32) / used_scale;
+                self.sharp = Some((texture, scale, size));
+            }
+            Err(e) => {
+                crate::vlog!("zoom: SVG rasterization failed: {}", e);
+                self.svg = None;
+            }
+        }
+    }
+}
+
+/// A run of plain markdown, an image standing in a paragraph of its own (which
+/// can be clicked to zoom, and is captioned with its title), or a `<details>`
+/// block shown as a collapsing header.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Markdown(String),
+    Image { markdown: String, uri: String, title: Option<String> },
+    Details { summary: String, open: bool, body: String },
+}
+
+/// Render markdown, turning `<details>`/`<summary>` blocks into collapsing headers.
+/// Returns the URI of a standalone image that was clicked.
+fn show_markdown(ui: &mut egui::Ui, cache: &mut CommonMarkCache, markdown: &str) -> Option<String> {
+    let mut clicked = None;
+    for (i, segment) in split_segments(markdown).iter().enumerate() {
+        match segment {
+            Segment::Markdown(md) => {
+                ui.push_id(i, |ui| {
+                    CommonMarkViewer::new().show(ui, cache, md);
+                });
+            }
+            Segment::Image { markdown, uri, title } => {
+                let response = ui.push_id(i, |ui| CommonMarkViewer::new().show(ui, cache, markdown).response).inner;
+                let zoom = ui.interact(response.rect, response.id.with("zoom"), egui::Sense::click())
+                    .on_hover_cursor(egui::CursorIcon::ZoomIn);
+                if zoom.clicked() {
+                    clicked = Some(uri.clone());
+                }
+                if let Some(title) = title {
+                    ui.label(egui::RichText::new(title).italics().small().weak());
+                }
+            }
+            Segment::Details { summary, open, body } => {
+                let body_clicked = egui::CollapsingHeader::new(summary.as_str())
+                    .id_salt(i)
+                    .default_open(*open)
+                    .show(ui, |ui| show_markdown(ui, cache, body))
+                    .body_returned
+                    .flatten();
+                clicked = clicked.or(body_clicked);
+            }
+        }
+    }
+    clicked
+}
+
+/// The URI and title of an image that is alone on its line, like
+/// `![diagram](data:… "Title")`.
+fn standalone_image(line: &str) -> Option<(&str, Option<&str>)> {
+    use std::sync::OnceLock;
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r#"^\s*!\[[^\]]*\]\(([^)\s]+)(?:\s+(?:"([^"]*)"|'([^']*)'))?\)\s*$"#).unwrap());
+    re.captures(line).map(|caps| {
+        let title = caps.get(2).or(caps.get(3)).map(|m| m.as_str());
+        (caps.get(1).unwrap().as_str(), title)
+    })
+}
+
+/// Split markdown into plain runs, standalone images and top-level `<details>` blocks.
+/// Nested blocks stay in the body and are split again when it is rendered.
+fn split_segments(markdown: &str) -> Vec<Segment> {
+    use crate::core::details::{is_details_close, parse_details_open, parse_summary, summary_line_rest};
+
+    let mut segments = Vec::new();
+    let mut plain = String::new();
+    let mut lines = markdown.lines().peekable();
+    let mut in_code_block = false;
+
+    while let Some(line) = lines.next() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        let open = match parse_details_open(line) {
+            Some(open) if !in_code_block => open,
+            _ => {
+                // An image between blank lines is a paragraph of its own
+                let own_paragraph = (plain.is_empty() || plain.ends_with("\n\n"))
+                    && lines.peek().is_none_or(|next| next.trim().is_empty());
+                if let Some((uri, title)) = standalone_image(line).filter(|_| !in_code_block && own_paragraph) {
+                    if !plain.is_empty() {
+                        segments.push(Segment::Markdown(std::mem::take(&mut plain)));
+                    }
+                    segments.push(Segment::Image {
+                        markdown: line.to_string(),
+                        uri: uri.to_string(),
+                        title: title.filter(|title| !title.is_empty()).map(str::to_string),
+                    });
+                    continue;
+                }
+                // A stray closing tag has nothing to close
+                if in_code_block || !is_details_close(line) {
+                    plain.push_str(line);
+                    plain.push('\n');
+                }
+                continue;
+            }
+        };
+
+        if !plain.is_empty() {
+            segments.push(Segment::Markdown(std::mem::take(&mut plain)));
+        }
+
+        let mut summary = parse_summary(line).map(|summary| (summary, line));
+        if summary.is_none() {
+            while lines.peek().is_some_and(|l| l.trim().is_empty()) {
+                lines.next();
+            }
+            summary = lines.peek().and_then(|&l| parse_summary(l).map(|summary| (summary, l)));
+            if summary.is_some() {
+                lines.next();
+            }
+        }
+
+        // Body text after `</summary>`, which may close the block on the same line
+        let (rest, closed) = summary.as_ref().map_or(("", false), |(_, line)| summary_line_rest(line));
+        let mut body = if rest.is_empty() { String::new() } else { format!("{}\n", rest) };
+        let mut depth = 0usize;
+        let mut in_body_code = false;
+        // A block closed on its summary line has no more body
+        let body_lines = if closed { 0 } else { usize::MAX };
+        for line in lines.by_ref().take(body_lines) {
+            if line.trim_start().starts_with("```") {
+                in_body_code = !in_body_code;
+            }
+            if !in_body_code {
+                if parse_details_open(line).is_some() {
+                    depth += 1;
+                } else if is_details_close(line) {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        segments.push(Segment::Details {
+            summary: summary.map_or_else(|| "Details".to_string(), |(summary, _)| summary),
+            open,
+            body,
+        });
+    }
+
+    if !plain.is_empty() {
+        segments.push(Segment::Markdown(plain));
+    }
+    segments
+}
+
+/// The height each section of a scroll area took when it was last laid out, so
+/// [`show_sections`] can stand in empty space for the sections out of view.
+#[derive(Default)]
+struct SectionHeights {
+    /// Content width the heights were measured at; text wraps differently at others.
+    width: f32,
+    heights: Vec<Option<f32>>,
+}
+
+/// Where the sections of a scroll area laid out by [`show_sections`] ended up.
+struct SectionsView {
+    /// The section at the top of the viewport.
+    top: usize,
+    /// Whether the pointer is over the scroll area.
+    hovered: bool,
+    /// Each section's distance from the top of the content.
+    anchor_offsets: Vec<f32>,
+    content_height: f32,
+    /// The visible part of the scroll area, in screen coordinates.
+    viewport: egui::Rect,
+}
+
+/// Lay out `count` sections in a vertical scroll area, each preceded by an invisible anchor.
+/// Scrolls to `scroll_to` if set; with `stick_to_bottom` the view stays at the end as the
+/// content grows, until the user scrolls away.
+///
+/// Only the sections in view are rendered. The others take up the space they were last
+/// measured at in `heights`, so the scrollbar and the anchors stay where they would be;
+/// sections never measured yet, and all of them when the width changes, are rendered.
+fn show_sections(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    count: usize,
+    heights: &mut SectionHeights,
+    scroll_to: Option<usize>,
+    stick_to_bottom: bool,
+    mut add_section: impl FnMut(&mut egui::Ui, usize),
+) -> SectionsView {
+    let mut anchor_tops = Vec::with_capacity(count);
+    let scroll_area = egui::ScrollArea::vertical().id_salt(id_salt).stick_to_bottom(stick_to_bottom);
+    let output = scroll_area.show(ui, |ui| {
+        let width = ui.available_width();
+        if heights.width != width || heights.heights.len() != count {
+            *heights = SectionHeights { width, heights: vec![None; count] };
+        }
+        let visible = ui.clip_rect();
+        for i in 0..count {
+            let response = ui.allocate_response(egui::vec2(0.0, 0.0), egui::Sense::hover());
+
+            // If this is the target section, scroll to the anchor
+            if scroll_to == Some(i) {
+                response.scroll_to_me(Some(egui::Align::TOP));
+            }
+            anchor_tops.push(response.rect.top());
+
+            match heights.heights[i] {
+                Some(height) if !visible.intersects(egui::Rect::from_min_size(ui.cursor().min, egui::vec2(width, height))) => {
+                    ui.allocate_space(egui::vec2(width, height));
+                }
+                _ => {
+                    let anchor_id = ui.id().with(format!("section_{}", i));
+                    let section = ui.push_id(anchor_id, |ui| add_section(ui, i));
+                    heights.heights[i] = Some(section.response.rect.height());
+                }
+            }
+        }
+    });
+    let viewport = output.inner_rect;
+    let content_top = viewport.top() - output.state.offset.y;
+    SectionsView {
+        top: top_visible_section(&anchor_tops, viewport.top()),
+        hovered: ui.rect_contains_pointer(viewport),
+        anchor_offsets: anchor_tops.iter().map(|top| top - content_top).collect(),
+        content_height: output.content_size.y,
+        viewport,
+    }
+}
+
+/// Draw a clickable tick beside the scrollbar for each heading section of `view`,
+/// named by its heading's TOC entry on hover. Returns the section whose tick was clicked.
+fn show_section_markers(ui: &egui::Ui, view: &SectionsView, toc_entries: &[TocEntry], has_preamble: bool) -> Option<usize> {
+    if view.content_height <= view.viewport.height() {
+        return None;
+    }
+    let scroll = &ui.spacing().scroll;
+    let right = view.viewport.right() - scroll.bar_width - scroll.bar_outer_margin - 2.0;
+    let mut clicked = None;
+    let mut headings = toc_entries.iter().filter(|entry| !entry.bold_line);
+    for (i, &offset) in view.anchor_offsets.iter().enumerate().skip(usize::from(has_preamble)) {
+        let Some(entry) = headings.next() else {
+            break;
+        };
+        let y = view.viewport.top() + marker_position(offset, view.content_height, view.viewport.height());
+        let width = if entry.level <= 2 { 10.0 } else { 6.0 };
+        let tick = egui::Rect::from_min_max(egui::pos2(right - width, y - 1.0), egui::pos2(right, y + 1.0));
+        let response = ui.interact(tick.expand2(egui::vec2(2.0, 2.0)), ui.id().with(("section_marker", i)), egui::Sense::click());
+        let color = if i == view.top || response.hovered() {
+            ui.visuals().selection.bg_fill
+        } else {
+            ui.visuals().weak_text_color()
+        };
+        ui.painter().rect_filled(tick, 0.0, color);
+        if response.on_hover_text(&entry.text).clicked() {
+            clicked = Some(i);
+        }
+    }
+    clicked
+}
+
+/// Where content at `offset` sits on a scrollbar track `track_height` tall, for
+/// content `content_height` tall.
+fn marker_position(offset: f32, content_height: f32, track_height: f32) -> f32 {
+    if content_height <= 0.0 {
+        return 0.0;
+    }
+    (offset / content_height).clamp(0.0, 1.0) * track_height
+}
+
+/// Index of the last section whose anchor sits at or above the top of the viewport.
+fn top_visible_section(anchor_tops: &[f32], viewport_top: f32) -> usize {
+    anchor_tops
+        .iter()
+        .rposition(|&top| top <= viewport_top + 1.0)
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -374,20 +1405,238 @@ mod tests {
         assert!(sections[0].contains("Line 2"));
         assert!(sections[1].contains("Line 3"));
     }
+
+    // --- top_visible_section tests ---
+
+    #[test]
+    fn top_visible_section_picks_last_anchor_above_viewport() {
+        let anchors = [-500.0, -20.0, 40.0, 300.0];
+        assert_eq!(top_visible_section(&anchors, 0.0), 1);
+        assert_eq!(top_visible_section(&anchors, 40.0), 2);
+    }
+
+    #[test]
+    fn top_visible_section_defaults_to_first() {
+        assert_eq!(top_visible_section(&[10.0, 50.0], 0.0), 0);
+        assert_eq!(top_visible_section(&[], 0.0), 0);
+    }
+
+    #[test]
+    fn marker_position_scales_offsets_to_the_track() {
+        assert_eq!(marker_position(0.0, 4000.0, 400.0), 0.0);
+        assert_eq!(marker_position(1000.0, 4000.0, 400.0), 100.0);
+        assert_eq!(marker_position(5000.0, 4000.0, 400.0), 400.0);
+        assert_eq!(marker_position(10.0, 0.0, 400.0), 0.0);
+    }
+
+    #[test]
+    fn sections_out_of_view_keep_their_height_without_rendering() {
+        let ctx = egui::Context::default();
+        let mut heights = SectionHeights::default();
+        let mut time = 0.0;
+        let mut frame = |scroll_to| {
+            // A second per frame, so scrolling animations finish right away
+            time += 1.0;
+            let input = egui::RawInput {
+                screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(400.0, 300.0))),
+                time: Some(time),
+                ..Default::default()
+            };
+            let mut rendered = Vec::new();
+            let mut view = None;
+            let _ = ctx.run(input, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    view = Some(show_sections(ui, "test", 50, &mut heights, scroll_to, false, |ui, i| {
+                        rendered.push(i);
+                        ui.allocate_space(egui::vec2(100.0, 100.0));
+                    }));
+                });
+            });
+            (rendered, view.unwrap())
+        };
+
+        // Everything is measured once, then only what is in view is rendered
+        let (rendered, first) = frame(None);
+        assert_eq!(rendered.len(), 50);
+        let (rendered, second) = frame(None);
+        assert!(rendered.len() < 5 && rendered[0] == 0, "{:?}", rendered);
+        assert_eq!(second.content_height, first.content_height);
+        assert_eq!(second.anchor_offsets, first.anchor_offsets);
+
+        // Jumping far down still lands on the section
+        frame(Some(40));
+        frame(None);
+        let (rendered, _) = frame(None);
+        assert!(rendered.contains(&40) && !rendered.contains(&0), "{:?}", rendered);
+    }
+
+    // --- split_details tests ---
+
+    #[test]
+    fn split_details_wraps_block_with_summary() {
+        let md = "Intro\n\n<details>\n<summary>FAQ</summary>\n\nAnswer **here**\n</details>\n\nOutro\n";
+        let segments = split_segments(md);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0], Segment::Markdown("Intro\n\n".to_string()));
+        assert_eq!(segments[1], Segment::Details {
+            summary: "FAQ".to_string(),
+            open: false,
+            body: "\nAnswer **here**\n".to_string(),
+        });
+        assert_eq!(segments[2], Segment::Markdown("\nOutro\n".to_string()));
+    }
+
+    #[test]
+    fn split_details_closes_a_one_line_block() {
+        let md = "<details><summary>FAQ</summary>Answer</details>\n\n# After\n";
+        assert_eq!(split_segments(md), vec![
+            Segment::Details { summary: "FAQ".to_string(), open: false, body: "Answer\n".to_string() },
+            Segment::Markdown("\n# After\n".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn split_details_keeps_nested_block_in_body() {
+        let md = "<details open><summary>Outer</summary>\n<details>\n<summary>Inner</summary>\nx\n</details>\ny\n</details>\n";
+        let segments = split_segments(md);
+        assert_eq!(segments.len(), 1);
+        let Segment::Details { summary, open, body } = &segments[0] else {
+            panic!("expected a details segment");
+        };
+        assert_eq!(summary, "Outer");
+        assert!(*open);
+        assert_eq!(split_segments(body).len(), 2);
+    }
+
+    #[test]
+    fn split_details_ignores_tags_in_code_blocks() {
+        let md = "```html\n<details>\n</details>\n```\n";
+        assert_eq!(split_segments(md), vec![Segment::Markdown(md.to_string())]);
+    }
+
+    #[test]
+    fn split_segments_separates_standalone_images() {
+        let md = "Intro\n\n![chart](data:image/png;base64,AAAA \"Chart\")\n\nSee ![icon](i.png) inline\n\n```\n\n![code](c.png)\n\n```\n";
+        let segments = split_segments(md);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0], Segment::Markdown("Intro\n\n".to_string()));
+        assert_eq!(segments[1], Segment::Image {
+            markdown: "![chart](data:image/png;base64,AAAA \"Chart\")".to_string(),
+            uri: "data:image/png;base64,AAAA".to_string(),
+            title: Some("Chart".to_string()),
+        });
+        let Segment::Markdown(rest) = &segments[2] else {
+            panic!("expected markdown");
+        };
+        assert!(rest.contains("![icon](i.png)") && rest.contains("![code](c.png)"));
+    }
+
+    // --- section_for_source_line tests ---
+
+    #[test]
+    fn section_for_source_line_maps_and_clamps() {
+        let (_, sections) = split_by_headings("intro\n# A\na1\na2\n# B\nb1\n");
+        assert_eq!(section_for_source_line(&sections, 0), 0);
+        assert_eq!(section_for_source_line(&sections, 1), 0);
+        assert_eq!(section_for_source_line(&sections, 2), 1);
+        assert_eq!(section_for_source_line(&sections, 4), 1);
+        assert_eq!(section_for_source_line(&sections, 5), 2);
+        assert_eq!(section_for_source_line(&sections, 500), 2);
+    }
+
+    #[test]
+    fn anchor_resolves_to_its_section() {
+        let md = "intro\n\n# Install\n\nsteps\n\n## From source\n\ncargo build\n\n# Usage\n\nrun it\n";
+        let (_, sections) = split_by_headings(md);
+        let entries = toc::extract_headings(md);
+        let section = |anchor: &str| toc::find_anchor(&entries, anchor).map(|i| section_for_source_line(&sections, entries[i].line));
+        assert_eq!(section("#install"), Some(1));
+        assert_eq!(section("from-source"), Some(2));
+        assert_eq!(section("#usage"), Some(3));
+        assert_eq!(section("#nowhere"), None);
+    }
+
+    // --- prepare_sections tests ---
+
+    #[test]
+    fn prepare_sections_raw_fences_each_section() {
+        let md = "# Title\n![img](missing.png)\n\n## Next\ntext\n";
+        let (has_preamble, sections) = prepare_sections(md, std::path::Path::new("."), &RenderSettings { raw: true, ..RenderSettings::default() });
+        assert!(!has_preamble);
+        assert_eq!(sections.len(), 2);
+        assert!(sections[0].starts_with("```markdown\n# Title"));
+        assert!(sections[0].contains("![img](missing.png)"));
+        assert!(sections[1].starts_with("```markdown\n## Next"));
+    }
+
+    // --- resolve_local_image_paths tests ---
+
+    #[test]
+    fn images_resolve_against_a_custom_base_dir() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_base_dir_{}", std::process::id()));
+        let assets = dir.join("assets");
+        std::fs::create_dir_all(&assets).unwrap();
+        std::fs::write(assets.join("logo.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let settings = RenderSettings { base_dir: Some(assets.clone()), ..RenderSettings::default() };
+        assert_eq!(settings.base_dir(&dir.join("doc.md")), assets);
+        let markdown = "# Doc\n\n![logo](logo.png)\n";
+        assert!(resolve_local_image_paths(markdown, &settings.base_dir(&dir.join("doc.md"))).contains("](data:image/png;base64,"));
+        // Against the document's own directory the image isn't found and is left alone
+        assert_eq!(resolve_local_image_paths(markdown, &dir), markdown);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn images_with_titles_parentheses_and_in_tables_resolve() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_image_syntax_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["pic.png", "pic(1).png", "my pic.png"] {
+            std::fs::write(dir.join(name), b"\x89PNG\r\n\x1a\n").unwrap();
+        }
+        let resolve = |markdown: &str| resolve_local_image_paths(markdown, &dir);
+        let uri = "data:image/png;base64,iVBORw0KGgo=";
+
+        assert_eq!(resolve(r#"![a](pic.png "A (small) title")"#), format!(r#"![a]({} "A (small) title")"#, uri));
+        assert_eq!(resolve("![a](pic.png 'quoted')"), format!("![a]({} 'quoted')", uri));
+        assert_eq!(resolve("![a](pic(1).png)"), format!("![a]({})", uri));
+        // Without angle brackets a space ends the URL, so this isn't an image
+        assert_eq!(resolve("![a](my pic.png)"), "![a](my pic.png)");
+        assert_eq!(resolve("![a](<my pic.png>)"), format!("![a]({})", uri));
+        assert_eq!(
+            resolve("| Logo | Name |\n|---|---|\n| ![a](pic.png) | x |"),
+            format!("| Logo | Name |\n|---|---|\n| ![a]({}) | x |", uri)
+        );
+        assert_eq!(resolve("- item ![a](pic.png \"t\") and ![b](https://x.org/b.png)"),
+            format!("- item ![a]({} \"t\") and ![b](https://x.org/b.png)", uri));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
 
 /// Resolve relative image paths in markdown to inline data URIs.
 /// We use data URIs for ALL images (not file:// URLs) because:
 /// - file:// URLs break when paths contain spaces
 /// - Data URIs are self-contained and always work
+///
 /// SVG files are rasterized to PNG first to avoid egui_commonmark parsing issues.
+/// Handles `![alt](src "title")` titles (which are kept), `<path with spaces>` and
+/// URLs with balanced parentheses, wherever the image sits: paragraphs, list items
+/// or table cells.
 fn resolve_local_image_paths(markdown: &str, base_dir: &std::path::Path) -> String {
     use std::sync::OnceLock;
     static RE: OnceLock<regex::Regex> = OnceLock::new();
-    let re = RE.get_or_init(|| regex::Regex::new(r"!\[([^\]]*)\]\(([^)]+)\)").unwrap());
+    let re = RE.get_or_init(|| {
+        regex::Regex::new(
+            r#"!\[([^\]]*)\]\(\s*(?:<([^>\n]*)>|((?:[^\s()]|\([^\s()]*\))+))(\s+(?:"[^"]*"|'[^']*'|\([^)]*\)))?\s*\)"#,
+        )
+        .unwrap()
+    });
     re.replace_all(markdown, |caps: &regex::Captures| {
         let alt = &caps[1];
-        let src = &caps[2];
+        let src = caps.get(2).or(caps.get(3)).map_or("", |m| m.as_str());
+        let title = caps.get(4).map_or("", |m| m.as_str());
         // Skip URLs and data URIs
         if src.starts_with("http://") || src.starts_with("https://")
             || src.starts_with("data:") || src.starts_with("file://")
@@ -402,6 +1651,16 @@ fn resolve_local_image_paths(markdown: &str, base_dir: &std::path::Path) -> Stri
             }
         }
         if abs_path.exists() {
+            // Mermaid diagram files are drawn as the diagram
+            if is_diagram_file(&abs_path) {
+                return match diagram_file_to_png_data_uri(&abs_path) {
+                    Ok(data_uri) => format!("![{}]({}{})", alt, data_uri, title),
+                    Err(e) => {
+                        crate::vlog!("mermaid: {} not rendered: {}", abs_path.display(), e);
+                        caps[0].to_string()
+                    }
+                };
+            }
             // SVG files: rasterize to PNG data URI to avoid parsing failures
             let is_svg = abs_path.extension()
                 .and_then(|e| e.to_str())
@@ -410,18 +1669,18 @@ fn resolve_local_image_paths(markdown: &str, base_dir: &std::path::Path) -> Stri
             if is_svg {
                 // Try rasterizing SVG to PNG (handles complex SVGs better)
                 if let Ok(data_uri) = rasterize_svg_to_png_data_uri(&abs_path) {
-                    return format!("![{}]({})", alt, data_uri);
+                    return format!("![{}]({}{})", alt, data_uri, title);
                 }
                 // Fallback: embed SVG directly as data URI for egui_commonmark's SVG feature
                 if let Ok(data_uri) = file_to_data_uri(&abs_path) {
-                    return format!("![{}]({})", alt, data_uri);
+                    return format!("![{}]({}{})", alt, data_uri, title);
                 }
                 // SVG completely failed — skip it
                 return caps[0].to_string();
             }
             // All non-SVG images: embed as base64 data URI
             if let Ok(data_uri) = file_to_data_uri(&abs_path) {
-                return format!("![{}]({})", alt, data_uri);
+                return format!("![{}]({}{})", alt, data_uri, title);
             }
             caps[0].to_string()
         } else {
@@ -453,11 +1712,6 @@ fn file_to_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error
 /// Rasterize an SVG file to PNG and return as a base64 data URI.
 /// Caps dimensions at 8192px to avoid GPU texture overflow.
 fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
-    use base64::Engine;
-    use std::sync::{Arc, OnceLock};
-
-    const MAX_DIM: f32 = 8192.0;
-
     let svg_data = std::fs::read_to_string(path)?;
 
     // Reject files that aren't actually SVG (e.g. HTML pages saved with .svg extension)
@@ -468,6 +1722,19 @@ fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<d
         }
     }
 
+    // Cached by content so reloads don't re-rasterize unchanged SVGs
+    static CACHE: SvgCache<String> = SvgCache::new();
+    let data_uri = CACHE.get_or_insert_with(&svg_data, 2.0, || render_svg_to_png_data_uri(&svg_data))?;
+    SVG_SOURCES.insert(&data_uri, &svg_data);
+    Ok(data_uri)
+}
+
+fn render_svg_to_png_data_uri(svg_data: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use base64::Engine;
+    use std::sync::{Arc, OnceLock};
+
+    const MAX_DIM: f32 = 8192.0;
+
     static FONTDB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
     let fontdb = FONTDB.get_or_init(|| {
         let mut db = usvg::fontdb::Database::new();
@@ -477,7 +1744,7 @@ fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<d
 
     let mut options = usvg::Options::default();
     options.fontdb = Arc::clone(fontdb);
-    let tree = usvg::Tree::from_str(&svg_data, &options)?;
+    let tree = usvg::Tree::from_str(svg_data, &options)?;
     let size = tree.size();
     let svg_w = size.width();
     let svg_h = size.height();
@@ -1,32 +1,312 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
-use tao::event::{Event, WindowEvent};
-use tao::event_loop::{ControlFlow, EventLoop};
-use tao::window::WindowBuilder;
-use wry::WebViewBuilder;
+use std::time::Instant;
 
-use crate::core::markdown::{parse_markdown, GITHUB_CSS};
+use tao::event::{Event, WindowEvent};
+use tao::event_loop::{ControlFlow, EventLoopBuilder};
+use tao::dpi::{LogicalPosition, LogicalSize};
+use tao::window::{Fullscreen, Window, WindowBuilder};
+use wry::{WebView, WebViewBuilder};
+
+use crate::core::highlight::highlight_markdown_html;
+use crate::core::io::read_markdown;
+use crate::core::markdown::{parse_markdown_with_sourcepos, wrap_code_lines, SourceposHtml, GITHUB_CSS};
+use crate::core::mermaid::{diagram_files, is_diagram_file, render_diagram_file};
+use crate::core::recent::{add_recent_file, recent_files};
+use crate::core::svg_cache::SvgCache;
+use crate::core::tab::Tab;
+use crate::core::meta::DocMeta;
+use crate::core::stats::{self, DocStats};
 use crate::core::toc;
+use crate::core::watcher::{DirWatchGuard, FileEvent};
+use crate::core::window::{WindowOptions, DEFAULT_SIZE, MIN_SIZE};
+use crate::core::config::{ScrollBehavior, Theme};
+use crate::core::RenderSettings;
 use crate::vlog;
 
-pub fn run(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    // Canonicalize the file path first so parent() always gives an absolute directory.
-    // Without this, a bare filename like "README.md" gives parent() = "" (empty),
-    // which breaks relative image resolution when CWD differs from expected.
-    let canonical_file = std::fs::canonicalize(&file_path)
-        .unwrap_or_else(|_| {
-            // If canonicalize fails, try current_dir + file_path
-            std::env::current_dir()
-                .map(|cwd| cwd.join(&file_path))
-                .unwrap_or_else(|_| file_path.clone())
-        });
-    let base_dir = canonical_file.parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    let markdown_content = std::fs::read_to_string(&file_path)?;
-    vlog!("webview: file_path={}", file_path.display());
-    vlog!("webview: base_dir={}", base_dir.display());
+pub fn run(
+    files: Vec<PathBuf>,
+    settings: &RenderSettings,
+    start_line: Option<usize>,
+    window_options: WindowOptions,
+    dir_watch: Option<DirWatchGuard>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = settings.clone();
+    let mut tabs = files.into_iter().map(|file| Tab::open(file, &settings)).collect::<Result<Vec<_>, _>>()?;
+    let mut active = 0;
+    let markdown_content = read_markdown(&tabs[active].file_path)?;
+    let sections = render_sections(&tabs[active], &markdown_content, &settings);
+    // Hashes of the sections on the page, so a reload only sends the ones that changed
+    let mut shown_sections: Vec<u64> = sections.iter().map(|section| section_hash(section)).collect();
+    let html_body: String = sections.iter().map(|section| format!(r#"<div class="doc-section">{}</div>"#, section)).collect();
+    let toc_entries = toc::extract_toc(&markdown_content, &settings);
+    let saved = load_window_state();
+    let mut focus_mode = settings.focus_mode || saved.is_some_and(|state| state.focus_mode);
+    let page = Page {
+        body: &html_body,
+        toc_entries: &toc_entries,
+        stats: stats::document_stats(&markdown_content),
+        meta: &meta_text(&tabs[active], &settings),
+        tab_strip: &build_tab_strip_html(&tabs, active),
+        recent: &build_recent_html(&recent_files()),
+        start_line,
+        focus: focus_mode,
+    };
+    let full_html = build_html(&page, &settings);
+
+    let event_loop = EventLoopBuilder::<TabEvent>::with_user_event().build();
+    let mut builder = WindowBuilder::new()
+        .with_title(window_title(&tabs[active]))
+        .with_min_inner_size(LogicalSize::new(MIN_SIZE.0, MIN_SIZE.1));
+    let icon = crate::core::icon::load_icon_rgba()
+        .and_then(|(rgba, width, height)| Ok(tao::window::Icon::from_rgba(rgba, width, height)?));
+    match icon {
+        Ok(icon) => builder = builder.with_window_icon(Some(icon)),
+        Err(e) => vlog!("webview: window icon not loaded: {}", e),
+    }
+    let mut always_on_top = window_options.always_on_top || saved.is_some_and(|state| state.always_on_top);
+    // Flags given for this run win over, and don't replace, the remembered geometry
+    let keep_geometry = window_options.is_explicit();
+    builder = match saved.filter(|_| !keep_geometry) {
+        Some(state) => builder
+            .with_inner_size(LogicalSize::new(state.width, state.height))
+            .with_position(LogicalPosition::new(state.x, state.y))
+            .with_maximized(state.maximized),
+        None => {
+            let (width, height) = window_options.size.unwrap_or(DEFAULT_SIZE);
+            builder
+                .with_inner_size(LogicalSize::new(width, height))
+                .with_maximized(window_options.maximized)
+        }
+    };
+    builder = builder.with_always_on_top(always_on_top);
+    if window_options.fullscreen {
+        builder = builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = builder.build(&event_loop)?;
+
+    let proxy = event_loop.create_proxy();
+    let webview = WebViewBuilder::new()
+        .with_html(&full_html)
+        .with_ipc_handler(move |request| {
+            if let Some(event) = parse_tab_message(request.body()) {
+                let _ = proxy.send_event(event);
+            }
+        })
+        .build(&window)?;
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        // Check for file changes; other tabs are rendered afresh when switched to.
+        // A removed file keeps its last content, under a banner, until it is back
+        for (i, tab) in tabs.iter_mut().enumerate() {
+            let Some(file_event) = tab.watcher.poll() else {
+                continue;
+            };
+            if i == active {
+                if file_event == FileEvent::Changed {
+                    reload(&webview, tab, &settings, &mut shown_sections);
+                }
+                let _ = webview.evaluate_script(&removed_banner_script(tab));
+            }
+        }
+        // --watch-dir: bring up whichever file changed last, opening a tab for it if needed
+        let watched_tab = dir_watch.as_ref().and_then(DirWatchGuard::changed_file).and_then(|path| tab_for(&mut tabs, path, &settings));
+
+        let switch_to = match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                remember_window_state(&window, keep_geometry, always_on_top, focus_mode);
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            Event::UserEvent(TabEvent::ToggleAlwaysOnTop) => {
+                always_on_top = !always_on_top;
+                window.set_always_on_top(always_on_top);
+                return;
+            }
+            // The page dims itself; only remember the setting
+            Event::UserEvent(TabEvent::ToggleFocusMode) => {
+                focus_mode = !focus_mode;
+                return;
+            }
+            Event::UserEvent(TabEvent::Reload) => {
+                reload(&webview, &tabs[active], &settings, &mut shown_sections);
+                return;
+            }
+            Event::UserEvent(TabEvent::Edit(line)) => {
+                if let Err(e) = crate::core::editor::spawn(settings.editor.as_deref(), &tabs[active].file_path, line) {
+                    let message = serde_json::to_string(&format!("Could not open the editor: {}", e)).unwrap_or_default();
+                    let _ = webview.evaluate_script(&format!("alert({});", message));
+                }
+                return;
+            }
+            Event::UserEvent(TabEvent::Open) | Event::UserEvent(TabEvent::OpenRecent(_)) => {
+                let file = match event {
+                    Event::UserEvent(TabEvent::OpenRecent(i)) => recent_files().into_iter().nth(i),
+                    // A cancelled dialog just leaves the current file up
+                    _ => crate::core::recent::pick_markdown_file(),
+                };
+                let Some(file) = file.map(|file| file.canonicalize().unwrap_or(file)) else {
+                    return;
+                };
+                add_recent_file(&file);
+                match tab_for(&mut tabs, file, &settings) {
+                    Some(i) => i,
+                    None => return,
+                }
+            }
+            Event::UserEvent(TabEvent::Switch(i)) if i < tabs.len() && i != active => i,
+            Event::UserEvent(TabEvent::Close(i)) if i < tabs.len() => {
+                // Dropping the tab stops its watcher
+                tabs.remove(i);
+                if tabs.is_empty() {
+                    remember_window_state(&window, keep_geometry, always_on_top, focus_mode);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                if i < active || active == tabs.len() {
+                    active -= 1;
+                }
+                active
+            }
+            _ => match watched_tab {
+                Some(i) if i != active => i,
+                _ => return,
+            },
+        };
+        active = switch_to;
+        window.set_title(&window_title(&tabs[active]));
+        let tab_strip = serde_json::to_string(&build_tab_strip_html(&tabs, active)).unwrap_or_default();
+        shown_sections.clear();
+        let update = document_update_script(&tabs[active], &settings, &mut shown_sections).unwrap_or_default();
+        let recent = serde_json::to_string(&build_recent_html(&recent_files())).unwrap_or_default();
+        let js = format!(
+            "{} setTabStrip({}); {} document.getElementById('recentFiles').innerHTML = {}; refreshSearch(); restoreTabScroll(); updateActiveHeading(); updateReadingProgress();",
+            update, tab_strip, removed_banner_script(&tabs[active]), recent
+        );
+        let _ = webview.evaluate_script(&js);
+    });
+}
+
+/// Save the window's position, size, always-on-top and focus mode settings for the
+/// next session.
+/// With `keep_geometry` (the window was opened with explicit geometry flags) the
+/// remembered position and size are left alone, and a maximized window keeps the
+/// size it had before, so it still unmaximizes to something sensible.
+fn remember_window_state(window: &Window, keep_geometry: bool, always_on_top: bool, focus_mode: bool) {
+    let maximized = window.is_maximized();
+    let state = match load_window_state() {
+        Some(previous) if keep_geometry => WindowState { always_on_top, focus_mode, ..previous },
+        Some(previous) if maximized => WindowState { maximized, always_on_top, focus_mode, ..previous },
+        _ => {
+            let Ok(position) = window.outer_position() else {
+                return;
+            };
+            let position: LogicalPosition<f64> = position.to_logical(window.scale_factor());
+            let size: LogicalSize<f64> = window.inner_size().to_logical(window.scale_factor());
+            WindowState {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+                maximized,
+                always_on_top,
+                focus_mode,
+            }
+        }
+    };
+    if let Err(e) = save_window_state(state) {
+        vlog!("webview: could not save the window state: {}", e);
+    }
+}
+
+/// Script showing the removed-file banner for `tab`, or hiding it.
+fn removed_banner_script(tab: &Tab) -> String {
+    let name = tab.watcher.is_removed().then(|| tab.title());
+    format!("setFileRemoved({});", serde_json::to_string(&name).unwrap_or_default())
+}
+
+/// Index of the tab showing `path` (canonical), opening one for it at the end if needed.
+fn tab_for(tabs: &mut Vec<Tab>, path: PathBuf, settings: &RenderSettings) -> Option<usize> {
+    let open = tabs.iter().position(|tab| tab.file_path.canonicalize().ok().as_ref() == Some(&path));
+    open.or_else(|| {
+        tabs.push(Tab::open(path, settings).ok()?);
+        Some(tabs.len() - 1)
+    })
+}
+
+fn window_title(tab: &Tab) -> String {
+    format!("mdr - {}", tab.file_path.display())
+}
+
+/// Requests posted by the page through `window.ipc`.
+#[derive(Debug, PartialEq)]
+enum TabEvent {
+    Switch(usize),
+    Close(usize),
+    /// F5 / Ctrl+R: re-read the active tab's file.
+    Reload,
+    /// Ctrl+Shift+T: keep the window above others, or stop doing so.
+    ToggleAlwaysOnTop,
+    /// Ctrl+D: focus mode was turned on or off in the page.
+    ToggleFocusMode,
+    /// Edit button / Ctrl+Shift+E: open the active tab's file in the editor at this source line.
+    Edit(usize),
+    /// Open button / Ctrl+O: pick a file to open in the file dialog.
+    Open,
+    /// Open the file at this position in the recent files.
+    OpenRecent(usize),
+}
+
+/// Parse a `tab:N` or `close:N` message from the tab strip, `reload`, `always-on-top`,
+/// `focus-mode`, `edit:LINE`, `open` or `recent:N`.
+fn parse_tab_message(message: &str) -> Option<TabEvent> {
+    match message {
+        "reload" => return Some(TabEvent::Reload),
+        "open" => return Some(TabEvent::Open),
+        "always-on-top" => return Some(TabEvent::ToggleAlwaysOnTop),
+        "focus-mode" => return Some(TabEvent::ToggleFocusMode),
+        _ => {}
+    }
+    let (kind, index) = message.split_once(':')?;
+    let index = index.parse().ok()?;
+    match kind {
+        "tab" => Some(TabEvent::Switch(index)),
+        "close" => Some(TabEvent::Close(index)),
+        "edit" => Some(TabEvent::Edit(index)),
+        "recent" => Some(TabEvent::OpenRecent(index)),
+        _ => None,
+    }
+}
+
+/// Syntect theme of the `--raw` view for `--theme`, or `None` to leave it plain:
+/// with `--no-color`, the monochrome themes, or `auto` under `NO_COLOR`.
+fn raw_syntax_theme(settings: &RenderSettings) -> Option<&'static str> {
+    match settings.tui_theme() {
+        Theme::Monochrome | Theme::HighContrast => None,
+        Theme::Auto if crate::core::no_color_env() => None,
+        Theme::Light => Some("InspiredGitHub"),
+        Theme::Solarized => Some("Solarized (dark)"),
+        _ => Some("base16-ocean.dark"),
+    }
+}
+
+/// Render a tab's markdown to the HTML sections that go inside `.content`.
+/// The `--raw` view shows the source itself, highlighted, as one section.
+fn render_sections(tab: &Tab, markdown_content: &str, settings: &RenderSettings) -> Vec<String> {
+    vlog!("webview: file_path={}", tab.file_path.display());
+    vlog!("webview: base_dir={}", tab.base_dir.display());
     vlog!("webview: markdown_content length={} bytes", markdown_content.len());
-    let html_body = parse_markdown(&markdown_content);
+    tab.watcher.watch_dependencies(&diagram_files(markdown_content, &tab.base_dir));
+    if settings.raw {
+        return vec![highlight_markdown_html(markdown_content, raw_syntax_theme(settings))];
+    }
+    let SourceposHtml { html: html_body, heading_lines } = render_body(markdown_content, settings, &tab.base_dir);
     vlog!("webview: html_body length={} bytes", html_body.len());
     // In verbose mode, dump all <img> tags found in the HTML
     if crate::core::verbose() {
@@ -42,61 +322,179 @@ pub fn run(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
-    let html_body = resolve_local_images(&html_body, &base_dir);
-    let toc_entries = toc::extract_toc(&markdown_content);
-    let full_html = build_html(&html_body, &toc_entries);
+    let html_body = add_heading_anchors(&resolve_local_images(&html_body, &tab.base_dir), &tab.file_path);
+    split_sections(&html_body, &heading_lines)
+}
 
-    let watcher_rx = crate::core::watcher::watch_file(&file_path)?;
+/// Put a copy-link anchor at the start of every heading that has an id.
+/// `data-link` holds a shareable `path#id` link for the clipboard.
+fn add_heading_anchors(html: &str, file_path: &std::path::Path) -> String {
+    use std::sync::OnceLock;
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r#"<h[1-6][^>]* id="([^"]+)">"#).unwrap());
+    let path = escape_attr(&file_path.to_string_lossy());
+    re.replace_all(html, |caps: &regex::Captures| {
+        format!(
+            r##"{}<a class="heading-anchor" href="#{id}" data-link="{}#{id}" title="Copy link to this section" aria-label="Copy link to this section"></a>"##,
+            &caps[0],
+            path,
+            id = &caps[1],
+        )
+    })
+    .to_string()
+}
 
-    let (icon_rgba, icon_w, icon_h) = crate::core::icon::load_icon_rgba();
+/// JS that brings the page's content, TOC and stats up to date with the tab's file as
+/// it is on disk. `shown` holds the hashes of the sections on the page; only the
+/// sections that differ from them are sent, so unchanged diagrams and images aren't
+/// decoded again. An empty `shown` replaces the whole content.
+fn document_update_script(tab: &Tab, settings: &RenderSettings, shown: &mut Vec<u64>) -> Option<String> {
+    let content = read_markdown(&tab.file_path).ok()?;
+    let sections = render_sections(tab, &content, settings);
+    let toc_html = build_toc_html(&toc::extract_toc(&content, settings), settings.toc_depth);
+    let stats_text = stats::document_stats(&content).to_string();
+
+    let toc_json = serde_json::to_string(&toc_html).unwrap_or_default();
+    let stats_json = serde_json::to_string(&stats_text).unwrap_or_default();
+    let meta_json = serde_json::to_string(&meta_text(tab, settings)).unwrap_or_default();
+    Some(format!(
+        "{} document.querySelector('.sidebar ul').innerHTML = {}; document.getElementById('docStats').textContent = {}; document.getElementById('docMeta').textContent = {};",
+        sections_update_script(&sections, shown), toc_json, stats_json, meta_json
+    ))
+}
 
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title(format!("mdr - {}", file_path.display()))
-        .with_inner_size(tao::dpi::LogicalSize::new(1100.0, 900.0))
-        .with_window_icon(Some(tao::window::Icon::from_rgba(icon_rgba, icon_w, icon_h).unwrap()))
-        .build(&event_loop)?;
+/// Split a rendered document before each top-level heading, much as the egui backend
+/// splits its sections. Headings are found by the source line in their
+/// `data-sourcepos`, matched against the `heading_lines` of the render, so those
+/// nested in lists or quotes don't split their container.
+fn split_sections(html: &str, heading_lines: &[usize]) -> Vec<String> {
+    use std::sync::OnceLock;
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r#"<h[1-6] data-sourcepos="(\d+):"#).unwrap());
+
+    let mut sections = Vec::new();
+    let mut start = 0;
+    for caps in re.captures_iter(html) {
+        let at = caps.get(0).map_or(0, |m| m.start());
+        let top_level = caps[1].parse().is_ok_and(|line: usize| heading_lines.contains(&line));
+        if top_level && at > start {
+            sections.push(html[start..at].to_string());
+            start = at;
+        }
+    }
+    sections.push(html[start..].to_string());
+    sections
+}
 
-    let webview = WebViewBuilder::new()
-        .with_html(&full_html)
-        .build(&window)?;
+fn section_hash(html: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    html.hash(&mut hasher);
+    hasher.finish()
+}
 
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+/// JS that swaps the page's sections for `sections`, keeping the runs at the start
+/// and end that are unchanged since `shown` and sending only the ones in between.
+/// `shown` is updated to match.
+fn sections_update_script(sections: &[String], shown: &mut Vec<u64>) -> String {
+    let hashes: Vec<u64> = sections.iter().map(|section| section_hash(section)).collect();
+    let keep_start = hashes.iter().zip(shown.iter()).take_while(|(new, old)| new == old).count();
+    let keep_end = hashes[keep_start..]
+        .iter()
+        .rev()
+        .zip(shown[keep_start.min(shown.len())..].iter().rev())
+        .take_while(|(new, old)| new == old)
+        .count();
+    let changed = &sections[keep_start..sections.len() - keep_end];
+    vlog!(
+        "webview: updating {} of {} sections ({} bytes)",
+        changed.len(),
+        sections.len(),
+        changed.iter().map(String::len).sum::<usize>()
+    );
+    *shown = hashes;
+    let changed_json = serde_json::to_string(changed).unwrap_or_default();
+    format!("replaceSections({}, {}, {});", keep_start, keep_end, changed_json)
+}
 
-        // Check for file changes
-        if watcher_rx.try_recv().is_ok() {
-            while watcher_rx.try_recv().is_ok() {}
-            if let Ok(content) = std::fs::read_to_string(&file_path) {
-                let new_html = parse_markdown(&content);
-                let new_html = resolve_local_images(&new_html, &base_dir);
-                let new_toc = toc::extract_toc(&content);
-                let toc_html = build_toc_html(&new_toc);
-
-                let body_json = serde_json::to_string(&new_html).unwrap_or_default();
-                let toc_json = serde_json::to_string(&toc_html).unwrap_or_default();
-                let js = format!(
-                    "document.querySelector('.content').innerHTML = {}; document.querySelector('.sidebar ul').innerHTML = {};",
-                    body_json, toc_json
-                );
-                let _ = webview.evaluate_script(&js);
-            }
-        }
+/// The tab's file age and last commit for the sidebar footer, with `--show-meta`.
+fn meta_text(tab: &Tab, settings: &RenderSettings) -> String {
+    if settings.show_meta {
+        DocMeta::gather(&tab.file_path).to_string()
+    } else {
+        String::new()
+    }
+}
 
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => *control_flow = ControlFlow::Exit,
-            _ => {}
-        }
-    });
+/// Re-render `tab` from disk into the page, as after a change on disk.
+/// In follow mode the view stays at the end if it was there.
+fn reload(webview: &WebView, tab: &Tab, settings: &RenderSettings, shown_sections: &mut Vec<u64>) {
+    let started = Instant::now();
+    if let Some(update) = document_update_script(tab, settings, shown_sections) {
+        let _ = webview.evaluate_script(&reload_script(&update));
+    }
+    vlog!("webview: reloaded {} in {:?}", tab.file_path.display(), started.elapsed());
+}
+
+/// JS that applies `update` and redoes what depends on the content: the open
+/// search is run again, and follow mode stays at the end.
+fn reload_script(update: &str) -> String {
+    format!(
+        "var atBottom = isAtBottom(); {} refreshSearch(); if (followMode && atBottom) scrollToBottom(); updateActiveHeading(); updateReadingProgress();",
+        update
+    )
+}
+
+/// Tab strip buttons for every open file; empty when only one is open.
+fn build_tab_strip_html(tabs: &[Tab], active: usize) -> String {
+    if tabs.len() < 2 {
+        return String::new();
+    }
+    let mut html = String::new();
+    for (i, tab) in tabs.iter().enumerate() {
+        html.push_str(&format!(
+            "<span class=\"tab{}\" data-tab=\"{}\" role=\"tab\" aria-selected=\"{}\" title=\"{}\">{}<button class=\"tab-close\" data-close=\"{}\" title=\"Close (Ctrl+W)\" aria-label=\"Close {}\">&#x2715;</button></span>",
+            if i == active { " active" } else { "" },
+            i,
+            i == active,
+            escape_attr(&tab.file_path.display().to_string()),
+            escape_attr(&tab.title()),
+            i,
+            escape_attr(&tab.title())
+        ));
+    }
+    html
+}
+
+/// The sidebar's list of recent files, which post `recent:N` when clicked.
+fn build_recent_html(files: &[PathBuf]) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let items: String = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let name = file.file_name().map_or_else(|| file.display().to_string(), |name| name.to_string_lossy().into_owned());
+            format!(
+                r#"<li><button data-recent="{}" title="{}">{}</button></li>"#,
+                i,
+                escape_attr(&file.to_string_lossy()),
+                escape_attr(&name)
+            )
+        })
+        .collect();
+    format!(r#"<p class="sidebar-title">Recent files</p><ul class="recent-files">{}</ul>"#, items)
+}
+
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
 }
 
 /// Resolve local image paths to inline base64 data URIs.
 /// wry's `with_html()` does not allow loading file:// URLs, so we must embed images directly.
 /// SVG files are rasterized to PNG first (to avoid executing embedded scripts/links).
-/// Handles both `<img src="...">` and `<img alt="..." src="...">` attribute orders.
+/// Handles both `<img src="...">` and `<img alt="..." src="...">` attribute orders;
+/// only `src` is rewritten, so `alt` and the `title` tooltip are kept.
 fn resolve_local_images(html: &str, base_dir: &std::path::Path) -> String {
     use std::sync::OnceLock;
     vlog!("resolve_local_images: base_dir={}", base_dir.display());
@@ -117,7 +515,7 @@ fn resolve_local_images(html: &str, base_dir: &std::path::Path) -> String {
             return full_tag.to_string();
         }
         // URL-decode the src path (comrak may percent-encode spaces etc.)
-        let decoded_src = percent_decode(src);
+        let decoded_src = crate::core::markdown::percent_decode(src);
         // Resolve relative path
         let abs_path = base_dir.join(&decoded_src);
         vlog!("    abs_path={}", abs_path.display());
@@ -130,6 +528,21 @@ fn resolve_local_images(html: &str, base_dir: &std::path::Path) -> String {
             }
         }
         if abs_path.exists() {
+            // Mermaid diagram files are drawn as the diagram; an SVG in an <img> runs no scripts
+            if is_diagram_file(&abs_path) {
+                match render_diagram_file(&abs_path) {
+                    Ok(svg) => {
+                        use base64::Engine;
+                        let data_uri = format!("data:image/svg+xml;base64,{}", base64::engine::general_purpose::STANDARD.encode(svg));
+                        vlog!("    → mermaid diagram rendered ({} bytes)", data_uri.len());
+                        return re_src.replace(full_tag, format!("src=\"{}\"", data_uri).as_str()).to_string();
+                    }
+                    Err(e) => {
+                        vlog!("    → mermaid diagram FAILED: {}", e);
+                        return full_tag.to_string();
+                    }
+                }
+            }
             let is_svg = abs_path.extension()
                 .and_then(|e| e.to_str())
                 .map(|e| e.eq_ignore_ascii_case("svg"))
@@ -176,28 +589,6 @@ fn resolve_local_images(html: &str, base_dir: &std::path::Path) -> String {
     .to_string()
 }
 
-/// Decode percent-encoded URL path components (e.g. %20 -> space).
-fn percent_decode(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    let mut chars = s.chars();
-    while let Some(c) = chars.next() {
-        if c == '%' {
-            let hex: String = chars.by_ref().take(2).collect();
-            if hex.len() == 2 {
-                if let Ok(byte) = u8::from_str_radix(&hex, 16) {
-                    result.push(byte as char);
-                    continue;
-                }
-            }
-            result.push('%');
-            result.push_str(&hex);
-        } else {
-            result.push(c);
-        }
-    }
-    result
-}
-
 /// Convert a local file to a base64 data URI string.
 fn file_to_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
     use base64::Engine;
@@ -217,12 +608,13 @@ fn file_to_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error
     Ok(format!("data:{};base64,{}", mime, b64))
 }
 
-fn build_toc_html(entries: &[toc::TocEntry]) -> String {
+/// Sidebar links for the headings down to `depth`.
+fn build_toc_html(entries: &[toc::TocEntry], depth: usize) -> String {
     let mut toc = String::new();
-    for entry in entries {
+    for entry in entries.iter().filter(|entry| entry.level as usize <= depth) {
         toc.push_str(&format!(
-            "<li class=\"toc-h{}\"><a href=\"#{}\">{}</a></li>",
-            entry.level, entry.anchor, entry.text
+            "<li class=\"toc-h{}\"><a href=\"#{}\" data-line=\"{}\">{}</a></li>",
+            entry.level, entry.anchor, entry.line, entry.text
         ));
     }
     toc
@@ -231,14 +623,130 @@ fn build_toc_html(entries: &[toc::TocEntry]) -> String {
 /// Mermaid.js embedded at compile time — only injected when the Rust renderer fails.
 const MERMAID_JS: &str = include_str!("../../assets/mermaid.min.js");
 
+/// `--sortable-tables`: clicking a header (or Enter on it) sorts the table by that
+/// column, ascending, then descending, then back to the document order. Columns
+/// whose cells are all numbers sort numerically. Handlers are delegated, so tables
+/// swapped in by a reload work once `prepareSortableTables` has marked them.
+const SORTABLE_TABLES_JS: &str = r#"
+(function() {
+    function cellText(row, column) {
+        var cell = row.cells[column];
+        return cell ? cell.textContent.trim() : '';
+    }
+    function asNumber(text) {
+        var n = parseFloat(text.replace(/[,\s%$€£]/g, ''));
+        return /\d/.test(text) && isFinite(n) ? n : null;
+    }
+    window.prepareSortableTables = function() {
+        document.querySelectorAll('.content table').forEach(function(table) {
+            if (table.dataset.sortable || !table.tHead || !table.tBodies[0]) return;
+            table.dataset.sortable = 'true';
+            Array.prototype.forEach.call(table.tBodies[0].rows, function(row, i) { row.dataset.order = i; });
+            Array.prototype.forEach.call(table.tHead.rows[0].cells, function(th) {
+                th.tabIndex = 0;
+                th.setAttribute('aria-sort', 'none');
+            });
+        });
+    };
+    function sortBy(th) {
+        var next = { none: 'ascending', ascending: 'descending' }[th.getAttribute('aria-sort')] || 'none';
+        Array.prototype.forEach.call(th.parentNode.cells, function(cell) { cell.setAttribute('aria-sort', 'none'); });
+        th.setAttribute('aria-sort', next);
+        var body = th.closest('table').tBodies[0];
+        var column = th.cellIndex;
+        var rows = Array.prototype.slice.call(body.rows);
+        var numeric = rows.every(function(row) {
+            var text = cellText(row, column);
+            return text === '' || asNumber(text) !== null;
+        });
+        rows.sort(function(a, b) {
+            var byOrder = a.dataset.order - b.dataset.order;
+            if (next === 'none') return byOrder;
+            var x = cellText(a, column), y = cellText(b, column);
+            var order = numeric
+                ? (asNumber(x) || 0) - (asNumber(y) || 0)
+                : x.localeCompare(y, undefined, { numeric: true, sensitivity: 'base' });
+            return (next === 'ascending' ? order : -order) || byOrder;
+        });
+        rows.forEach(function(row) { body.appendChild(row); });
+    }
+    function sortableHeader(e) {
+        return e.target.closest && !e.target.closest('a') && e.target.closest('.content table[data-sortable] thead th');
+    }
+    document.addEventListener('click', function(e) {
+        var th = sortableHeader(e);
+        if (th) sortBy(th);
+    });
+    document.addEventListener('keydown', function(e) {
+        var th = (e.key === 'Enter' || e.key === ' ') && sortableHeader(e);
+        if (!th) return;
+        e.preventDefault();
+        sortBy(th);
+    });
+    prepareSortableTables();
+})();
+"#;
+
+/// The DOM-free part of the search bar: the query as a regex, the matches in each
+/// block's text and the pieces of text nodes they cover, and how a node's text
+/// splits around its pieces. The search script draws the marks from these.
+const SEARCH_MATCHES_JS: &str = r#"
+// The query as a global regex, honoring "Aa" and ".*"; null if it doesn't compile
+function searchPattern(query, useRegex, matchCase) {
+    var source = useRegex ? query : query.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
+    try {
+        return new RegExp(source, matchCase ? 'g' : 'gi');
+    } catch (e) {
+        return null;
+    }
+}
+
+// Every match of `pattern` in each block's text, numbered across blocks, as the
+// pieces of the text nodes it covers: a map from node to `{ from, to, match }`
+// in node order. A match can span nodes but never blocks; empty matches are skipped.
+function matchPieces(blocks, pattern) {
+    var pieces = new Map();
+    var count = 0;
+    blocks.forEach(function(block) {
+        var found;
+        pattern.lastIndex = 0;
+        while ((found = pattern.exec(block.text)) !== null) {
+            var start = found.index;
+            var end = start + found[0].length;
+            if (end === start) { pattern.lastIndex++; continue; }
+            block.nodes.forEach(function(entry) {
+                var from = Math.max(start, entry.start) - entry.start;
+                var to = Math.min(end, entry.start + entry.node.textContent.length) - entry.start;
+                if (from >= to) return;
+                if (!pieces.has(entry.node)) pieces.set(entry.node, []);
+                pieces.get(entry.node).push({ from: from, to: to, match: count });
+            });
+            count++;
+        }
+    });
+    return { pieces: pieces, count: count };
+}
+
+// `text` cut at its pieces: `{ text, match }` parts, with a null `match` for
+// the plain text between them
+function splitPieces(text, list) {
+    var parts = [];
+    var last = 0;
+    list.forEach(function(piece) {
+        if (piece.from > last) parts.push({ text: text.slice(last, piece.from), match: null });
+        parts.push({ text: text.slice(piece.from, piece.to), match: piece.match });
+        last = piece.to;
+    });
+    if (last < text.length) parts.push({ text: text.slice(last), match: null });
+    return parts;
+}
+"#;
+
 /// Rasterize an SVG file to PNG and return as a base64 data URI.
 /// This is safer than inlining SVG because SVG can contain scripts, links, and styles
 /// that would execute in the page context and cause unwanted navigation/requests.
 /// Returns Err if the file is not a valid SVG (e.g., an HTML page saved with .svg extension).
 fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
-    use base64::Engine;
-    use std::sync::{Arc, OnceLock};
-
     let svg_data = std::fs::read_to_string(path)?;
 
     // Reject files that aren't actually SVG (e.g. HTML pages saved with .svg extension)
@@ -249,6 +757,15 @@ fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<d
         }
     }
 
+    // Cached by content so reloads don't re-rasterize unchanged SVGs
+    static CACHE: SvgCache<String> = SvgCache::new();
+    CACHE.get_or_insert_with(&svg_data, 2.0, || render_svg_to_png_data_uri(&svg_data))
+}
+
+fn render_svg_to_png_data_uri(svg_data: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use base64::Engine;
+    use std::sync::{Arc, OnceLock};
+
     // Max pixel dimension to avoid memory issues
     const MAX_DIM: f32 = 8192.0;
 
@@ -262,7 +779,7 @@ fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<d
 
     let mut options = usvg::Options::default();
     options.fontdb = Arc::clone(fontdb);
-    let tree = usvg::Tree::from_str(&svg_data, &options)?;
+    let tree = usvg::Tree::from_str(svg_data, &options)?;
     let size = tree.size();
     let svg_w = size.width();
     let svg_h = size.height();
@@ -294,8 +811,43 @@ fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<d
     Ok(format!("data:image/png;base64,{}", b64))
 }
 
-fn build_html(body: &str, toc_entries: &[toc::TocEntry]) -> String {
-    let toc_html = build_toc_html(toc_entries);
+/// Render markdown for the webview, with source positions and per-line code styling.
+fn render_body(content: &str, settings: &RenderSettings, base_dir: &std::path::Path) -> SourceposHtml {
+    let rendered = parse_markdown_with_sourcepos(content, settings, base_dir);
+    SourceposHtml { html: wrap_code_lines(&rendered.html, settings.code_line_numbers), ..rendered }
+}
+
+/// What the first page shows of the active tab and the window around it.
+#[derive(Default)]
+struct Page<'a> {
+    body: &'a str,
+    toc_entries: &'a [toc::TocEntry],
+    stats: DocStats,
+    meta: &'a str,
+    tab_strip: &'a str,
+    recent: &'a str,
+    /// Source line to scroll to once loaded.
+    start_line: Option<usize>,
+    focus: bool,
+}
+
+fn build_html(page: &Page, settings: &RenderSettings) -> String {
+    let Page { body, toc_entries, stats, meta, tab_strip, recent, start_line, focus } = *page;
+    let follow = settings.follow;
+    let toc_html = build_toc_html(toc_entries, settings.toc_depth);
+    let start_script = match start_line {
+        Some(line) => format!("<script>scrollToSourceLine({});</script>", line),
+        None if follow => "<script>scrollToBottom();</script>".to_string(),
+        None => String::new(),
+    };
+    let mut body_classes = Vec::new();
+    if !tab_strip.is_empty() {
+        body_classes.push("has-tabs");
+    }
+    if settings.theme == Theme::HighContrast {
+        body_classes.push("high-contrast");
+    }
+    let body_class = if body_classes.is_empty() { String::new() } else { format!(" class=\"{}\"", body_classes.join(" ")) };
     // Only include mermaid.js if there are fallback blocks that need JS rendering
     let mermaid_script = if body.contains(r#"class="mermaid""#) {
         format!(
@@ -307,6 +859,18 @@ fn build_html(body: &str, toc_entries: &[toc::TocEntry]) -> String {
         String::new()
     };
 
+    let sortable_script = if settings.sortable_tables {
+        format!("<script>{}</script>", SORTABLE_TABLES_JS)
+    } else {
+        String::new()
+    };
+
+    let progress_bar = if settings.progress_bar {
+        r#"<div class="reading-progress" id="readingProgress" aria-hidden="true"></div>"#
+    } else {
+        ""
+    };
+
     format!(
         r#"<!DOCTYPE html>
 <html>
@@ -315,115 +879,480 @@ fn build_html(body: &str, toc_entries: &[toc::TocEntry]) -> String {
 <meta http-equiv="Content-Security-Policy" content="default-src 'none'; style-src 'unsafe-inline'; script-src 'unsafe-inline'; img-src data:;">
 <style>{css}</style>
 </head>
-<body>
-<nav class="sidebar">
+<body{body_class}>
+<div class="tab-strip" id="tabStrip" role="tablist" aria-label="Open files">{tab_strip}</div>
+{progress_bar}
+<nav class="sidebar" aria-label="Table of contents">
 <p class="sidebar-title">Table of Contents</p>
 <ul>{toc}</ul>
+<p class="sidebar-footer" id="docStats">{stats}</p>
+<p class="sidebar-meta" id="docMeta">{meta}</p>
+<div class="sidebar-recent" id="recentFiles">{recent}</div>
 </nav>
-<div class="content">
+<main class="content">
 {body}
+</main>
+<div class="copy-toolbar" role="toolbar" aria-label="Document">
+    <button onclick="copyDocument(true)" title="Copy as HTML (Ctrl+Shift+C)">Copy HTML</button>
+    <button onclick="copyDocument(false)" title="Copy as plain text (Ctrl+Alt+C)">Copy text</button>
+    <button onclick="editDocument()" title="Open in your editor (Ctrl+Shift+E)">Edit</button>
+    <button onclick="window.ipc.postMessage('open')" title="Open another file (Ctrl+O)">Open</button>
 </div>
+<div class="toast" id="toast" role="status" aria-live="polite"></div>
+<div class="file-removed" id="fileRemoved" role="alert" hidden></div>
+<div class="lightbox" id="lightbox" role="dialog" aria-modal="true" aria-label="Image viewer" hidden></div>
 <script>
+// Jumps to headings and search matches scroll smoothly, unless --scroll-behavior
+// instant says not to or the system asks for reduced motion
+var SMOOTH_SCROLL = {smooth_scroll};
+function scrollBehavior() {{
+    if (!SMOOTH_SCROLL || window.matchMedia('(prefers-reduced-motion: reduce)').matches) return 'instant';
+    return 'smooth';
+}}
 document.querySelector('.sidebar').addEventListener('click', function(e) {{
     if (e.target.tagName === 'A') {{
         e.preventDefault();
         var id = e.target.getAttribute('href').substring(1);
-        var el = document.getElementById(id);
+        var el = id && document.getElementById(id);
         if (el) {{
-            el.scrollIntoView({{ behavior: 'smooth', block: 'start' }});
-            document.querySelectorAll('.sidebar a').forEach(a => a.classList.remove('active'));
-            e.target.classList.add('active');
+            recordJump();
+            el.scrollIntoView({{ behavior: scrollBehavior(), block: 'start' }});
+            document.querySelectorAll('.sidebar a').forEach(function(a) {{ setActiveLink(a, a === e.target); }});
+        }} else if (e.target.hasAttribute('data-line')) {{
+            // Bold lines listed by --toc-heuristic have no id; find them by source line
+            recordJump();
+            scrollToSourceLine(parseInt(e.target.getAttribute('data-line'), 10));
         }}
     }}
 }});
+
+// Mark the TOC link of the last listed heading at or above the top of the viewport,
+// and in focus mode the section of the last heading there, listed or not
+function updateActiveHeading() {{
+    var active = null, top = null;
+    var listed = new Set(Array.prototype.map.call(document.querySelectorAll('.sidebar a'), function(a) {{ return a.getAttribute('href'); }}));
+    document.querySelectorAll('.content h1[id], .content h2[id], .content h3[id], .content h4[id], .content h5[id], .content h6[id]').forEach(function(h) {{
+        if (h.getBoundingClientRect().top > 10) return;
+        top = h;
+        if (listed.has('#' + h.id)) active = h;
+    }});
+    document.querySelectorAll('.sidebar a').forEach(function(a) {{
+        setActiveLink(a, active !== null && a.getAttribute('href') === '#' + active.id);
+    }});
+    var focused = top ? top.closest('.doc-section') : document.querySelector('.content .doc-section');
+    document.querySelectorAll('.content .doc-section').forEach(function(section) {{
+        section.classList.toggle('focused', section === focused);
+    }});
+}}
+// The active TOC link is also announced to screen readers
+function setActiveLink(a, active) {{
+    a.classList.toggle('active', active);
+    if (active) a.setAttribute('aria-current', 'location');
+    else a.removeAttribute('aria-current');
+}}
+// Ctrl+D: focus mode dims every section but the one being read (remembered)
+var focusMode = {focus};
+document.body.classList.toggle('focus-mode', focusMode);
+document.addEventListener('keydown', function(e) {{
+    if (e.ctrlKey && !e.shiftKey && e.key === 'd') {{
+        e.preventDefault();
+        focusMode = !focusMode;
+        document.body.classList.toggle('focus-mode', focusMode);
+        window.ipc.postMessage('focus-mode');
+    }}
+}});
+// The reading progress bar fills with how far down the document the view is
+// (absent with --no-progress-bar)
+function updateReadingProgress() {{
+    var bar = document.getElementById('readingProgress');
+    if (!bar) return;
+    var root = document.documentElement;
+    var scrollable = root.scrollHeight - root.clientHeight;
+    var progress = scrollable > 0 ? Math.min(1, Math.max(0, window.scrollY / scrollable)) : 0;
+    bar.style.transform = 'scaleX(' + progress + ')';
+}}
+var activeHeadingPending = false;
+function scheduleScrollUpdate() {{
+    if (activeHeadingPending) return;
+    activeHeadingPending = true;
+    requestAnimationFrame(function() {{
+        activeHeadingPending = false;
+        updateActiveHeading();
+        updateReadingProgress();
+    }});
+}}
+window.addEventListener('scroll', scheduleScrollUpdate, {{ passive: true, capture: true }});
+window.addEventListener('resize', scheduleScrollUpdate);
+updateActiveHeading();
+updateReadingProgress();
+
+// Back/forward through anchor and tab jumps: Alt+Left / Alt+Right and the mouse buttons
+var navBack = [], navForward = [], pendingScroll = null;
+var NAV_LIMIT = 100;
+function currentPlace() {{
+    var tab = document.querySelector('#tabStrip .tab.active');
+    return {{ file: tab ? tab.title : '', y: Math.round(window.scrollY) }};
+}}
+function samePlace(a, b) {{
+    return a.file === b.file && a.y === b.y;
+}}
+function pushPlace(stack, place) {{
+    if (stack.length > 0 && samePlace(stack[stack.length - 1], place)) return;
+    if (stack.length === NAV_LIMIT) stack.shift();
+    stack.push(place);
+}}
+function recordJump() {{
+    navForward = [];
+    pushPlace(navBack, currentPlace());
+}}
+// Places in tabs that have since been closed are skipped
+function goToPlace(place) {{
+    if (place.file === currentPlace().file) {{
+        window.scrollTo(0, place.y);
+        return true;
+    }}
+    var tab = Array.prototype.find.call(document.querySelectorAll('#tabStrip .tab'), function(t) {{ return t.title === place.file; }});
+    if (!tab) return false;
+    pendingScroll = place.y;
+    window.ipc.postMessage('tab:' + tab.getAttribute('data-tab'));
+    return true;
+}}
+function navigate(back) {{
+    var from = back ? navBack : navForward;
+    var to = back ? navForward : navBack;
+    var current = currentPlace();
+    while (from.length > 0) {{
+        var place = from.pop();
+        if (samePlace(place, current) || !goToPlace(place)) continue;
+        pushPlace(to, current);
+        return;
+    }}
+}}
+// Called by the app once it has swapped in another tab
+function restoreTabScroll() {{
+    window.scrollTo(0, pendingScroll || 0);
+    pendingScroll = null;
+}}
+document.querySelector('.content').addEventListener('click', function(e) {{
+    if (e.target.closest("a[href^='#']:not(.heading-anchor)")) recordJump();
+}});
+document.addEventListener('keydown', function(e) {{
+    if (!e.altKey || (e.key !== 'ArrowLeft' && e.key !== 'ArrowRight')) return;
+    e.preventDefault();
+    navigate(e.key === 'ArrowLeft');
+}});
+document.addEventListener('mouseup', function(e) {{
+    if (e.button !== 3 && e.button !== 4) return;
+    e.preventDefault();
+    navigate(e.button === 3);
+}});
+
+// Reloads replace only the sections that changed: the first `keepStart` and last
+// `keepEnd` stay, with their decoded images and diagrams, and `sections` go between
+function replaceSections(keepStart, keepEnd, sections) {{
+    var content = document.querySelector('.content');
+    var old = content.children;
+    var end = old.length - keepEnd;
+    var before = old[end] || null;
+    for (var i = end - 1; i >= keepStart; i--) content.removeChild(old[i]);
+    sections.forEach(function(html) {{
+        var section = document.createElement('div');
+        section.className = 'doc-section';
+        section.innerHTML = html;
+        content.insertBefore(section, before);
+    }});
+    if (window.prepareSortableTables) prepareSortableTables();
+}}
+
+// Banner over the last content while the file is removed; null hides it
+function setFileRemoved(name) {{
+    var banner = document.getElementById('fileRemoved');
+    banner.textContent = name === null ? '' : name + ' was removed. Showing the last version until it is back.';
+    banner.hidden = name === null;
+}}
+
+// Tabs: clicks and Ctrl+Tab / Ctrl+Shift+Tab / Ctrl+W are handled by the app
+function setTabStrip(html) {{
+    document.getElementById('tabStrip').innerHTML = html;
+    document.body.classList.toggle('has-tabs', html !== '');
+}}
+document.getElementById('tabStrip').addEventListener('click', function(e) {{
+    var close = e.target.closest('[data-close]');
+    var tab = e.target.closest('[data-tab]');
+    if (close) window.ipc.postMessage('close:' + close.getAttribute('data-close'));
+    else if (tab && !tab.classList.contains('active')) {{
+        recordJump();
+        window.ipc.postMessage('tab:' + tab.getAttribute('data-tab'));
+    }}
+}});
+// F5 / Ctrl+R re-read the file, in case the watcher missed a change
+document.addEventListener('keydown', function(e) {{
+    if (e.key === 'F5' || (e.ctrlKey && !e.shiftKey && e.key === 'r')) {{
+        e.preventDefault();
+        window.ipc.postMessage('reload');
+    }}
+}});
+// Ctrl+O or a recent file in the sidebar opens another file in a tab
+document.addEventListener('keydown', function(e) {{
+    if (e.ctrlKey && !e.shiftKey && e.key === 'o') {{
+        e.preventDefault();
+        window.ipc.postMessage('open');
+    }}
+}});
+document.getElementById('recentFiles').addEventListener('click', function(e) {{
+    var file = e.target.closest('[data-recent]');
+    if (file) {{
+        recordJump();
+        window.ipc.postMessage('recent:' + file.getAttribute('data-recent'));
+    }}
+}});
+// Ctrl+Shift+T keeps the window above others, or stops doing so
+document.addEventListener('keydown', function(e) {{
+    if (e.ctrlKey && e.shiftKey && (e.key === 'T' || e.key === 't')) {{
+        e.preventDefault();
+        window.ipc.postMessage('always-on-top');
+    }}
+}});
+document.addEventListener('keydown', function(e) {{
+    if (!e.ctrlKey || (e.key !== 'Tab' && e.key !== 'w')) return;
+    var tabs = document.querySelectorAll('#tabStrip .tab');
+    var active = Array.prototype.findIndex.call(tabs, function(t) {{ return t.classList.contains('active'); }});
+    if (e.key === 'w') {{
+        e.preventDefault();
+        window.ipc.postMessage('close:' + Math.max(active, 0));
+        return;
+    }}
+    if (tabs.length < 2) return;
+    e.preventDefault();
+    var next = (active + (e.shiftKey ? tabs.length - 1 : 1)) % tabs.length;
+    recordJump();
+    window.ipc.postMessage('tab:' + next);
+}});
+
+// --follow: reloads keep the view at the end while it was already there
+var followMode = {follow};
+function isAtBottom() {{
+    return window.innerHeight + window.scrollY >= document.documentElement.scrollHeight - 4;
+}}
+function scrollToBottom() {{
+    window.scrollTo(0, document.documentElement.scrollHeight);
+}}
+
+// Open the file in the user's editor at the first source line in view
+function editDocument() {{
+    var line = 1;
+    document.querySelectorAll('.content [data-sourcepos]').forEach(function(el) {{
+        if (el.getBoundingClientRect().top <= 1) line = parseInt(el.getAttribute('data-sourcepos'), 10);
+    }});
+    window.ipc.postMessage('edit:' + line);
+}}
+document.addEventListener('keydown', function(e) {{
+    if (e.ctrlKey && e.shiftKey && (e.key === 'E' || e.key === 'e')) {{
+        e.preventDefault();
+        editDocument();
+    }}
+}});
+
+// Scroll to the last rendered block starting at or before a 1-based source line
+function scrollToSourceLine(line) {{
+    var target = null;
+    document.querySelectorAll('.content [data-sourcepos]').forEach(function(el) {{
+        var start = parseInt(el.getAttribute('data-sourcepos'), 10);
+        if (start <= line) target = el;
+    }});
+    if (target) target.scrollIntoView({{ block: 'start' }});
+}}
 </script>
-<div class="search-bar" id="searchBar" style="display:none;">
-    <input type="text" id="searchInput" placeholder="Search..." />
-    <span class="search-info" id="searchInfo">0/0</span>
-    <button onclick="searchNav(-1)">&#9650;</button>
-    <button onclick="searchNav(1)">&#9660;</button>
-    <button class="close-btn" onclick="closeSearch()">Esc</button>
+<div class="search-bar" id="searchBar" role="search" style="display:none;">
+    <input type="text" id="searchInput" placeholder="Search..." aria-label="Search the document" />
+    <label class="search-option" title="Match case"><input type="checkbox" id="searchCase" /> Aa</label>
+    <label class="search-option" title="Treat the query as a regular expression"><input type="checkbox" id="searchRegex" /> .*</label>
+    <label class="search-option" title="Also match table of contents entries"><input type="checkbox" id="searchToc" /> TOC</label>
+    <span class="search-info" id="searchInfo" aria-live="polite">0/0</span>
+    <button onclick="searchNav(-1)" title="Previous match (Shift+Enter)" aria-label="Previous match">&#9650;</button>
+    <button onclick="searchNav(1)" title="Next match (Enter)" aria-label="Next match">&#9660;</button>
+    <button class="close-btn" onclick="closeSearch()" aria-label="Close search">Esc</button>
 </div>
-<script>
+<script>{search_matches}
 (function() {{
+    // Typing only searches once it pauses for this long, so large documents don't
+    // re-highlight on every keystroke
+    var SEARCH_DELAY_MS = 150;
+    // One entry per match: the marks it is drawn with, one per text node it covers
     var matches = [];
     var currentIdx = -1;
+    var invalid = false;
+    var pending = null;
+    // Text nodes swapped for highlighted copies, with the nodes that replaced them
+    var replaced = [];
 
+    // Put every highlighted text node back as it was, so repeated searches leave
+    // the document exactly as rendered
     function clearHighlights() {{
-        document.querySelectorAll('mark.search-highlight').forEach(function(m) {{
-            var parent = m.parentNode;
-            parent.replaceChild(document.createTextNode(m.textContent), m);
-            parent.normalize();
+        replaced.forEach(function(entry) {{
+            var first = entry.parts[0];
+            if (!first.parentNode) return;
+            first.parentNode.insertBefore(entry.node, first);
+            entry.parts.forEach(function(part) {{ part.remove(); }});
+        }});
+        replaced = [];
+        document.querySelectorAll('.sidebar a.search-match').forEach(function(a) {{
+            a.classList.remove('search-match');
         }});
         matches = [];
         currentIdx = -1;
     }}
 
-    function highlightMatches(query) {{
-        clearHighlights();
-        if (!query) {{ updateInfo(); return; }}
+    // With "TOC" checked, mark matching sidebar links and return the index of
+    // the first match inside a heading they point to
+    function markTocMatches(pattern) {{
+        if (!document.getElementById('searchToc').checked) return -1;
+        var first = -1;
+        document.querySelectorAll('.sidebar a').forEach(function(a) {{
+            pattern.lastIndex = 0;
+            if (!pattern.test(a.textContent)) return;
+            a.classList.add('search-match');
+            var heading = document.getElementById(a.getAttribute('href').substring(1));
+            if (first >= 0 || !heading) return;
+            for (var i = 0; i < matches.length; i++) {{
+                if (heading.contains(matches[i][0])) {{ first = i; break; }}
+            }}
+        }});
+        return first;
+    }}
+
+    // The content's text nodes grouped by the block they sit in, with each node's
+    // offset into its block's text. Matches run across inline formatting such as
+    // **bold** or `code`, but not from one block into the next. Diagrams are skipped.
+    function textBlocks() {{
         var walker = document.createTreeWalker(
             document.querySelector('.content'),
-            NodeFilter.SHOW_TEXT, null, false
+            NodeFilter.SHOW_TEXT,
+            {{ acceptNode: function(node) {{
+                return node.parentNode.closest('svg, script, style') ? NodeFilter.FILTER_REJECT : NodeFilter.FILTER_ACCEPT;
+            }} }},
+            false
         );
-        var textNodes = [];
-        while (walker.nextNode()) textNodes.push(walker.currentNode);
-
-        var queryLower = query.toLowerCase();
-        for (var i = textNodes.length - 1; i >= 0; i--) {{
-            var node = textNodes[i];
-            var text = node.textContent;
-            var textLower = text.toLowerCase();
-            var idx = textLower.lastIndexOf(queryLower);
-            while (idx >= 0) {{
-                var range = document.createRange();
-                range.setStart(node, idx);
-                range.setEnd(node, idx + query.length);
+        var blocks = [];
+        var block = null;
+        while (walker.nextNode()) {{
+            var node = walker.currentNode;
+            var element = node.parentNode.closest('p, li, dt, dd, td, th, h1, h2, h3, h4, h5, h6, pre, blockquote, .content');
+            if (!block || block.element !== element) {{
+                block = {{ element: element, nodes: [], text: '' }};
+                blocks.push(block);
+            }}
+            block.nodes.push({{ node: node, start: block.text.length }});
+            block.text += node.textContent;
+        }}
+        return blocks;
+    }}
+
+    // Without `restoreIdx` the search starts at the first match (or heading, with
+    // "TOC") and scrolls to it; with it, that match stays current and the view stays put
+    function highlightMatches(query, restoreIdx) {{
+        clearTimeout(pending);
+        pending = null;
+        clearHighlights();
+        var pattern = query
+            ? searchPattern(query, document.getElementById('searchRegex').checked, document.getElementById('searchCase').checked)
+            : null;
+        invalid = !!query && !pattern;
+        document.getElementById('searchInput').classList.toggle('invalid', invalid);
+        if (!pattern) {{ updateInfo(); return; }}
+
+        // First find every match and the pieces of text nodes it covers, leaving
+        // the tree untouched while it is being read
+        var found = matchPieces(textBlocks(), pattern);
+
+        // Then swap each affected text node for its highlighted copy in one write
+        for (var i = 0; i < found.count; i++) matches.push([]);
+        found.pieces.forEach(function(list, node) {{
+            var parts = splitPieces(node.textContent, list).map(function(part) {{
+                if (part.match === null) return document.createTextNode(part.text);
                 var mark = document.createElement('mark');
                 mark.className = 'search-highlight';
-                range.surroundContents(mark);
-                node = mark.previousSibling || node.parentNode.firstChild;
-                idx = idx > 0 ? node.textContent.toLowerCase().lastIndexOf(queryLower, idx - 1) : -1;
-            }}
+                mark.textContent = part.text;
+                matches[part.match].push(mark);
+                return mark;
+            }});
+            var fragment = document.createDocumentFragment();
+            parts.forEach(function(part) {{ fragment.appendChild(part); }});
+            node.replaceWith(fragment);
+            replaced.push({{ node: node, parts: parts }});
+        }});
+        var firstHeading = markTocMatches(pattern);
+        if (matches.length > 0 && restoreIdx !== undefined) {{
+            currentIdx = Math.min(Math.max(restoreIdx, 0), matches.length - 1);
+            goToCurrent(false);
+        }} else if (matches.length > 0) {{
+            currentIdx = Math.max(firstHeading, 0);
+            goToCurrent(true);
         }}
-        matches = document.querySelectorAll('mark.search-highlight');
-        if (matches.length > 0) {{ currentIdx = 0; goToCurrent(); }}
         updateInfo();
     }}
 
-    function goToCurrent() {{
+    function scheduleSearch() {{
+        clearTimeout(pending);
+        pending = setTimeout(function() {{
+            highlightMatches(document.getElementById('searchInput').value);
+        }}, SEARCH_DELAY_MS);
+    }}
+
+    function goToCurrent(scroll) {{
         document.querySelectorAll('mark.search-highlight.current').forEach(function(m) {{ m.classList.remove('current'); }});
         if (matches.length > 0 && currentIdx >= 0) {{
-            matches[currentIdx].classList.add('current');
-            matches[currentIdx].scrollIntoView({{ behavior: 'smooth', block: 'center' }});
+            matches[currentIdx].forEach(function(m) {{ m.classList.add('current'); }});
+            if (scroll) matches[currentIdx][0].scrollIntoView({{ behavior: scrollBehavior(), block: 'center' }});
         }}
     }}
 
+    // Live reloads and tab switches swap out the content the marks were in: search
+    // the new content again, keeping the current match number
+    window.refreshSearch = function() {{
+        var query = document.getElementById('searchInput').value;
+        if (document.getElementById('searchBar').style.display === 'none' || !query) return;
+        highlightMatches(query, currentIdx);
+    }};
+
     function updateInfo() {{
         var info = document.getElementById('searchInfo');
-        if (matches.length === 0) {{ info.textContent = '0/0'; }}
+        if (invalid) {{ info.textContent = 'invalid regex'; }}
+        else if (matches.length === 0) {{ info.textContent = '0/0'; }}
         else {{ info.textContent = (currentIdx + 1) + '/' + matches.length; }}
     }}
 
     window.searchNav = function(dir) {{
+        // Enter right after typing runs the search that was still waiting
+        if (pending !== null) {{ highlightMatches(document.getElementById('searchInput').value); return; }}
         if (matches.length === 0) return;
         currentIdx = (currentIdx + dir + matches.length) % matches.length;
-        goToCurrent();
+        goToCurrent(true);
         updateInfo();
     }};
 
+    // Focus goes back to where it was when the search bar closes
+    var focusBeforeSearch = null;
     window.closeSearch = function() {{
-        document.getElementById('searchBar').style.display = 'none';
+        clearTimeout(pending);
+        pending = null;
+        var bar = document.getElementById('searchBar');
+        var wasOpen = bar.style.display !== 'none';
+        bar.style.display = 'none';
         clearHighlights();
         updateInfo();
+        if (wasOpen && focusBeforeSearch && document.contains(focusBeforeSearch)) focusBeforeSearch.focus();
+        focusBeforeSearch = null;
     }};
 
     document.addEventListener('keydown', function(e) {{
-        if ((e.ctrlKey || e.metaKey) && e.key === 'f') {{
+        var typing = /^(INPUT|TEXTAREA|SELECT)$/.test(document.activeElement.tagName);
+        if (((e.ctrlKey || e.metaKey) && e.key === 'f') || (e.key === '/' && !typing && !e.ctrlKey && !e.metaKey)) {{
             e.preventDefault();
             var bar = document.getElementById('searchBar');
-            bar.style.display = 'flex';
             var input = document.getElementById('searchInput');
+            if (bar.style.display === 'none') focusBeforeSearch = document.activeElement;
+            bar.style.display = 'flex';
             input.focus();
             input.select();
         }}
@@ -437,21 +1366,256 @@ document.querySelector('.sidebar').addEventListener('click', function(e) {{
         }}
     }});
 
-    document.getElementById('searchInput').addEventListener('input', function() {{
-        highlightMatches(this.value);
+    document.getElementById('searchInput').addEventListener('input', scheduleSearch);
+    ['searchCase', 'searchRegex', 'searchToc'].forEach(function(id) {{
+        document.getElementById(id).addEventListener('change', function() {{
+            highlightMatches(document.getElementById('searchInput').value);
+        }});
     }});
 }})();
 </script>
+<script>
+(function() {{
+    var toastTimer = null;
+    function showToast(message) {{
+        var toast = document.getElementById('toast');
+        toast.textContent = message;
+        toast.classList.add('visible');
+        clearTimeout(toastTimer);
+        toastTimer = setTimeout(function() {{ toast.classList.remove('visible'); }}, 2000);
+    }}
+
+    // execCommand fallback for pages without async clipboard access (about:blank origin)
+    function copyWithEvent(html, text) {{
+        var onCopy = function(e) {{
+            if (html !== null) e.clipboardData.setData('text/html', html);
+            e.clipboardData.setData('text/plain', text);
+            e.preventDefault();
+        }};
+        document.addEventListener('copy', onCopy);
+        var ok = document.execCommand('copy');
+        document.removeEventListener('copy', onCopy);
+        return ok;
+    }}
+
+    window.copyDocument = function(asHtml) {{
+        var content = document.querySelector('.content').cloneNode(true);
+        content.querySelectorAll('mark.search-highlight').forEach(function(m) {{
+            m.replaceWith(document.createTextNode(m.textContent));
+        }});
+        content.querySelectorAll('[data-sourcepos]').forEach(function(el) {{
+            el.removeAttribute('data-sourcepos');
+        }});
+        content.querySelectorAll('pre .line').forEach(function(el) {{
+            el.replaceWith(document.createTextNode(el.textContent));
+        }});
+        content.querySelectorAll('.heading-anchor').forEach(function(el) {{ el.remove(); }});
+        content.querySelectorAll('.doc-section').forEach(function(el) {{ el.replaceWith(...el.childNodes); }});
+        var html = asHtml ? content.innerHTML : null;
+        var text = document.querySelector('.content').innerText;
+        var done = function() {{ showToast(asHtml ? 'Copied document as HTML' : 'Copied document as plain text'); }};
+        var fallback = function() {{
+            if (copyWithEvent(html, text)) done(); else showToast('Clipboard unavailable');
+        }};
+        if (navigator.clipboard && window.ClipboardItem) {{
+            var items = {{ 'text/plain': new Blob([text], {{ type: 'text/plain' }}) }};
+            if (html !== null) items['text/html'] = new Blob([html], {{ type: 'text/html' }});
+            navigator.clipboard.write([new ClipboardItem(items)]).then(done, fallback);
+        }} else {{
+            fallback();
+        }}
+    }};
+
+    // The anchor beside a heading copies a link to it instead of jumping
+    document.querySelector('.content').addEventListener('click', function(e) {{
+        var anchor = e.target.closest('.heading-anchor');
+        if (!anchor) return;
+        e.preventDefault();
+        var link = anchor.getAttribute('data-link');
+        var done = function() {{ showToast('Copied ' + link); }};
+        var fallback = function() {{
+            if (copyWithEvent(null, link)) done(); else showToast('Clipboard unavailable');
+        }};
+        if (navigator.clipboard && navigator.clipboard.writeText) {{
+            navigator.clipboard.writeText(link).then(done, fallback);
+        }} else {{
+            fallback();
+        }}
+    }});
+
+    document.addEventListener('keydown', function(e) {{
+        if ((e.ctrlKey || e.metaKey) && (e.key === 'C' || e.key === 'c') && (e.shiftKey || e.altKey)) {{
+            e.preventDefault();
+            window.copyDocument(e.shiftKey);
+        }}
+    }});
+}})();
+</script>
+<script>
+// Click an image or diagram to see it at full size; drag to pan, click or Esc to close
+(function() {{
+    var box = document.getElementById('lightbox');
+    var drag = null;
+    var dragged = false;
+
+    function open(el) {{
+        var copy = el.cloneNode(true);
+        copy.removeAttribute('style');
+        if (el.tagName.toLowerCase() === 'svg') {{
+            var viewBox = el.viewBox && el.viewBox.baseVal;
+            if (viewBox && viewBox.width > 0) {{
+                copy.setAttribute('width', viewBox.width);
+                copy.setAttribute('height', viewBox.height);
+            }}
+        }} else {{
+            copy.removeAttribute('width');
+            copy.removeAttribute('height');
+        }}
+        box.replaceChildren(copy);
+        box.hidden = false;
+        box.scrollLeft = (box.scrollWidth - box.clientWidth) / 2;
+        box.scrollTop = 0;
+    }}
+
+    function close() {{
+        box.hidden = true;
+        box.replaceChildren();
+    }}
+
+    document.querySelector('.content').addEventListener('click', function(e) {{
+        var target = e.target.closest('img, .mermaid-diagram svg');
+        if (!target || target.closest('a')) return;
+        e.preventDefault();
+        open(target);
+    }});
+    box.addEventListener('mousedown', function(e) {{
+        if (e.button !== 0) return;
+        e.preventDefault();
+        drag = {{ x: e.clientX, y: e.clientY, left: box.scrollLeft, top: box.scrollTop }};
+        dragged = false;
+    }});
+    window.addEventListener('mousemove', function(e) {{
+        if (!drag) return;
+        var dx = e.clientX - drag.x;
+        var dy = e.clientY - drag.y;
+        if (Math.abs(dx) + Math.abs(dy) > 3) dragged = true;
+        box.scrollLeft = drag.left - dx;
+        box.scrollTop = drag.top - dy;
+    }});
+    window.addEventListener('mouseup', function() {{ drag = null; }});
+    box.addEventListener('click', function() {{
+        if (!dragged) close();
+    }});
+    // Captured first so Esc closes only the lightbox, not the search bar under it
+    window.addEventListener('keydown', function(e) {{
+        if (e.key !== 'Escape' || box.hidden) return;
+        e.stopPropagation();
+        close();
+    }}, true);
+}})();
+</script>
 {mermaid_script}
+{sortable_script}
+{start_script}
 </body>
 </html>"#,
         css = GITHUB_CSS,
+        body_class = body_class,
+        tab_strip = tab_strip,
+        recent = recent,
         toc = toc_html,
+        stats = stats,
+        meta = escape_attr(meta),
         body = body,
-        mermaid_script = mermaid_script
+        mermaid_script = mermaid_script,
+        sortable_script = sortable_script,
+        search_matches = SEARCH_MATCHES_JS,
+        progress_bar = progress_bar,
+        smooth_scroll = settings.scroll_behavior == ScrollBehavior::Smooth,
+        start_script = start_script,
+        follow = follow,
+        focus = focus
     )
 }
 
+/// The window as it was last closed: position and size in logical pixels, and
+/// the maximized, always-on-top and focus mode flags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WindowState {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    maximized: bool,
+    always_on_top: bool,
+    focus_mode: bool,
+}
+
+impl WindowState {
+    /// Parse the one-line `x y width height maximized always_on_top focus_mode` form
+    /// written by [`save_window_state`].
+    fn parse(s: &str) -> Option<Self> {
+        let mut fields = s.split_whitespace();
+        let mut number = || fields.next()?.parse::<f64>().ok().filter(|n| n.is_finite());
+        let (x, y, width, height) = (number()?, number()?, number()?, number()?);
+        let maximized = number()? != 0.0;
+        let always_on_top = number().is_some_and(|n| n != 0.0);
+        let focus_mode = number().is_some_and(|n| n != 0.0);
+        Some(WindowState {
+            x,
+            y,
+            width: width.max(MIN_SIZE.0),
+            height: height.max(MIN_SIZE.1),
+            maximized,
+            always_on_top,
+            focus_mode,
+        })
+    }
+
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {} {} {} {}\n",
+            self.x,
+            self.y,
+            self.width,
+            self.height,
+            u8::from(self.maximized),
+            u8::from(self.always_on_top),
+            u8::from(self.focus_mode)
+        )
+    }
+}
+
+/// Where the window state is kept between sessions (egui has eframe's storage for this).
+fn window_state_file() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/state")))?
+    };
+    Some(dir.join("mdr").join("webview-window"))
+}
+
+/// The state saved when the last window closed, if any.
+fn load_window_state() -> Option<WindowState> {
+    WindowState::parse(&std::fs::read_to_string(window_state_file()?).ok()?)
+}
+
+/// Remember `state` for the next window.
+fn save_window_state(state: WindowState) -> Result<(), Box<dyn std::error::Error>> {
+    let path = window_state_file().ok_or("no directory to save the window state in")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, state.to_line())?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -550,6 +1714,20 @@ mod tests {
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn resolve_local_images_in_tables_and_lists() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_webview_images_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pic.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let md = "| Logo | Name |\n|---|---|\n| ![a](pic.png \"A title\") | x |\n\n- item ![b](pic.png)\n";
+        let html = resolve_local_images(&parse_markdown_with_sourcepos(md, &RenderSettings::default(), &dir).html, &dir);
+        assert_eq!(html.matches("src=\"data:image/png;base64,").count(), 2, "{}", html);
+        assert!(html.contains("title=\"A title\""), "{}", html);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn resolve_local_images_empty_base_dir() {
         // When file_path.parent() is empty (bare filename), base_dir is ""
@@ -665,4 +1843,254 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn heading_anchors_link_to_the_file_and_id() {
+        let html = r#"<h2 data-sourcepos="3:1-3:9" id="install">Install</h2><h3>No id</h3>"#;
+        let result = add_heading_anchors(html, std::path::Path::new("docs/guide.md"));
+        assert_eq!(
+            result,
+            r##"<h2 data-sourcepos="3:1-3:9" id="install"><a class="heading-anchor" href="#install" data-link="docs/guide.md#install" title="Copy link to this section" aria-label="Copy link to this section"></a>Install</h2><h3>No id</h3>"##
+        );
+    }
+
+    #[test]
+    fn sections_split_at_top_level_headings_only() {
+        let md = "Intro\n\n# One\n\n> ## Quoted\n\n- item\n\n  ## In a list\n\n## Two\n\ntext\n";
+        let body = render_body(md, &RenderSettings::default(), std::path::Path::new("."));
+        assert_eq!(body.heading_lines, [3, 11]);
+        let sections = split_sections(&body.html, &body.heading_lines);
+        assert_eq!(sections.len(), 3, "{:#?}", sections);
+        assert!(sections[0].contains("Intro") && !sections[0].contains("<h1"));
+        assert!(sections[1].contains("One") && sections[1].contains("Quoted") && sections[1].contains("In a list"));
+        assert!(sections[2].starts_with("<h2") && sections[2].contains("text"));
+        assert_eq!(split_sections("<pre>raw</pre>", &[1]).len(), 1);
+    }
+
+    #[test]
+    fn reloads_send_only_the_changed_sections() {
+        let sections = |texts: &[&str]| texts.iter().map(|text| text.to_string()).collect::<Vec<_>>();
+        let mut shown = Vec::new();
+        let first = sections_update_script(&sections(&["a", "b", "c"]), &mut shown);
+        assert_eq!(first, r#"replaceSections(0, 0, ["a","b","c"]);"#);
+        assert_eq!(sections_update_script(&sections(&["a", "B", "c"]), &mut shown), r#"replaceSections(1, 1, ["B"]);"#);
+        assert_eq!(sections_update_script(&sections(&["a", "B", "x", "c"]), &mut shown), r#"replaceSections(2, 1, ["x"]);"#);
+        assert_eq!(sections_update_script(&sections(&["a", "c"]), &mut shown), r#"replaceSections(1, 1, []);"#);
+        assert_eq!(sections_update_script(&sections(&["a", "c"]), &mut shown), r#"replaceSections(2, 0, []);"#);
+    }
+
+    #[test]
+    fn parse_tab_messages() {
+        assert_eq!(parse_tab_message("tab:2"), Some(TabEvent::Switch(2)));
+        assert_eq!(parse_tab_message("close:0"), Some(TabEvent::Close(0)));
+        assert_eq!(parse_tab_message("close:-1"), None);
+        assert_eq!(parse_tab_message("reload"), Some(TabEvent::Reload));
+        assert_eq!(parse_tab_message("reload:1"), None);
+        assert_eq!(parse_tab_message("always-on-top"), Some(TabEvent::ToggleAlwaysOnTop));
+        assert_eq!(parse_tab_message("focus-mode"), Some(TabEvent::ToggleFocusMode));
+        assert_eq!(parse_tab_message("edit:12"), Some(TabEvent::Edit(12)));
+        assert_eq!(parse_tab_message("open"), Some(TabEvent::Open));
+        assert_eq!(parse_tab_message("recent:3"), Some(TabEvent::OpenRecent(3)));
+        assert_eq!(parse_tab_message("tab"), None);
+    }
+
+    #[test]
+    fn recent_files_list_names_with_full_path_on_hover() {
+        assert_eq!(build_recent_html(&[]), "");
+        let html = build_recent_html(&[PathBuf::from("/docs/a&b.md"), PathBuf::from("/notes/todo.md")]);
+        assert!(html.contains(r#"<button data-recent="0" title="/docs/a&amp;b.md">a&amp;b.md</button>"#), "{}", html);
+        assert!(html.contains(r#"data-recent="1" title="/notes/todo.md">todo.md<"#), "{}", html);
+    }
+
+    #[test]
+    fn tab_strip_only_with_several_files() {
+        let dir = std::env::temp_dir().join("mdr_test_webview_tabs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "# A\n").unwrap();
+        std::fs::write(dir.join("b&c.md"), "# B\n").unwrap();
+
+        let mut tabs = vec![Tab::open(dir.join("a.md"), &RenderSettings::default()).unwrap()];
+        assert_eq!(build_tab_strip_html(&tabs, 0), "");
+
+        tabs.push(Tab::open(dir.join("b&c.md"), &RenderSettings::default()).unwrap());
+        let html = build_tab_strip_html(&tabs, 1);
+        assert!(html.contains(r#"<span class="tab" data-tab="0""#), "got: {}", html);
+        assert!(html.contains(r#"<span class="tab active" data-tab="1""#), "got: {}", html);
+        assert!(html.contains(r#"data-tab="1" role="tab" aria-selected="true""#), "got: {}", html);
+        assert!(html.contains(">b&amp;c.md<button"), "got: {}", html);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn page_has_landmarks_and_labels() {
+        let page = |theme| build_html(&Page { body: "<p>Hi</p>", ..Page::default() }, &RenderSettings { theme, ..RenderSettings::default() });
+        let html = page(Theme::Default);
+        for landmark in [
+            r#"<nav class="sidebar" aria-label="Table of contents">"#,
+            "<main class=\"content\">\n<p>Hi</p>\n</main>",
+            r#"role="search""#,
+            r#"<input type="text" id="searchInput" placeholder="Search..." aria-label="Search the document" />"#,
+            r#"id="searchInfo" aria-live="polite""#,
+        ] {
+            assert!(html.contains(landmark), "{} missing", landmark);
+        }
+        assert!(html.contains("<body>"), "no body classes expected");
+        assert!(page(Theme::HighContrast).contains(r#"<body class="high-contrast">"#));
+    }
+
+    #[test]
+    fn sortable_tables_script_only_with_the_option() {
+        let table = "<table><thead><tr><th>Name</th></tr></thead><tbody><tr><td>b</td></tr></tbody></table>";
+        let page = |sortable_tables| build_html(&Page { body: table, ..Page::default() }, &RenderSettings { sortable_tables, ..RenderSettings::default() });
+        assert!(page(true).contains("window.prepareSortableTables = function()"));
+        assert!(!page(false).contains("window.prepareSortableTables = function()"));
+        // Reloads swap sections in and mark their tables again
+        assert!(page(false).contains("if (window.prepareSortableTables) prepareSortableTables();"));
+    }
+
+    #[test]
+    fn progress_bar_can_be_left_out() {
+        let page = |progress_bar| build_html(&Page { body: "<p>Hi</p>", ..Page::default() }, &RenderSettings { progress_bar, ..RenderSettings::default() });
+        let bar = r#"<div class="reading-progress" id="readingProgress" aria-hidden="true"></div>"#;
+        assert!(page(true).contains(bar));
+        assert!(!page(false).contains(bar));
+        // The script copes with the bar missing, so reloads can call it either way
+        assert!(page(false).contains("if (!bar) return;"));
+    }
+
+    #[test]
+    fn scroll_behavior_follows_the_option() {
+        let page = |scroll_behavior| build_html(&Page { body: "<p>Hi</p>", ..Page::default() }, &RenderSettings { scroll_behavior, ..RenderSettings::default() });
+        assert!(page(ScrollBehavior::Smooth).contains("var SMOOTH_SCROLL = true;"));
+        assert!(page(ScrollBehavior::Instant).contains("var SMOOTH_SCROLL = false;"));
+        // Both the sidebar and search jumps go through it
+        assert_eq!(page(ScrollBehavior::Smooth).matches("behavior: scrollBehavior()").count(), 2);
+    }
+
+    #[test]
+    fn raw_view_follows_the_theme() {
+        let theme = |theme, no_color| raw_syntax_theme(&RenderSettings { theme, no_color, ..RenderSettings::default() });
+        assert_eq!(theme(Theme::Light, false), Some("InspiredGitHub"));
+        assert_eq!(theme(Theme::Solarized, false), Some("Solarized (dark)"));
+        assert_eq!(theme(Theme::Default, false), Some("base16-ocean.dark"));
+        assert_eq!(theme(Theme::HighContrast, false), None);
+        assert_eq!(theme(Theme::Light, true), None);
+    }
+
+    /// Run `SEARCH_MATCHES_JS` followed by `script` in Node and return what it
+    /// prints, or `None` where Node isn't installed.
+    fn run_search_js(script: &str) -> Option<String> {
+        let output = std::process::Command::new("node")
+            .arg("-e")
+            .arg(format!("{}\n{}", SEARCH_MATCHES_JS, script))
+            .output()
+            .ok()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// The marks a search draws in a block made of `nodes`, one `[node, text, match]`
+    /// per piece, as JSON.
+    fn search_marks(nodes: &[&str], query: &str, regex: bool, case: bool) -> Option<String> {
+        let script = format!(
+            r#"var nodes = {nodes};
+var block = {{ text: '', nodes: [] }};
+nodes.forEach(function(text) {{
+    block.nodes.push({{ node: {{ textContent: text }}, start: block.text.length }});
+    block.text += text;
+}});
+var found = matchPieces([block], searchPattern({query}, {regex}, {case}));
+var marks = [];
+block.nodes.forEach(function(entry, i) {{
+    splitPieces(entry.node.textContent, found.pieces.get(entry.node) || []).forEach(function(part) {{
+        if (part.match !== null) marks.push([i, part.text, part.match]);
+    }});
+}});
+console.log(JSON.stringify(marks));"#,
+            nodes = serde_json::to_string(nodes).unwrap(),
+            query = serde_json::to_string(query).unwrap(),
+        );
+        run_search_js(&script)
+    }
+
+    #[test]
+    fn back_to_back_matches_are_separate() {
+        let Some(marks) = search_marks(&["abab ab"], "ab", false, false) else { return };
+        assert_eq!(marks, r#"[[0,"ab",0],[0,"ab",1],[0,"ab",2]]"#);
+    }
+
+    #[test]
+    fn matches_straddle_inline_tags() {
+        // "foo **bar** baz" and "x`y`z": a match is one mark per text node it covers
+        let Some(marks) = search_marks(&["foo b", "ar", " baz"], "o bar b", false, false) else { return };
+        assert_eq!(marks, r#"[[0,"o b",0],[1,"ar",0],[2," b",0]]"#);
+        let Some(marks) = search_marks(&["x", "y", "z", "xyz"], "zx", false, false) else { return };
+        assert_eq!(marks, r#"[[2,"z",0],[3,"x",0]]"#);
+    }
+
+    #[test]
+    fn splitting_keeps_the_text_around_marks() {
+        let script = r#"console.log(JSON.stringify(splitPieces('a bc d', [{ from: 2, to: 3, match: 0 }, { from: 3, to: 4, match: 1 }])));"#;
+        let Some(parts) = run_search_js(script) else { return };
+        assert_eq!(parts, r#"[{"text":"a ","match":null},{"text":"b","match":0},{"text":"c","match":1},{"text":" d","match":null}]"#);
+    }
+
+    #[test]
+    fn search_options_change_what_matches() {
+        // Plain queries are literal and ignore case
+        let Some(marks) = search_marks(&["a.c abc A.C"], "a.c", false, false) else { return };
+        assert_eq!(marks, r#"[[0,"a.c",0],[0,"A.C",1]]"#);
+        let Some(marks) = search_marks(&["a.c abc A.C"], "a.c", false, true) else { return };
+        assert_eq!(marks, r#"[[0,"a.c",0]]"#);
+        let Some(marks) = search_marks(&["a.c abc A.C"], "a.c", true, true) else { return };
+        assert_eq!(marks, r#"[[0,"a.c",0],[0,"abc",1]]"#);
+        // Empty matches don't loop or mark anything
+        let Some(marks) = search_marks(&["xx y"], "y*", true, false) else { return };
+        assert_eq!(marks, r#"[[0,"y",0]]"#);
+        // A regex that doesn't compile gives no pattern, which the bar shows as invalid
+        let Some(pattern) = run_search_js("console.log(searchPattern('(', true, false));") else { return };
+        assert_eq!(pattern, "null");
+    }
+
+    #[test]
+    fn typing_searches_after_a_pause() {
+        let page = build_html(&Page { body: "<p>Hi</p>", ..Page::default() }, &RenderSettings::default());
+        assert!(page.contains("var SEARCH_DELAY_MS = 150;"));
+        assert!(page.contains("addEventListener('input', scheduleSearch)"));
+        // Enter runs a search that is still waiting instead of moving on
+        assert!(page.contains("if (pending !== null) { highlightMatches(document.getElementById('searchInput').value); return; }"));
+        // Toggling an option searches again straight away
+        assert!(page.contains("['searchCase', 'searchRegex', 'searchToc'].forEach"));
+        assert!(page.contains(SEARCH_MATCHES_JS));
+    }
+
+    #[test]
+    fn reloads_search_the_new_content_again() {
+        let script = reload_script("UPDATE;");
+        let update = script.find("UPDATE;").unwrap();
+        assert!(script.find("refreshSearch();").unwrap() > update);
+        let page = build_html(&Page { body: "<p>Hi</p>", ..Page::default() }, &RenderSettings::default());
+        // Only an open search with a query is redone, keeping its match number
+        assert!(page.contains("if (document.getElementById('searchBar').style.display === 'none' || !query) return;"));
+        assert!(page.contains("highlightMatches(query, currentIdx);"));
+    }
+
+    #[test]
+    fn window_state_round_trips_through_its_line() {
+        let state = WindowState {
+            x: -10.0,
+            y: 20.5,
+            width: 900.0,
+            height: 700.0,
+            maximized: true,
+            always_on_top: true,
+            focus_mode: true,
+        };
+        assert_eq!(WindowState::parse(&state.to_line()), Some(state));
+        assert_eq!(WindowState::parse("1 2 3"), None);
+        assert_eq!(WindowState::parse("0 0 NaN 700 0"), None);
+        assert_eq!(WindowState::parse("0 0 800 600 0").map(|state| state.always_on_top), Some(false));
+        assert_eq!(WindowState::parse("0 0 800 600 0 1").map(|state| state.focus_mode), Some(false));
+    }
 }
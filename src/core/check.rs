@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use comrak::nodes::NodeValue;
+use comrak::{parse_document, Arena, Options};
+use regex::Regex;
+
+use crate::core::markdown::percent_decode;
+use crate::core::toc::extract_headings;
+
+/// What a broken reference points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    MissingImage,
+    MissingFile,
+    UnknownAnchor,
+}
+
+/// A link or image `--check` could not resolve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// 1-based source line of the link or image.
+    pub line: usize,
+    pub kind: ProblemKind,
+    /// The destination as written in the document.
+    pub target: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            ProblemKind::MissingImage => "missing image",
+            ProblemKind::MissingFile => "missing file",
+            ProblemKind::UnknownAnchor => "unknown anchor",
+        };
+        write!(f, "{}: {} '{}'", self.line, what, self.target)
+    }
+}
+
+/// Find the local images and links in `content` that lead nowhere: paths that
+/// don't exist under `base_dir`, and `#anchors` that match no heading slug (as
+/// in the TOC) or HTML `id`/`name`. Remote URLs are not checked.
+///
+/// ```
+/// let problems = mdr::check::check_document("# Intro\n\n[up](#intro) [oops](#outro)\n", ".".as_ref());
+/// assert_eq!(problems.len(), 1);
+/// assert_eq!(problems[0].to_string(), "3: unknown anchor '#outro'");
+/// ```
+pub fn check_document(content: &str, base_dir: &Path) -> Vec<Problem> {
+    let anchors = known_anchors(content);
+    let arena = Arena::new();
+    let mut options = Options::default();
+    options.extension.strikethrough = true;
+    options.extension.table = true;
+    options.extension.autolink = true;
+    options.extension.tasklist = true;
+    options.extension.footnotes = true;
+    let root = parse_document(&arena, content, &options);
+
+    let mut problems = Vec::new();
+    for node in root.descendants() {
+        let data = node.data.borrow();
+        let (url, is_image) = match &data.value {
+            NodeValue::Image(link) => (link.url.as_str(), true),
+            NodeValue::Link(link) => (link.url.as_str(), false),
+            _ => continue,
+        };
+        if url.is_empty() || has_scheme(url) {
+            continue;
+        }
+        let (path, fragment) = match url.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (url, None),
+        };
+        let kind = if !path.is_empty() {
+            let path = percent_decode(path);
+            let path = path.split_once('?').map_or(path.as_str(), |(path, _)| path);
+            let found = if path.starts_with('/') { Path::new(path).exists() } else { base_dir.join(path).exists() };
+            if found {
+                continue;
+            }
+            if is_image { ProblemKind::MissingImage } else { ProblemKind::MissingFile }
+        } else {
+            match fragment {
+                Some(fragment) if !anchors.contains(&percent_decode(fragment).to_lowercase()) => ProblemKind::UnknownAnchor,
+                _ => continue,
+            }
+        };
+        problems.push(Problem { line: data.sourcepos.start.line, kind, target: url.to_string() });
+    }
+    problems
+}
+
+/// Anchors a `#fragment` in the document can land on: heading slugs, with the
+/// `-1`, `-2`, ... suffixes repeated headings get, and explicit HTML ids.
+fn known_anchors(content: &str) -> HashSet<String> {
+    static RE_ID: OnceLock<Regex> = OnceLock::new();
+    let re_id = RE_ID.get_or_init(|| Regex::new(r#"\b(?:id|name)\s*=\s*["']([^"']+)["']"#).unwrap());
+
+    let mut anchors = HashSet::new();
+    for entry in extract_headings(content) {
+        let mut anchor = entry.anchor.clone();
+        let mut repeat = 0;
+        while !anchors.insert(anchor.clone()) {
+            repeat += 1;
+            anchor = format!("{}-{}", entry.anchor, repeat);
+        }
+    }
+    anchors.extend(re_id.captures_iter(content).map(|caps| caps[1].to_lowercase()));
+    anchors
+}
+
+/// Whether `url` starts with a scheme such as `https:` or `mailto:`. A single
+/// letter before the colon is a Windows drive, not a scheme.
+fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mdr_test_check_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn missing_images_and_files_are_reported() {
+        let dir = temp_dir("files");
+        std::fs::write(dir.join("here.png"), b"png").unwrap();
+        std::fs::write(dir.join("other doc.md"), b"# Other").unwrap();
+        let md = "# Doc\n\n![ok](here.png)\n\n![gone](img/gone.png \"Title\")\n\nSee [other](other%20doc.md#other), \
+                  [nope](nope.md) and [site](https://example.com/x.png).\n";
+
+        let problems = check_document(md, &dir);
+        assert_eq!(
+            problems,
+            vec![
+                Problem { line: 5, kind: ProblemKind::MissingImage, target: "img/gone.png".into() },
+                Problem { line: 7, kind: ProblemKind::MissingFile, target: "nope.md".into() },
+            ]
+        );
+        assert_eq!(problems[0].to_string(), "5: missing image 'img/gone.png'");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn anchors_must_match_a_heading_or_id() {
+        let md = "# Getting Started\n\n## Usage\n\n## Usage\n\n<a id=\"custom\"></a>\n\n\
+                  [a](#getting-started) [b](#usage-1) [c](#Usage) [d](#custom) [e](#instalation)\n";
+        let problems = check_document(md, Path::new("."));
+        assert_eq!(problems, vec![Problem { line: 9, kind: ProblemKind::UnknownAnchor, target: "#instalation".into() }]);
+        assert!(has_scheme("mailto:me@example.com") && !has_scheme("C:/docs/a.md") && !has_scheme("a.md"));
+    }
+}
@@ -0,0 +1,288 @@
+//! The settings file, `config.toml` in mdr's config directory: defaults for the
+//! rendering options, which the command line overrides.
+//!
+//! Its keys are named after the long options, such as `toc-depth = 3` or
+//! `theme = "light"`. Options that turn something off are written in the
+//! positive, as `mermaid = false` for `--no-mermaid`, and lists are TOML arrays,
+//! as `enable = ["dot", "plantuml"]`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::diagram_tools::DiagramTool;
+use super::RenderSettings;
+
+/// mdr's own config directory: under `%APPDATA%` on Windows, `~/Library/Application
+/// Support` on macOS, and `$XDG_CONFIG_HOME` (or `~/.config`) elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?
+    };
+    Some(dir.join("mdr"))
+}
+
+/// Where the settings file is looked for.
+pub fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// The default settings with the settings file applied over them. A missing file
+/// leaves the defaults; one that can't be read or parsed is an error naming it.
+pub fn load() -> Result<RenderSettings, String> {
+    let mut settings = RenderSettings::default();
+    let Some(path) = config_path() else {
+        return Ok(settings);
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(settings),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    settings.apply_config(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    crate::vlog!("config: read {}", path.display());
+    Ok(settings)
+}
+
+/// The settings file as written: every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    theme: Option<Theme>,
+    color: Option<bool>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    tab_width: Option<u64>,
+    toc_depth: Option<u64>,
+    toc_heuristic: Option<bool>,
+    code_line_numbers: Option<bool>,
+    mermaid: Option<bool>,
+    mermaid_fallback: Option<bool>,
+    enable: Option<Vec<String>>,
+    image_captions: Option<bool>,
+    show_meta: Option<bool>,
+    repo_url: Option<String>,
+    focus: Option<bool>,
+    sortable_tables: Option<bool>,
+    progress_bar: Option<bool>,
+    scroll_behavior: Option<ScrollBehavior>,
+    image_protocol: Option<ImageProtocol>,
+    follow: Option<bool>,
+    tui_poll: Option<u64>,
+    compact: Option<bool>,
+    tui_smooth_scroll: Option<bool>,
+    editor: Option<String>,
+    reload_debounce: Option<u64>,
+}
+
+impl RenderSettings {
+    /// Set the options in the settings file `text`, a TOML document; those it
+    /// leaves out keep their value. Numbers and URLs are checked as on the
+    /// command line.
+    pub fn apply_config(&mut self, text: &str) -> Result<(), String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        // Values the command line checks as text are checked the same way here
+        fn checked<T>(key: &str, value: impl ToString, parse: fn(&str) -> Result<T, String>) -> Result<T, String> {
+            parse(&value.to_string()).map_err(|e| format!("{}: {}", key, e))
+        }
+        let set = |field: &mut bool, value: Option<bool>| *field = value.unwrap_or(*field);
+        let unset = |field: &mut bool, value: Option<bool>| *field = value.map_or(*field, |on| !on);
+
+        if let Some(theme) = file.theme {
+            self.theme = theme;
+        }
+        unset(&mut self.no_color, file.color);
+        unset(&mut self.disabled_attributes.bold, file.bold);
+        unset(&mut self.disabled_attributes.italic, file.italic);
+        unset(&mut self.disabled_attributes.underline, file.underline);
+        if let Some(n) = file.tab_width {
+            self.tab_width = checked("tab-width", n, parse_tab_width)?;
+        }
+        if let Some(n) = file.toc_depth {
+            self.toc_depth = checked("toc-depth", n, parse_toc_depth)?;
+        }
+        set(&mut self.toc_heuristic, file.toc_heuristic);
+        set(&mut self.code_line_numbers, file.code_line_numbers);
+        set(&mut self.render_mermaid, file.mermaid);
+        set(&mut self.mermaid_fallback, file.mermaid_fallback);
+        if let Some(tools) = file.enable {
+            self.diagram_tools = tools.iter().map(|tool| checked("enable", tool, parse_diagram_tool)).collect::<Result<_, _>>()?;
+        }
+        set(&mut self.image_captions, file.image_captions);
+        set(&mut self.show_meta, file.show_meta);
+        if let Some(url) = file.repo_url {
+            self.repo_url = Some(checked("repo-url", url, parse_repo_url)?);
+        }
+        set(&mut self.focus_mode, file.focus);
+        set(&mut self.sortable_tables, file.sortable_tables);
+        set(&mut self.progress_bar, file.progress_bar);
+        if let Some(behavior) = file.scroll_behavior {
+            self.scroll_behavior = behavior;
+        }
+        if let Some(protocol) = file.image_protocol {
+            self.image_protocol = protocol;
+        }
+        set(&mut self.follow, file.follow);
+        if let Some(ms) = file.tui_poll {
+            self.poll_interval = Duration::from_millis(checked("tui-poll", ms, parse_tui_poll)?);
+        }
+        set(&mut self.compact, file.compact);
+        set(&mut self.tui_smooth_scroll, file.tui_smooth_scroll);
+        if let Some(editor) = file.editor {
+            self.editor = Some(editor);
+        }
+        if let Some(ms) = file.reload_debounce {
+            self.reload_debounce = Duration::from_millis(checked("reload-debounce", ms, parse_reload_debounce)?);
+        }
+        Ok(())
+    }
+}
+
+/// Color theme (`--theme`). `auto` is light or dark to match the terminal in the
+/// TUI, and the default palette elsewhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Auto,
+    Default,
+    Light,
+    Monochrome,
+    Solarized,
+    HighContrast,
+}
+
+/// Terminal image protocol of the TUI (`--image-protocol`): detected, forced, or
+/// `none` for no images at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageProtocol {
+    #[default]
+    Auto,
+    Kitty,
+    Iterm2,
+    Sixel,
+    Halfblocks,
+    None,
+}
+
+/// How the webview scrolls to headings and search matches (`--scroll-behavior`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollBehavior {
+    #[default]
+    Smooth,
+    Instant,
+}
+
+/// Text attributes the TUI leaves out (`--no-bold`, `--no-italic`, `--no-underline`),
+/// for terminals that draw them badly; some show italic as reverse video.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisabledAttributes {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+pub fn parse_tab_width(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if (1..=16).contains(&n) => Ok(n),
+        _ => Err(format!("invalid tab width '{}', expected a number from 1 to 16", s)),
+    }
+}
+
+pub fn parse_toc_depth(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if (1..=6).contains(&n) => Ok(n),
+        _ => Err(format!("invalid TOC depth '{}', expected a heading level from 1 to 6", s)),
+    }
+}
+
+pub fn parse_reload_debounce(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(n) if (10..=5000).contains(&n) => Ok(n),
+        _ => Err(format!("invalid reload debounce '{}', expected milliseconds from 10 to 5000", s)),
+    }
+}
+
+pub fn parse_repo_url(s: &str) -> Result<String, String> {
+    if s.starts_with("https://") || s.starts_with("http://") {
+        Ok(s.trim_end_matches('/').to_string())
+    } else {
+        Err(format!("invalid repository URL '{}', expected http:// or https://", s))
+    }
+}
+
+pub fn parse_tui_poll(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(n) if (10..=1000).contains(&n) => Ok(n),
+        _ => Err(format!("invalid TUI poll interval '{}', expected milliseconds from 10 to 1000", s)),
+    }
+}
+
+pub fn parse_diagram_tool(s: &str) -> Result<DiagramTool, String> {
+    DiagramTool::from_name(s).ok_or_else(|| format!("unknown diagram tool '{}', expected 'dot' or 'plantuml'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_come_from_a_sample_config() {
+        let config = r#"
+# Reading docs on a light terminal
+theme = "light"
+tab-width = 2
+toc-depth = 3   # only the top levels
+mermaid = false
+italic = false
+enable = ["dot", "plantuml"]
+repo-url = "https://github.com/o/r/"
+scroll-behavior = "instant"
+tui-poll = 50
+reload-debounce = 20
+editor = "sh -c \"vi +$LINE\""
+follow = true
+"#;
+        let mut settings = RenderSettings::default();
+        settings.apply_config(config).unwrap();
+        assert_eq!(settings.theme, Theme::Light);
+        assert_eq!((settings.tab_width, settings.toc_depth), (2, 3));
+        assert!(!settings.render_mermaid && settings.follow);
+        assert_eq!(settings.scroll_behavior, ScrollBehavior::Instant);
+        assert_eq!(settings.disabled_attributes, DisabledAttributes { italic: true, ..DisabledAttributes::default() });
+        assert_eq!(settings.diagram_tools, [DiagramTool::Dot, DiagramTool::PlantUml]);
+        assert_eq!(settings.repo_url.as_deref(), Some("https://github.com/o/r"));
+        assert_eq!((settings.poll_interval, settings.reload_debounce), (Duration::from_millis(50), Duration::from_millis(20)));
+        assert_eq!(settings.editor.as_deref(), Some(r#"sh -c "vi +$LINE""#));
+        // What the file leaves out keeps its default
+        assert_eq!(settings.image_protocol, ImageProtocol::Auto);
+        assert!(settings.image_captions && settings.progress_bar);
+    }
+
+    #[test]
+    fn bad_settings_are_refused() {
+        let apply = |text: &str| RenderSettings::default().apply_config(text);
+        assert_eq!(apply("# none\n"), Ok(()));
+        // Not TOML: strings are quoted
+        assert!(apply("theme = light\n").unwrap_err().contains("line 1"));
+        let unknown = apply("theme = \"light\"\n\ncolour = false\n").unwrap_err();
+        assert!(unknown.contains("line 3") && unknown.contains("unknown field `colour`"), "{}", unknown);
+        assert!(apply("follow = \"yes\"\n").unwrap_err().contains("expected a boolean"));
+        assert!(apply("image-protocol = \"ascii\"\n").unwrap_err().contains("unknown variant `ascii`"));
+        assert!(apply("enable = \"dot\"\n").unwrap_err().contains("expected a sequence"));
+        assert_eq!(apply("tab-width = 40\n"), Err("tab-width: invalid tab width '40', expected a number from 1 to 16".to_string()));
+        assert_eq!(apply("enable = [\"mmdc\"]\n"), Err("enable: unknown diagram tool 'mmdc', expected 'dot' or 'plantuml'".to_string()));
+    }
+}
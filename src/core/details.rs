@@ -0,0 +1,86 @@
+/// If `line` opens a `<details>` block, return whether it starts expanded (`<details open>`).
+pub fn parse_details_open(line: &str) -> Option<bool> {
+    let trimmed = line.trim_start();
+    let rest = trimmed.strip_prefix("<details")?;
+    let end = rest.find('>')?;
+    let attrs = &rest[..end];
+    if !attrs.is_empty() && !attrs.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(attrs.split_whitespace().any(|attr| attr == "open" || attr.starts_with("open=")))
+}
+
+/// Extract the plain summary text from a line containing `<summary>...</summary>`.
+pub fn parse_summary(line: &str) -> Option<String> {
+    let start = line.find("<summary")?;
+    let after_open = start + line[start..].find('>')? + 1;
+    let close = after_open + line[after_open..].find("</summary>")?;
+    Some(strip_tags(&line[after_open..close]).trim().to_string())
+}
+
+/// What follows `</summary>` on a summary line, like `body` in the one-line
+/// `<details><summary>S</summary>body</details>`, and whether a `</details>` at
+/// its end closes the block there.
+pub fn summary_line_rest(line: &str) -> (&str, bool) {
+    let rest = line.find("</summary>").map_or("", |i| &line[i + "</summary>".len()..]).trim();
+    match rest.strip_suffix("</details>") {
+        Some(body) => (body.trim(), true),
+        None => (rest, false),
+    }
+}
+
+/// Whether `line` closes a `<details>` block.
+pub fn is_details_close(line: &str) -> bool {
+    line.trim() == "</details>"
+}
+
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn details_open_tag_variants() {
+        assert_eq!(parse_details_open("<details>"), Some(false));
+        assert_eq!(parse_details_open("  <details open>"), Some(true));
+        assert_eq!(parse_details_open("<details class=\"faq\" open>"), Some(true));
+        assert_eq!(parse_details_open("<details><summary>Hi</summary>"), Some(false));
+        assert_eq!(parse_details_open("<detailsx>"), None);
+        assert_eq!(parse_details_open("text <details>"), None);
+    }
+
+    #[test]
+    fn summary_text_is_plain() {
+        assert_eq!(parse_summary("<summary>Click me</summary>").as_deref(), Some("Click me"));
+        assert_eq!(parse_summary("<details><summary><b>Bold</b> FAQ</summary>").as_deref(), Some("Bold FAQ"));
+        assert_eq!(parse_summary("<summary>unterminated"), None);
+    }
+
+    #[test]
+    fn text_after_the_summary() {
+        assert_eq!(summary_line_rest("<details><summary>S</summary>body text</details>"), ("body text", true));
+        assert_eq!(summary_line_rest("<summary>S</summary> body"), ("body", false));
+        assert_eq!(summary_line_rest("<details><summary>S</summary></details>"), ("", true));
+        assert_eq!(summary_line_rest("<summary>S</summary>"), ("", false));
+    }
+
+    #[test]
+    fn details_close_tag() {
+        assert!(is_details_close("</details>"));
+        assert!(is_details_close("  </details>  "));
+        assert!(!is_details_close("</details> trailing"));
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use comrak::nodes::NodeValue;
+use comrak::{parse_document, Arena, Options};
+
+use crate::core::markdown::split_info_string;
+use crate::core::mermaid::{render_block, MermaidError};
+use crate::core::toc::{active_entry, extract_headings, TocEntry};
+
+/// One mermaid block handled by `--export-diagrams`.
+#[derive(Debug)]
+pub struct DiagramExport {
+    /// 1-based source line of the block.
+    pub line: usize,
+    /// File name without extension: the slug of the heading above the block, or
+    /// `diagram-N` before the first heading.
+    pub name: String,
+    /// Files written for it; empty if it failed.
+    pub files: Vec<PathBuf>,
+    pub error: Option<ExportError>,
+}
+
+/// Why a diagram was not written.
+#[derive(Debug)]
+pub enum ExportError {
+    Render(MermaidError),
+    Write(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Render(error) => write!(f, "not rendered: {}", error),
+            ExportError::Write(message) => write!(f, "not written: {}", message),
+        }
+    }
+}
+
+/// Render every mermaid block in `content` and write it to `out_dir` as
+/// `<name>.svg`, plus `<name>.png` with `png`. `@file` blocks are read from
+/// `base_dir`. A diagram that fails is reported in its [`DiagramExport`] and the
+/// rest are still written; only failing to create `out_dir` is an error.
+pub fn export_diagrams(content: &str, base_dir: &Path, out_dir: &Path, png: bool) -> std::io::Result<Vec<DiagramExport>> {
+    std::fs::create_dir_all(out_dir)?;
+    let toc = extract_headings(content);
+    let mut names = Vec::new();
+    let mut exports = Vec::new();
+    for (index, (line, source)) in mermaid_blocks(content).into_iter().enumerate() {
+        let name = unique_name(&mut names, diagram_name(&toc, line, index + 1));
+        let mut export = DiagramExport { line, name, files: Vec::new(), error: None };
+        match render_block(&source, Some(base_dir)).1 {
+            Ok(svg) => {
+                if let Err(e) = write_files(&svg, &out_dir.join(&export.name), png, &mut export.files) {
+                    export.error = Some(ExportError::Write(e.to_string()));
+                }
+            }
+            Err(error) => export.error = Some(ExportError::Render(error)),
+        }
+        exports.push(export);
+    }
+    Ok(exports)
+}
+
+/// Source line and content of each ```mermaid block, in document order.
+fn mermaid_blocks(content: &str) -> Vec<(usize, String)> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, content, &Options::default());
+    root.descendants()
+        .filter_map(|node| match &node.data.borrow().value {
+            NodeValue::CodeBlock(block) if block.fenced && split_info_string(&block.info).0 == "mermaid" => {
+                Some((node.data.borrow().sourcepos.start.line, block.literal.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Name for the `number`th diagram, at source `line`: the slug of the heading it
+/// sits under, when there is one.
+fn diagram_name(toc: &[TocEntry], line: usize, number: usize) -> String {
+    active_entry(toc, line)
+        .map(|index| toc[index].anchor.clone())
+        .filter(|anchor| !anchor.is_empty())
+        .unwrap_or_else(|| format!("diagram-{}", number))
+}
+
+/// `name`, or `name-2`, `name-3`… if it was taken, recorded in `taken`.
+fn unique_name(taken: &mut Vec<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut n = 2;
+    while taken.contains(&unique) {
+        unique = format!("{}-{}", name, n);
+        n += 1;
+    }
+    taken.push(unique.clone());
+    unique
+}
+
+/// Write `svg` to `stem.svg`, and rasterized to `stem.png` with `png`, recording each file written.
+fn write_files(svg: &str, stem: &Path, png: bool, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let svg_path = stem.with_extension("svg");
+    std::fs::write(&svg_path, svg)?;
+    files.push(svg_path);
+    if png {
+        let png_path = stem.with_extension("png");
+        std::fs::write(&png_path, render_png(svg)?)?;
+        files.push(png_path);
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+fn render_png(svg: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // Twice the SVG's size, for sharp output on high-DPI screens
+    let (pixmap, _) = crate::core::mermaid::rasterize_svg(svg, 2.0)?;
+    Ok(pixmap.encode_png()?)
+}
+
+#[cfg(not(any(feature = "egui-backend", feature = "tui-backend")))]
+fn render_png(_svg: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Err("PNG output needs the egui or tui backend".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_each_diagram_named_after_its_heading() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_export_diagrams_{}", std::process::id()));
+        let md = "```mermaid\ngraph LR\n  A-->B\n```\n\n# Request Flow\n\n~~~mermaid\nsequenceDiagram\n  A->>B: hi\n~~~\n\n\
+                  ```mermaid\nnot a diagram\n```\n\n```rust\nfn main() {}\n```\n";
+
+        let exports = export_diagrams(md, Path::new("."), &dir, true).unwrap();
+        let names: Vec<&str> = exports.iter().map(|export| export.name.as_str()).collect();
+        assert_eq!(names, ["diagram-1", "request-flow", "request-flow-2"]);
+        assert_eq!(exports.iter().map(|export| export.line).collect::<Vec<_>>(), [1, 8, 13]);
+
+        for export in &exports[..2] {
+            assert!(export.error.is_none(), "{:?}", export.error);
+            assert_eq!(export.files, [dir.join(format!("{}.svg", export.name)), dir.join(format!("{}.png", export.name))]);
+            assert!(std::fs::read_to_string(&export.files[0]).unwrap().contains("<svg"));
+            assert!(std::fs::read(&export.files[1]).unwrap().starts_with(b"\x89PNG"));
+        }
+        assert!(matches!(exports[2].error, Some(ExportError::Render(_))));
+        assert!(exports[2].files.is_empty());
+        assert!(!dir.join("request-flow-2.svg").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
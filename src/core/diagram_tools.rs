@@ -0,0 +1,298 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::core::svg_cache::SvgCache;
+
+/// A diagram language drawn by an external program, opted into with `--enable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramTool {
+    /// Graphviz: ```dot or ```graphviz blocks, drawn by `dot`.
+    Dot,
+    /// ```plantuml or ```puml blocks, drawn by `plantuml`.
+    PlantUml,
+}
+
+impl DiagramTool {
+    /// The tool called `name` on the command line: `dot` (or `graphviz`) or `plantuml`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dot" | "graphviz" => Some(DiagramTool::Dot),
+            "plantuml" => Some(DiagramTool::PlantUml),
+            _ => None,
+        }
+    }
+
+    /// The tool that draws code blocks in `lang`, if it is one of `enabled`.
+    pub fn for_lang(lang: &str, enabled: &[DiagramTool]) -> Option<Self> {
+        let tool = match lang {
+            "dot" | "graphviz" => DiagramTool::Dot,
+            "plantuml" | "puml" => DiagramTool::PlantUml,
+            _ => return None,
+        };
+        enabled.contains(&tool).then_some(tool)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagramTool::Dot => "dot",
+            DiagramTool::PlantUml => "plantuml",
+        }
+    }
+
+    /// The program and the arguments that make it read a diagram on stdin and write SVG.
+    fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            DiagramTool::Dot => ("dot", &["-Tsvg"]),
+            DiagramTool::PlantUml => ("plantuml", &["-tsvg", "-pipe"]),
+        }
+    }
+
+    fn install_hint(self) -> &'static str {
+        match self {
+            DiagramTool::Dot => "install Graphviz",
+            DiagramTool::PlantUml => "install PlantUML",
+        }
+    }
+}
+
+/// How long a tool may take to draw one diagram before it is stopped, so one that
+/// hangs can't freeze the viewer.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Draw `source` as SVG with the tool's program. Every run starts a process, so
+/// results are cached by diagram source. A diagram the tool rejected or gave up on
+/// fails the same way until it changes, so only a tool that couldn't be started is
+/// tried again.
+pub fn render_with_tool(tool: DiagramTool, source: &str) -> Result<String, String> {
+    static CACHE: SvgCache<Result<String, String>> = SvgCache::new();
+    let (program, args) = tool.command();
+    // The tool's name keeps a dot and a plantuml block with the same text apart
+    CACHE
+        .get_or_insert_with(&format!("{}\n{}", tool.name(), source), 1.0, || {
+            spawn_tool(tool, program, args).map(|child| collect_output(child, program, source, TOOL_TIMEOUT))
+        })
+        .and_then(|result| result)
+}
+
+/// Run `program` (the tool's, or a stand-in) with `source` on stdin and return its output.
+#[cfg(test)]
+fn run_tool(tool: DiagramTool, program: &str, args: &[&str], source: &str, timeout: Duration) -> Result<String, String> {
+    spawn_tool(tool, program, args).and_then(|child| collect_output(child, program, source, timeout))
+}
+
+fn spawn_tool(tool: DiagramTool, program: &str, args: &[&str]) -> Result<Child, String> {
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    match child {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(format!("{} not found; {} to draw {} blocks", program, tool.install_hint(), tool.name()))
+        }
+        Err(e) => Err(e.to_string()),
+        Ok(child) => Ok(child),
+    }
+}
+
+/// Feed `source` to the running tool and return the SVG it writes, stopping it
+/// after `timeout`.
+fn collect_output(mut child: Child, program: &str, source: &str, timeout: Duration) -> Result<String, String> {
+    // Fed and drained from other threads, so a large diagram can't fill a pipe and
+    // a tool that hangs can still be stopped
+    let mut stdin = child.stdin.take().ok_or("no stdin")?;
+    let input = source.to_string();
+    std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} took longer than {:?} and was stopped", program, timeout));
+            }
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        return Err(format!("{} exited with {}: {}", program, status, String::from_utf8_lossy(&stderr).trim()));
+    }
+    let svg = String::from_utf8(stdout).map_err(|e| e.to_string())?;
+    if svg.contains("<svg") {
+        Ok(svg)
+    } else {
+        Err(format!("{} wrote no SVG", program))
+    }
+}
+
+/// Read all of `pipe` on another thread.
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+/// Replace the rendered code blocks of `enabled` tools in comrak's HTML with their
+/// diagrams, as SVG images (so nothing in them runs in the page). A block whose
+/// tool is missing, fails or takes too long stays a code block, headed by the error.
+pub fn process_tool_blocks(html: &str, enabled: &[DiagramTool]) -> String {
+    process_tool_blocks_with(html, enabled, render_with_tool)
+}
+
+fn process_tool_blocks_with(
+    html: &str,
+    enabled: &[DiagramTool],
+    render: impl Fn(DiagramTool, &str) -> Result<String, String>,
+) -> String {
+    if enabled.is_empty() {
+        return html.to_string();
+    }
+    use base64::Engine;
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r#"<pre( data-sourcepos="[^"]*")?><code[^>]*\bclass="language-([\w-]+)"[^>]*>([\s\S]*?)</code></pre>"#).unwrap()
+    });
+
+    re.replace_all(html, |caps: &regex::Captures| {
+        let Some(tool) = DiagramTool::for_lang(&caps[2], enabled) else {
+            return caps[0].to_string();
+        };
+        let source = crate::core::entities::decode_entities(&caps[3]);
+        match render(tool, &source) {
+            Ok(svg) => format!(
+                r#"<div class="mermaid-diagram"{}><img alt="{} diagram" src="data:image/svg+xml;base64,{}"></div>"#,
+                caps.get(1).map_or("", |m| m.as_str()),
+                tool.name(),
+                base64::engine::general_purpose::STANDARD.encode(svg)
+            ),
+            // The block stays code, under a header that says why, as mermaid's do
+            Err(e) => {
+                crate::vlog!("{}: diagram left as code: {}", tool.name(), e);
+                format!(
+                    r#"<div class="mermaid-fallback"><div class="mermaid-fallback-header"><span class="mermaid-icon">◇</span>{} diagram: {}</div>{}</div>"#,
+                    tool.name(),
+                    html_escape(&e),
+                    &caps[0]
+                )
+            }
+        }
+    })
+    .to_string()
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Pre-process markdown for egui: replace the code blocks of `enabled` tools with
+/// their diagrams as PNG data URIs, rasterized like mermaid's. A block whose tool
+/// is missing, fails or takes too long stays a code block, under the error.
+#[cfg(feature = "egui-backend")]
+pub fn preprocess_tool_blocks_for_egui(markdown: &str, enabled: &[DiagramTool]) -> String {
+    if enabled.is_empty() {
+        return markdown.to_string();
+    }
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"```([\w-]+)\n([\s\S]*?)```").unwrap());
+
+    re.replace_all(markdown, |caps: &regex::Captures| {
+        let Some(tool) = DiagramTool::for_lang(&caps[1], enabled) else {
+            return caps[0].to_string();
+        };
+        let image = render_with_tool(tool, &caps[2])
+            .and_then(|svg| crate::core::mermaid::svg_to_png_base64(&svg).map(|b64| (svg, b64)).map_err(|e| e.to_string()));
+        match image {
+            Ok((svg, b64)) => {
+                let data_uri = format!("data:image/png;base64,{}", b64);
+                crate::core::svg_cache::SVG_SOURCES.insert(&data_uri, &svg);
+                format!("![{} diagram]({})", tool.name(), data_uri)
+            }
+            Err(e) => {
+                crate::vlog!("{}: diagram left as code: {}", tool.name(), e);
+                format!("> **◇ {} diagram** *(not rendered: {})*\n\n{}", tool.name(), e, &caps[0])
+            }
+        }
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tools_are_named_on_the_command_line_and_by_block_language() {
+        assert_eq!(DiagramTool::from_name("graphviz"), Some(DiagramTool::Dot));
+        assert_eq!(DiagramTool::from_name("plantuml"), Some(DiagramTool::PlantUml));
+        assert_eq!(DiagramTool::from_name("mermaid"), None);
+        let both = [DiagramTool::Dot, DiagramTool::PlantUml];
+        assert_eq!(DiagramTool::for_lang("puml", &both), Some(DiagramTool::PlantUml));
+        assert_eq!(DiagramTool::for_lang("dot", &both[1..]), None);
+        assert_eq!(DiagramTool::for_lang("rust", &both), None);
+    }
+
+    #[test]
+    fn dot_block_stays_code_when_dot_is_missing() {
+        let html = "<p>Before</p>\n<pre data-sourcepos=\"3:1-5:3\"><code class=\"language-dot\">digraph { a -&gt; b }\n</code></pre>\n";
+        let missing = |tool: DiagramTool, source: &str| run_tool(tool, "mdr-no-such-dot", &["-Tsvg"], source, TOOL_TIMEOUT);
+        let result = process_tool_blocks_with(html, &[DiagramTool::Dot], missing);
+        let block = &html["<p>Before</p>\n".len()..html.len() - 1];
+        assert!(result.contains(&format!("dot diagram: mdr-no-such-dot not found; install Graphviz to draw dot blocks</div>{}</div>", block)), "{}", result);
+
+        let error = run_tool(DiagramTool::Dot, "mdr-no-such-dot", &[], "digraph {}", TOOL_TIMEOUT).unwrap_err();
+        assert!(error.contains("install Graphviz"), "{}", error);
+    }
+
+    #[test]
+    fn rendered_block_becomes_an_svg_image() {
+        let html = "<pre data-sourcepos=\"1:1-3:3\"><code class=\"language-graphviz\">digraph { a -&gt; b }\n</code></pre>\n\
+                    <pre><code class=\"language-plantuml\">@startuml\n</code></pre>\n";
+        let fake = |_: DiagramTool, source: &str| Ok(format!("<svg><text>{}</text></svg>", source.trim()));
+        let result = process_tool_blocks_with(html, &[DiagramTool::Dot], fake);
+        assert!(result.starts_with(r#"<div class="mermaid-diagram" data-sourcepos="1:1-3:3"><img alt="dot diagram" src="data:image/svg+xml;base64,"#), "{}", result);
+        // plantuml isn't enabled, so its block is left alone
+        assert!(result.contains("<code class=\"language-plantuml\">@startuml"));
+        assert_eq!(process_tool_blocks_with(html, &[], fake), html);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tool_reads_the_diagram_on_stdin() {
+        let svg = run_tool(DiagramTool::Dot, "sh", &["-c", "printf '<svg>'; cat; printf '</svg>'"], "digraph {}", TOOL_TIMEOUT).unwrap();
+        assert_eq!(svg, "<svg>digraph {}</svg>");
+        let error = run_tool(DiagramTool::Dot, "sh", &["-c", "echo 'syntax error' >&2; exit 1"], "x", TOOL_TIMEOUT).unwrap_err();
+        assert!(error.contains("syntax error"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tool_that_hangs_is_stopped() {
+        let started = Instant::now();
+        let hang = |tool: DiagramTool, source: &str| run_tool(tool, "sh", &["-c", "sleep 5"], source, Duration::from_millis(100));
+        let error = hang(DiagramTool::PlantUml, "@startuml\n@enduml").unwrap_err();
+        assert!(error.contains("took longer than 100ms"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        // The block falls back to code, headed by the error
+        let html = "<pre><code class=\"language-plantuml\">@startuml\n@enduml\n</code></pre>";
+        let result = process_tool_blocks_with(html, &[DiagramTool::PlantUml], hang);
+        assert!(result.contains("plantuml diagram: sh took longer than 100ms and was stopped</div><pre><code"), "{}", result);
+    }
+}
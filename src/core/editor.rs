@@ -0,0 +1,74 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+/// The editor to open files in: `configured` (`--editor`), else `$VISUAL`, else `$EDITOR`.
+pub fn editor(configured: Option<&str>) -> Option<String> {
+    configured
+        .map(str::to_string)
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+}
+
+/// The command that opens `file` in the user's [`editor`] at 1-based `line`.
+/// The editor setting may carry arguments of its own, such as `code --wait`.
+pub fn command(configured: Option<&str>, file: &Path, line: usize) -> Result<Command, Box<dyn std::error::Error>> {
+    let editor = editor(configured).ok_or("no editor: set $VISUAL or $EDITOR, or pass --editor")?;
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("no editor")?;
+    let mut command = Command::new(program);
+    command.args(words).args(line_args(program, file, line));
+    crate::vlog!("editor: {:?}", command);
+    Ok(command)
+}
+
+/// Start the editor without waiting for it, as the GUI backends do.
+pub fn spawn(configured: Option<&str>, file: &Path, line: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = command(configured, file, line)?.spawn()?;
+    // Reap the editor when it exits so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Arguments that open `file` at `line` in `program`, in the line-jump syntax of
+/// the editors that have one. Other editors just get the file.
+fn line_args(program: &str, file: &Path, line: usize) -> Vec<OsString> {
+    let name = Path::new(program).file_stem().and_then(|name| name.to_str()).unwrap_or(program).to_lowercase();
+    let at_line = || {
+        let mut arg = file.as_os_str().to_owned();
+        arg.push(format!(":{}", line));
+        arg
+    };
+    match name.as_str() {
+        "vi" | "vim" | "nvim" | "gvim" | "mvim" | "view" | "nano" | "pico" | "emacs" | "emacsclient" | "micro"
+        | "kak" | "joe" | "mg" | "ne" | "gedit" | "xed" | "mousepad" => {
+            vec![format!("+{}", line).into(), file.into()]
+        }
+        "code" | "code-insiders" | "codium" | "vscodium" | "cursor" | "windsurf" => vec!["--goto".into(), at_line()],
+        "subl" | "sublime_text" | "zed" | "hx" | "helix" => vec![at_line()],
+        "kate" | "kwrite" | "idea" | "pycharm" | "webstorm" | "clion" | "goland" | "rustrover" => {
+            vec!["--line".into(), line.to_string().into(), file.into()]
+        }
+        _ => vec![file.into()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(program: &str) -> Vec<String> {
+        line_args(program, Path::new("docs/guide.md"), 42).iter().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn editors_get_their_own_line_syntax() {
+        assert_eq!(args("vim"), ["+42", "docs/guide.md"]);
+        assert_eq!(args("/usr/bin/nvim"), ["+42", "docs/guide.md"]);
+        assert_eq!(args("code"), ["--goto", "docs/guide.md:42"]);
+        assert_eq!(args("hx"), ["docs/guide.md:42"]);
+        assert_eq!(args("kate"), ["--line", "42", "docs/guide.md"]);
+        assert_eq!(args("ed"), ["docs/guide.md"]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Named character references recognized by [`decode_entities`].
+/// Covers the HTML markup characters plus the typography, currency, arrow and
+/// math symbols that commonly show up in hand-written markdown.
+const NAMED_ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+    ("nbsp", "\u{a0}"),
+    ("shy", "\u{ad}"),
+    ("copy", "©"),
+    ("reg", "®"),
+    ("trade", "™"),
+    ("hellip", "…"),
+    ("mdash", "—"),
+    ("ndash", "–"),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("sbquo", "‚"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("bdquo", "„"),
+    ("laquo", "«"),
+    ("raquo", "»"),
+    ("lsaquo", "‹"),
+    ("rsaquo", "›"),
+    ("bull", "•"),
+    ("middot", "·"),
+    ("dagger", "†"),
+    ("Dagger", "‡"),
+    ("para", "¶"),
+    ("sect", "§"),
+    ("deg", "°"),
+    ("prime", "′"),
+    ("Prime", "″"),
+    ("iexcl", "¡"),
+    ("iquest", "¿"),
+    ("cent", "¢"),
+    ("pound", "£"),
+    ("yen", "¥"),
+    ("euro", "€"),
+    ("curren", "¤"),
+    ("plusmn", "±"),
+    ("times", "×"),
+    ("divide", "÷"),
+    ("minus", "−"),
+    ("frac12", "½"),
+    ("frac14", "¼"),
+    ("frac34", "¾"),
+    ("sup1", "¹"),
+    ("sup2", "²"),
+    ("sup3", "³"),
+    ("micro", "µ"),
+    ("le", "≤"),
+    ("ge", "≥"),
+    ("ne", "≠"),
+    ("asymp", "≈"),
+    ("equiv", "≡"),
+    ("infin", "∞"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("radic", "√"),
+    ("larr", "←"),
+    ("rarr", "→"),
+    ("uarr", "↑"),
+    ("darr", "↓"),
+    ("harr", "↔"),
+    ("lArr", "⇐"),
+    ("rArr", "⇒"),
+    ("hArr", "⇔"),
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("pi", "π"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("phi", "φ"),
+    ("omega", "ω"),
+    ("Delta", "Δ"),
+    ("Sigma", "Σ"),
+    ("Omega", "Ω"),
+    ("hearts", "♥"),
+    ("check", "✓"),
+];
+
+/// Decode HTML character references: named (`&copy;`), decimal (`&#169;`) and
+/// hex (`&#xA9;`). Unknown names are left as written; numeric references to
+/// invalid code points become U+FFFD, as in CommonMark.
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"&(?:#([0-9]{1,7})|#[xX]([0-9a-fA-F]{1,6})|([A-Za-z][A-Za-z0-9]{1,31}));").unwrap());
+
+    re.replace_all(s, |caps: &regex::Captures| {
+        let code = if let Some(dec) = caps.get(1) {
+            dec.as_str().parse::<u32>().ok()
+        } else if let Some(hex) = caps.get(2) {
+            u32::from_str_radix(hex.as_str(), 16).ok()
+        } else {
+            let name = &caps[3];
+            return NAMED_ENTITIES
+                .iter()
+                .find(|(entity, _)| *entity == name)
+                .map_or_else(|| caps[0].to_string(), |(_, text)| text.to_string());
+        };
+        let c = code.filter(|&c| c != 0).and_then(char::from_u32).unwrap_or('\u{fffd}');
+        c.to_string()
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_entities() {
+        assert_eq!(decode_entities("Tom &amp; Jerry &copy; 2024"), "Tom & Jerry © 2024");
+        assert_eq!(decode_entities("a &lt;b&gt; &mdash; &hellip;"), "a <b> — …");
+    }
+
+    #[test]
+    fn decimal_references() {
+        assert_eq!(decode_entities("&#169; &#38; &#128512;"), "© & 😀");
+    }
+
+    #[test]
+    fn hex_references() {
+        assert_eq!(decode_entities("&#xA9; &#X26; &#x1F600;"), "© & 😀");
+    }
+
+    #[test]
+    fn unknown_and_invalid_references() {
+        assert_eq!(decode_entities("&bogus; & &amp"), "&bogus; & &amp");
+        assert_eq!(decode_entities("&#0; &#xD800; &#x110000;"), "\u{fffd} \u{fffd} \u{fffd}");
+    }
+
+    #[test]
+    fn decodes_in_a_single_pass() {
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+    }
+}
@@ -0,0 +1,153 @@
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+use std::sync::OnceLock;
+
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+use syntect::easy::HighlightLines;
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+use syntect::highlighting::{Style, Theme, ThemeSet};
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+use syntect::util::LinesWithEndings;
+
+/// Syntect theme used when the requested one isn't bundled.
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+const FALLBACK_THEME: &str = "base16-ocean.dark";
+
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+fn syntax_set() -> &'static SyntaxSet {
+    static SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+fn theme(name: &str) -> &'static Theme {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    let themes = &THEMES.get_or_init(ThemeSet::load_defaults).themes;
+    themes.get(name).unwrap_or(&themes[FALLBACK_THEME])
+}
+
+#[cfg(any(feature = "tui-backend", feature = "webview-backend"))]
+fn markdown_syntax(set: &SyntaxSet) -> &SyntaxReference {
+    set.find_syntax_by_extension("md")
+        .unwrap_or_else(|| set.find_syntax_plain_text())
+}
+
+/// Highlight markdown source line by line with the syntect theme `theme_name`.
+/// Each line is a list of styled fragments with the trailing newline stripped.
+#[cfg(feature = "tui-backend")]
+pub fn highlight_markdown_lines(source: &str, theme_name: &str) -> Vec<Vec<(Style, String)>> {
+    let set = syntax_set();
+    let mut highlighter = HighlightLines::new(markdown_syntax(set), theme(theme_name));
+    LinesWithEndings::from(source)
+        .map(|line| match highlighter.highlight_line(line, set) {
+            Ok(ranges) => ranges
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\n', '\r']).to_string()))
+                .filter(|(_, text)| !text.is_empty())
+                .collect(),
+            Err(_) => vec![(Style::default(), line.trim_end_matches(['\n', '\r']).to_string())],
+        })
+        .collect()
+}
+
+/// Highlight markdown source as a standalone `<pre>` block with inline styles.
+/// Without a syntect theme the source is escaped into a plain `<pre>`.
+#[cfg(feature = "webview-backend")]
+pub fn highlight_markdown_html(source: &str, theme_name: Option<&str>) -> String {
+    let plain = || format!("<pre>{}</pre>", escape_html(source));
+    let Some(theme_name) = theme_name else {
+        return plain();
+    };
+    let set = syntax_set();
+    syntect::html::highlighted_html_for_string(source, set, markdown_syntax(set), theme(theme_name))
+        .unwrap_or_else(|_| plain())
+}
+
+/// Wrap markdown source in a fenced `markdown` code block.
+/// The fence is longer than any backtick run in the source so it can't be closed early.
+#[cfg(feature = "egui-backend")]
+pub fn fence_as_markdown_code(source: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in source.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}markdown\n{}\n{fence}\n", source.trim_end_matches('\n'))
+}
+
+#[cfg(feature = "webview-backend")]
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "tui-backend")]
+    #[test]
+    fn lines_preserve_text() {
+        let src = "# Title\n\nSome *emphasis* here.\n";
+        let lines = highlight_markdown_lines(src, "base16-ocean.dark");
+        assert_eq!(lines.len(), 3);
+        let first: String = lines[0].iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(first, "# Title");
+        assert!(lines[1].is_empty());
+        let third: String = lines[2].iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(third, "Some *emphasis* here.");
+    }
+
+    #[cfg(feature = "tui-backend")]
+    #[test]
+    fn heading_is_styled_differently_from_text() {
+        let lines = highlight_markdown_lines("# Title\nplain\n", "base16-ocean.dark");
+        let heading_fg = lines[0][0].0.foreground;
+        let text_fg = lines[1][0].0.foreground;
+        assert_ne!(heading_fg, text_fg);
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn html_is_escaped_pre() {
+        let html = highlight_markdown_html("<script>alert(1)</script>\n", Some("InspiredGitHub"));
+        assert!(html.starts_with("<pre"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn html_without_a_theme_has_no_colors() {
+        let html = highlight_markdown_html("# <Title>\n", None);
+        assert_eq!(html, "<pre># &lt;Title&gt;\n</pre>");
+    }
+
+    #[cfg(feature = "tui-backend")]
+    #[test]
+    fn unknown_theme_falls_back() {
+        let lines = highlight_markdown_lines("# Title\n", "no-such-theme");
+        let first: String = lines[0].iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(first, "# Title");
+    }
+
+    #[cfg(feature = "egui-backend")]
+    #[test]
+    fn fence_outlasts_inner_backticks() {
+        let src = "text\n````rust\nfn main() {}\n````\n";
+        let fenced = fence_as_markdown_code(src);
+        assert!(fenced.starts_with("`````markdown\n"));
+        assert!(fenced.ends_with("\n`````\n"));
+    }
+
+    #[cfg(feature = "egui-backend")]
+    #[test]
+    fn fence_defaults_to_three_backticks() {
+        assert_eq!(fence_as_markdown_code("hi\n"), "```markdown\nhi\n```\n");
+    }
+}
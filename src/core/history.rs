@@ -0,0 +1,112 @@
+/// Most places a history keeps in each direction; the oldest are dropped first.
+const MAX_ENTRIES: usize = 100;
+
+/// Back/forward navigation history, like a browser's: record where you were
+/// before each jump, then step back and forth through those places.
+#[derive(Default)]
+pub struct History<T> {
+    back: Vec<T>,
+    forward: Vec<T>,
+}
+
+impl<T: PartialEq> History<T> {
+    /// Note the place being left by a jump. Clears the forward history, and a
+    /// place equal to the last one recorded is not recorded twice.
+    pub fn record(&mut self, from: T) {
+        self.forward.clear();
+        push_bounded(&mut self.back, from);
+    }
+
+    /// Step back from `current`, returning the place to go to.
+    pub fn back(&mut self, current: T) -> Option<T> {
+        step(&mut self.back, &mut self.forward, current)
+    }
+
+    /// Step forward from `current` after going back, returning the place to go to.
+    pub fn forward(&mut self, current: T) -> Option<T> {
+        step(&mut self.forward, &mut self.back, current)
+    }
+}
+
+/// Pop the nearest place in `from` other than `current`, leaving `current` in `to`.
+fn step<T: PartialEq>(from: &mut Vec<T>, to: &mut Vec<T>, current: T) -> Option<T> {
+    while let Some(target) = from.pop() {
+        if target != current {
+            push_bounded(to, current);
+            return Some(target);
+        }
+    }
+    None
+}
+
+fn push_bounded<T: PartialEq>(stack: &mut Vec<T>, place: T) {
+    if stack.last() == Some(&place) {
+        return;
+    }
+    if stack.len() == MAX_ENTRIES {
+        stack.remove(0);
+    }
+    stack.push(place);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_and_forward_retrace_jumps() {
+        let mut history = History::default();
+        history.record(0);
+        history.record(40);
+        // Now at 90
+        assert_eq!(history.back(90), Some(40));
+        assert_eq!(history.back(40), Some(0));
+        assert_eq!(history.back(0), None);
+        assert_eq!(history.forward(0), Some(40));
+        assert_eq!(history.forward(40), Some(90));
+        assert_eq!(history.forward(90), None);
+    }
+
+    #[test]
+    fn recording_clears_forward_history() {
+        let mut history = History::default();
+        history.record(0);
+        assert_eq!(history.back(10), Some(0));
+        history.record(0);
+        assert_eq!(history.forward(20), None);
+    }
+
+    #[test]
+    fn consecutive_duplicates_are_kept_once() {
+        let mut history = History::default();
+        history.record(5);
+        history.record(5);
+        assert_eq!(history.back(7), Some(5));
+        assert_eq!(history.back(5), None);
+    }
+
+    #[test]
+    fn places_equal_to_the_current_one_are_skipped() {
+        let mut history = History::default();
+        history.record(1);
+        history.record(3);
+        assert_eq!(history.back(3), Some(1));
+        assert_eq!(history.forward(1), Some(3));
+    }
+
+    #[test]
+    fn oldest_places_are_dropped() {
+        let mut history = History::default();
+        for place in 0..MAX_ENTRIES + 10 {
+            history.record(place);
+        }
+        let mut current = usize::MAX;
+        let mut steps = 0;
+        while let Some(place) = history.back(current) {
+            current = place;
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_ENTRIES);
+        assert_eq!(current, 10);
+    }
+}
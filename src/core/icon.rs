@@ -3,13 +3,25 @@ use std::io::Cursor;
 
 static ICON_PNG: &[u8] = include_bytes!("../../assets/logo-128.png");
 
-pub fn load_icon_rgba() -> (Vec<u8>, u32, u32) {
+/// Decode the bundled window icon into RGBA pixels and its width and height.
+/// A bad asset is an error rather than a panic, so the window still opens without it.
+pub fn load_icon_rgba() -> Result<(Vec<u8>, u32, u32), Box<dyn std::error::Error>> {
     let img = ImageReader::new(Cursor::new(ICON_PNG))
-        .with_guessed_format()
-        .unwrap()
-        .decode()
-        .unwrap()
+        .with_guessed_format()?
+        .decode()?
         .to_rgba8();
     let (w, h) = img.dimensions();
-    (img.into_raw(), w, h)
+    Ok((img.into_raw(), w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_icon_decodes() {
+        let (rgba, w, h) = load_icon_rgba().unwrap();
+        assert_eq!((w, h), (128, 128));
+        assert_eq!(rgba.len(), (w * h * 4) as usize);
+    }
 }
@@ -0,0 +1,83 @@
+use encoding_rs::{Encoding, UTF_8};
+use std::path::Path;
+
+/// Read a markdown file as text, whatever its encoding. A byte order mark picks
+/// UTF-8 or UTF-16 (and is dropped); other files are taken as UTF-8 when they are
+/// valid, or transcoded from the encoding they look like (Latin-1, Shift_JIS, ...).
+/// Windows `\r\n` line endings become `\n`.
+pub fn read_markdown(path: &Path) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(decode_markdown(&bytes, path))
+}
+
+/// Decode file contents as described in [`read_markdown`]. `path` is only for logging.
+pub fn decode_markdown(bytes: &[u8], path: &Path) -> String {
+    normalize_line_endings(decode(bytes, path))
+}
+
+fn decode(bytes: &[u8], path: &Path) -> String {
+    let (encoding, body) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_length)) => (encoding, &bytes[bom_length..]),
+        None => match std::str::from_utf8(bytes) {
+            Ok(text) => return text.to_string(),
+            Err(_) => {
+                let mut detector = chardetng::EncodingDetector::new();
+                detector.feed(bytes, true);
+                (detector.guess(None, true), bytes)
+            }
+        },
+    };
+    if encoding != UTF_8 {
+        crate::vlog!("{}: decoding as {}", path.display(), encoding.name());
+    }
+    let (text, had_errors) = encoding.decode_without_bom_handling(body);
+    if had_errors {
+        crate::vlog!("{}: not valid {}, replacing the bad bytes", path.display(), encoding.name());
+    }
+    text.into_owned()
+}
+
+fn normalize_line_endings(text: String) -> String {
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf16le_with_bom_is_transcoded() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("# Título\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let path = std::env::temp_dir().join(format!("mdr_test_utf16_{}.md", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(read_markdown(&path).unwrap(), "# Título\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn utf8_bom_is_dropped_and_latin1_is_transcoded() {
+        let path = Path::new("doc.md");
+        assert_eq!(decode_markdown(b"\xEF\xBB\xBF# Title\n", path), "# Title\n");
+        assert_eq!(decode_markdown("# Café\n".as_bytes(), path), "# Café\n");
+        assert_eq!(decode_markdown(b"# Caf\xE9 cr\xE8me br\xFBl\xE9e\n", path), "# Café crème brûlée\n");
+    }
+
+    #[test]
+    fn crlf_line_endings_become_lf() {
+        let path = Path::new("doc.md");
+        assert_eq!(decode_markdown(b"# Title\r\n\r\n```\r\ncode\r\n```\r\n", path), "# Title\n\n```\ncode\n```\n");
+        assert_eq!(decode_markdown(b"a\rb\n", path), "a\rb\n");
+    }
+
+    #[test]
+    fn undecodable_bytes_are_replaced_not_fatal() {
+        let text = decode_markdown(b"\xEF\xBB\xBFok \xFF\xFE end", Path::new("doc.md"));
+        assert!(text.starts_with("ok ") && text.ends_with(" end"), "{:?}", text);
+        assert!(text.contains('\u{FFFD}'));
+    }
+}
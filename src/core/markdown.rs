@@ -1,38 +1,641 @@
-use comrak::{markdown_to_html, Options};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use comrak::nodes::NodeValue;
+use comrak::{format_html, parse_document, Arena, Options};
+use regex::Regex;
+use crate::core::diagram_tools::process_tool_blocks;
 use crate::core::mermaid::process_mermaid_blocks;
+use crate::core::RenderSettings;
 
 /// Convert markdown content to HTML with all GFM extensions enabled.
 /// Processes mermaid code blocks into inline SVG diagrams.
 /// Adds id attributes to headings for TOC anchor navigation.
-pub fn parse_markdown(content: &str) -> String {
+pub fn parse_markdown(content: &str, settings: &RenderSettings) -> String {
+    render_markdown(content, settings, false, None).0
+}
+
+/// HTML from [`parse_markdown_with_sourcepos`], with the source lines of its
+/// top-level headings: those not nested in a list, quote or other container.
+#[cfg(feature = "webview-backend")]
+pub struct SourceposHtml {
+    pub html: String,
+    pub heading_lines: Vec<usize>,
+}
+
+/// Like [`parse_markdown`], but block elements carry `data-sourcepos="line:col-line:col"`
+/// attributes so the viewer can map source lines to rendered content, and mermaid
+/// `@file` blocks are read from `base_dir`.
+#[cfg(feature = "webview-backend")]
+pub fn parse_markdown_with_sourcepos(content: &str, settings: &RenderSettings, base_dir: &Path) -> SourceposHtml {
+    let (html, heading_lines) = render_markdown(content, settings, true, Some(base_dir));
+    SourceposHtml { html, heading_lines }
+}
+
+/// The HTML and the source lines of the top-level headings, from one parse.
+fn render_markdown(content: &str, settings: &RenderSettings, sourcepos: bool, base_dir: Option<&Path>) -> (String, Vec<usize>) {
     let mut options = Options::default();
     options.extension.strikethrough = true;
     options.extension.table = true;
     options.extension.autolink = true;
     options.extension.tasklist = true;
     options.extension.footnotes = true;
+    options.extension.description_lists = true;
     options.render.r#unsafe = true;
+    options.render.sourcepos = sourcepos;
+    // Keeps `{1,3-5}` line specs as `data-meta` on <code> instead of dropping them
+    options.render.full_info_string = true;
 
-    let html = markdown_to_html(content, &options);
+    let content = strip_html_comments(content);
+    let content = link_references(&content, settings.repo_url.as_deref());
+    let content = expand_code_tabs(&content, settings.tab_width);
+    let content = expand_toc_markers(&content, true, settings);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &content, &options);
+    let heading_lines = root
+        .children()
+        .filter_map(|node| {
+            let data = node.data.borrow();
+            matches!(data.value, NodeValue::Heading(_)).then_some(data.sourcepos.start.line)
+        })
+        .collect();
+    let mut html = String::new();
+    format_html(root, &options, &mut html).unwrap();
     let html = add_heading_ids(&html);
-    process_mermaid_blocks(&html)
+    let html = if settings.image_captions { wrap_image_captions(&html) } else { html };
+    let html = wrap_image_galleries(&html);
+    let html = process_mermaid_blocks(&html, settings.render_mermaid, base_dir);
+    (process_tool_blocks(&html, &settings.diagram_tools), heading_lines)
+}
+
+/// Expand tabs to spaces inside fenced code blocks, leaving the rest of the document alone.
+/// Tabs outside code are insignificant in markdown, but inside code every viewer
+/// would otherwise pick its own tab stops.
+pub fn expand_code_tabs(content: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(content.len());
+    // Opening fence of the code block we're inside: (fence char, length)
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in content.split_inclusive('\n') {
+        match fence {
+            None => {
+                fence = fence_marker(line);
+                out.push_str(line);
+            }
+            Some(open) if closes_fence(line, open) => {
+                fence = None;
+                out.push_str(line);
+            }
+            Some(_) => out.push_str(&expand_tabs(line, tab_width)),
+        }
+    }
+    out
+}
+
+/// Remove HTML comments (`<!-- ... -->`, which may span lines) so authors' notes
+/// aren't shown, leaving fenced code blocks and code spans alone. The line breaks
+/// inside a comment are kept, so source line numbers don't shift.
+pub fn strip_html_comments(content: &str) -> String {
+    if !content.contains("<!--") {
+        return content.to_string();
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut fence = None;
+    let mut in_comment = false;
+
+    for line in content.split_inclusive('\n') {
+        if !in_comment {
+            let in_fence = fence.is_some();
+            match fence {
+                None => fence = fence_marker(line),
+                Some(open) if closes_fence(line, open) => fence = None,
+                Some(_) => {}
+            }
+            if in_fence || fence.is_some() {
+                out.push_str(line);
+                continue;
+            }
+        }
+        let (mut rest, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (line, ""),
+        };
+        while !rest.is_empty() {
+            if in_comment {
+                match rest.find("-->") {
+                    Some(end) => {
+                        rest = &rest[end + 3..];
+                        in_comment = false;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            let Some(start) = rest.find(['<', '`']) else {
+                out.push_str(rest);
+                break;
+            };
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("<!--") {
+                rest = after;
+                in_comment = true;
+            } else if rest.starts_with('`') {
+                // A code span runs to the next run of as many backticks
+                let ticks = rest.len() - rest.trim_start_matches('`').len();
+                let span = rest[ticks..].find(&rest[..ticks]).map_or(ticks, |end| 2 * ticks + end);
+                out.push_str(&rest[..span]);
+                rest = &rest[span..];
+            } else {
+                out.push('<');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Link `#123`, `@user` and commit hashes to the repository given by a `repo_url`
+/// front matter key or `repo_url` (`--repo-url`). Without either, the content is unchanged.
+pub fn link_references(content: &str, repo_url: Option<&str>) -> String {
+    let front_matter = &content[..front_matter_end(content)];
+    let repo_url = front_matter
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once([':', '='])?;
+            matches!(key.trim(), "repo_url" | "repo-url").then(|| value.trim().trim_matches(['"', '\'']).trim_end_matches('/'))
+        })
+        .filter(|url| !url.is_empty())
+        .or(repo_url);
+    match repo_url {
+        Some(url) => autolink_references(content, url),
+        None => content.to_string(),
+    }
+}
+
+/// Turn `#123` into a link to `<repo_url>/issues/123`, `@user` into a link to the
+/// user's profile on the same host, and 7 to 40 character hex hashes into links to
+/// `<repo_url>/commit/<hash>`. Code blocks, code spans, existing links, HTML tags,
+/// URLs and front matter are left alone.
+pub fn autolink_references(content: &str, repo_url: &str) -> String {
+    let front_matter = front_matter_end(content);
+    let mut out = String::with_capacity(content.len());
+    out.push_str(&content[..front_matter]);
+    let mut fence = None;
+    let mut indented_code = false;
+    let mut after_blank = true;
+
+    for line in content[front_matter..].split_inclusive('\n') {
+        let in_fence = fence.is_some();
+        match fence {
+            None => fence = fence_marker(line),
+            Some(open) if closes_fence(line, open) => fence = None,
+            Some(_) => {}
+        }
+        // An indented code block starts after a blank line and runs until a line
+        // that isn't indented by four spaces
+        let blank = line.trim().is_empty();
+        if !blank {
+            let indented = line.starts_with('\t') || line.len() - line.trim_start_matches(' ').len() >= 4;
+            indented_code = indented && (indented_code || after_blank);
+        }
+        after_blank = blank;
+        if in_fence || fence.is_some() || indented_code {
+            out.push_str(line);
+            continue;
+        }
+        let (mut rest, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (line, ""),
+        };
+        while let Some(start) = rest.find(['`', '[', '<']) {
+            out.push_str(&link_plain_text(&rest[..start], repo_url));
+            rest = &rest[start..];
+            let skip = match rest.as_bytes()[0] {
+                b'`' => {
+                    let ticks = rest.len() - rest.trim_start_matches('`').len();
+                    rest[ticks..].find(&rest[..ticks]).map_or(ticks, |end| 2 * ticks + end)
+                }
+                b'[' => link_len(rest),
+                _ if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') => {
+                    rest.find('>').map_or(1, |end| end + 1)
+                }
+                _ => 1,
+            };
+            out.push_str(&rest[..skip]);
+            rest = &rest[skip..];
+        }
+        out.push_str(&link_plain_text(rest, repo_url));
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Byte length of the bracketed text at the start of `s` together with the
+/// `(destination)`, `[label]` or `: definition` that follows it. An unclosed
+/// bracket is just the bracket.
+fn link_len(s: &str) -> usize {
+    let Some(text) = closing_bracket(s, '[', ']') else {
+        return 1;
+    };
+    let rest = &s[text..];
+    match rest.chars().next() {
+        Some('(') => text + closing_bracket(rest, '(', ')').unwrap_or(0),
+        Some('[') => text + closing_bracket(rest, '[', ']').unwrap_or(0),
+        Some(':') => s.len(),
+        _ => text,
+    }
+}
+
+/// Length of `s` up to and including the bracket closing the one `s` starts with.
+fn closing_bracket(s: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Link the references in `text`, which holds no code or links but may hold bare URLs.
+fn link_plain_text(text: &str, repo_url: &str) -> String {
+    static RE_URL: OnceLock<Regex> = OnceLock::new();
+    static RE_REF: OnceLock<Regex> = OnceLock::new();
+    let re_url = RE_URL.get_or_init(|| Regex::new(r"(?:https?|ftp)://\S+|www\.\S+").unwrap());
+    // `&#123;` is a character reference, `\#1` an escape and `a@b` an email
+    let re_ref = RE_REF.get_or_init(|| {
+        Regex::new(r"(?:^|[^\w&\\/.#@-])(#(\d+)|@([A-Za-z0-9][A-Za-z0-9-]{0,38})|([0-9a-f]{7,40}))\b").unwrap()
+    });
+    if !text.contains(['#', '@']) && !text.contains(|c: char| c.is_ascii_digit()) {
+        return text.to_string();
+    }
+
+    let host_end = repo_url.find("://").map_or(0, |i| i + 3);
+    let host = &repo_url[..repo_url[host_end..].find('/').map_or(repo_url.len(), |i| host_end + i)];
+    let mut out = String::with_capacity(text.len());
+    let mut plain_start = 0;
+    let urls = re_url.find_iter(text).map(|m| (m.start(), m.end())).chain([(text.len(), text.len())]);
+    for (url_start, url_end) in urls {
+        let plain = &text[plain_start..url_start];
+        let mut last = 0;
+        for caps in re_ref.captures_iter(plain) {
+            let reference = caps.get(1).unwrap();
+            let target = if let Some(number) = caps.get(2) {
+                format!("{}/issues/{}", repo_url, number.as_str())
+            } else if let Some(user) = caps.get(3) {
+                format!("{}/{}", host, user.as_str())
+            } else {
+                // A hash mixes digits and letters and isn't part of a UUID or a
+                // longer word; plain numbers and words like "defaced" stay text
+                let hash = reference.as_str();
+                if !hash.contains(|c: char| c.is_ascii_digit())
+                    || !hash.contains(|c: char| c.is_ascii_lowercase())
+                    || plain[reference.end()..].starts_with('-')
+                {
+                    continue;
+                }
+                format!("{}/commit/{}", repo_url, hash)
+            };
+            out.push_str(&plain[last..reference.start()]);
+            out.push_str(&format!("[{}]({})", reference.as_str(), target));
+            last = reference.end();
+        }
+        out.push_str(&plain[last..]);
+        out.push_str(&text[url_start..url_end]);
+        plain_start = url_end;
+    }
+    out
+}
+
+/// Byte length of a leading `---` (YAML) or `+++` (TOML) front matter block, or 0.
+fn front_matter_end(content: &str) -> usize {
+    let Some(first) = content.split_inclusive('\n').next() else {
+        return 0;
+    };
+    let delimiter = first.trim_end();
+    if delimiter != "---" && delimiter != "+++" {
+        return 0;
+    }
+    let mut offset = first.len();
+    for line in content[offset..].split_inclusive('\n') {
+        offset += line.len();
+        if line.trim_end() == delimiter {
+            return offset;
+        }
+    }
+    0
+}
+
+/// The fence character and length if `line` opens a fenced code block
+/// (three or more backticks or tildes).
+pub fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start();
+    trimmed
+        .chars()
+        .next()
+        .filter(|c| *c == '`' || *c == '~')
+        .map(|c| (c, trimmed.chars().take_while(|t| *t == c).count()))
+        .filter(|(_, len)| *len >= 3)
+}
+
+/// Whether `line` closes the block opened by the fence `open`: the same character,
+/// at least as many of them, and nothing else on the line.
+pub fn closes_fence(line: &str, open: (char, usize)) -> bool {
+    let trimmed = line.trim();
+    fence_marker(trimmed).is_some_and(|(c, len)| c == open.0 && len >= open.1 && trimmed.chars().all(|t| t == c))
+}
+
+/// Whether `line` is a `[TOC]` or `[[_TOC_]]` placeholder for an inline table of contents.
+pub fn is_toc_marker(line: &str) -> bool {
+    let line = line.trim();
+    line.eq_ignore_ascii_case("[toc]") || line.eq_ignore_ascii_case("[[_toc_]]")
+}
+
+/// Replace `[TOC]` / `[[_TOC_]]` placeholder lines outside code blocks with the
+/// document's table of contents, limited to `settings.toc_depth` levels. With `html`
+/// it is a nested list of links kept on one line, so source line numbers below it
+/// don't shift; otherwise a markdown list. A document without headings just loses
+/// the placeholder.
+pub fn expand_toc_markers(content: &str, html: bool, settings: &RenderSettings) -> String {
+    let mut fence = None;
+    let mut toc = None;
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        match fence {
+            None => fence = fence_marker(line),
+            Some(open) if closes_fence(line, open) => fence = None,
+            Some(_) => {}
+        }
+        if fence.is_some() || !is_toc_marker(line) {
+            out.push_str(line);
+            continue;
+        }
+        let toc = toc.get_or_insert_with(|| inline_toc(content, html, settings));
+        out.push_str(toc);
+        if line.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The table of contents for [`expand_toc_markers`], without a trailing newline.
+fn inline_toc(content: &str, html: bool, settings: &RenderSettings) -> String {
+    let entries = crate::core::toc::extract_toc(content, settings);
+    let shown: Vec<_> = crate::core::toc::shown_entries(&entries, settings.toc_depth)
+        .into_iter()
+        .map(|i| &entries[i])
+        .collect();
+    let Some(base) = shown.iter().map(|entry| entry.level).min() else {
+        return String::new();
+    };
+    if !html {
+        // Nesting goes at most one level deeper per item, as markdown lists require
+        let mut depth = 0;
+        let items: Vec<String> = shown
+            .iter()
+            .map(|entry| {
+                depth = (entry.level - base + 1).min(depth + 1);
+                format!("{}- {}", "  ".repeat(depth as usize - 1), entry.text)
+            })
+            .collect();
+        return items.join("\n");
+    }
+    let mut out = String::from(r#"<nav class="inline-toc">"#);
+    let mut depth = 0;
+    for entry in shown {
+        let level = (entry.level - base + 1).min(depth + 1);
+        if level > depth {
+            out.push_str("<ul>");
+        } else {
+            out.push_str("</li>");
+            for _ in level..depth {
+                out.push_str("</ul></li>");
+            }
+        }
+        depth = level;
+        let text = entry.text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        out.push_str(&format!(r##"<li><a href="#{}">{}</a>"##, entry.anchor, text));
+    }
+    for _ in 0..depth {
+        out.push_str("</li></ul>");
+    }
+    out.push_str("</nav>");
+    out
+}
+
+/// Replace each tab with spaces up to the next multiple of `tab_width` columns.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let tab_width = tab_width.max(1);
+    let mut out = String::with_capacity(line.len() + tab_width);
+    let mut column = 0;
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = tab_width - column % tab_width;
+                out.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\n' | '\r' => {
+                out.push(c);
+                column = 0;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Split a fence info string like `rust {2,4-6}` into the language and the
+/// 1-based lines to highlight. A spec that doesn't parse highlights nothing.
+pub fn split_info_string(info: &str) -> (&str, BTreeSet<usize>) {
+    let info = info.trim();
+    let (lang, rest) = if info.starts_with('{') {
+        ("", info)
+    } else {
+        info.split_once(char::is_whitespace).unwrap_or((info, ""))
+    };
+    let lines = rest
+        .trim()
+        .strip_prefix('{')
+        .and_then(|spec| spec.split_once('}'))
+        .and_then(|(spec, _)| parse_line_ranges(spec))
+        .unwrap_or_default();
+    (lang, lines)
+}
+
+/// Parse a comma-separated list of line numbers and `a-b` ranges.
+fn parse_line_ranges(spec: &str) -> Option<BTreeSet<usize>> {
+    // Guards against a typo like `1-1000000000` allocating a huge set
+    const MAX_LINE: usize = 100_000;
+    let mut lines = BTreeSet::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end): (usize, usize) = match part.split_once('-') {
+            Some((a, b)) => (a.trim().parse().ok()?, b.trim().parse().ok()?),
+            None => {
+                let n = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if start == 0 || start > end || end > MAX_LINE {
+            return None;
+        }
+        lines.extend(start..=end);
+    }
+    Some(lines)
+}
+
+/// Wrap each line of every rendered code block in `<span class="line">` when it
+/// needs per-line styling: a line-number gutter (`line_numbers`) or lines picked
+/// out by a `{1,3-5}` fence spec (`highlighted-line`).
+/// Numbers are drawn by CSS from `data-line`, so they're never part of selected or
+/// copied text. Numbering restarts at 1 for each block.
+#[cfg(feature = "webview-backend")]
+pub fn wrap_code_lines(html: &str, line_numbers: bool) -> String {
+    use std::sync::OnceLock;
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_CLASS: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_META: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r"<pre([^>]*)><code([^>]*)>([\s\S]*?)</code></pre>").unwrap());
+    let re_class = RE_CLASS.get_or_init(|| regex::Regex::new(r#"class="language-([^"]*)""#).unwrap());
+    let re_meta = RE_META.get_or_init(|| regex::Regex::new(r#"data-meta="([^"]*)""#).unwrap());
+
+    re.replace_all(html, |caps: &regex::Captures| {
+        let code_attrs = &caps[2];
+        let lang = re_class.captures(code_attrs).map_or("", |c| c.get(1).unwrap().as_str());
+        let meta = re_meta.captures(code_attrs).map_or("", |c| c.get(1).unwrap().as_str());
+        let (_, highlighted) = split_info_string(&format!("{} {}", lang, meta));
+        if !line_numbers && highlighted.is_empty() {
+            return caps[0].to_string();
+        }
+
+        let body = caps[3].strip_suffix('\n').unwrap_or(&caps[3]);
+        let lines: String = body
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                let class = if highlighted.contains(&(i + 1)) { "line highlighted-line" } else { "line" };
+                format!("<span class=\"{}\" data-line=\"{}\">{}</span>\n", class, i + 1, line)
+            })
+            .collect();
+        let pre_class = if line_numbers { r#" class="line-numbers""# } else { "" };
+        format!("<pre{}{}><code{}>{}</code></pre>", pre_class, &caps[1], code_attrs, lines)
+    })
+    .to_string()
 }
 
 /// Add id attributes to heading tags for anchor navigation.
 fn add_heading_ids(html: &str) -> String {
     use std::sync::OnceLock;
     static RE: OnceLock<regex::Regex> = OnceLock::new();
-    let re = RE.get_or_init(|| regex::Regex::new(r"<(h[1-6])>(.*?)</h[1-6]>").unwrap());
+    let re = RE.get_or_init(|| regex::Regex::new(r"<(h[1-6])([^>]*)>(.*?)</h[1-6]>").unwrap());
     re.replace_all(html, |caps: &regex::Captures| {
         let tag = &caps[1];
-        let content = &caps[2];
+        let attrs = &caps[2];
+        let content = &caps[3];
         let plain_text = strip_html_tags(content);
         let id = slugify(&plain_text);
-        format!("<{} id=\"{}\">{}</{}>", tag, id, content, tag)
+        format!("<{}{} id=\"{}\">{}</{}>", tag, attrs, id, content, tag)
+    })
+    .to_string()
+}
+
+/// Wrap an image paragraph followed by a paragraph (or line) that is all emphasis,
+/// as `![Chart](chart.png)` then `*Figure 1: sales*`, in a `<figure>` with the
+/// emphasis as its `<figcaption>`. The figure takes the image's `data-sourcepos`.
+fn wrap_image_captions(html: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(
+            r#"<p( data-sourcepos="[^"]*")?>((?:<a [^>]*>)?<img [^>]*>(?:</a>)?)(?:
+|<br />
+|</p>
+<p(?: data-sourcepos="[^"]*")?>)<em>(.*?)</em></p>"#,
+        )
+        .unwrap()
+    });
+    re.replace_all(html, |caps: &regex::Captures| {
+        let caption = &caps[3];
+        // `*a* and *b*` is emphasis, not an emphasized line
+        if caption.contains("</em>") {
+            return caps[0].to_string();
+        }
+        format!(
+            "<figure class=\"image-figure\"{}>\n{}\n<figcaption>{}</figcaption>\n</figure>",
+            caps.get(1).map_or("", |m| m.as_str()),
+            &caps[2],
+            caption
+        )
+    })
+    .to_string()
+}
+
+/// Wrap runs of two or more images, in one paragraph or in paragraphs that follow
+/// each other with nothing else between them, in a `<div class="image-gallery">`
+/// so they flow in a grid instead of stacking full-width. Linked images keep their
+/// links; the gallery takes the `data-sourcepos` of its first paragraph.
+fn wrap_image_galleries(html: &str) -> String {
+    const IMAGE: &str = r#"(?:<a [^>]*>)?<img [^>]*>(?:</a>)?"#;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static RE_IMAGE: OnceLock<Regex> = OnceLock::new();
+    static RE_SOURCEPOS: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        let paragraph = format!(r#"<p(?: data-sourcepos="[^"]*")?>\s*{0}(?:\s*(?:<br />)?\s*{0})*\s*</p>"#, IMAGE);
+        Regex::new(&format!(r#"(?:{}\s*)+"#, paragraph)).unwrap()
+    });
+    let re_image = RE_IMAGE.get_or_init(|| Regex::new(IMAGE).unwrap());
+    let re_sourcepos = RE_SOURCEPOS.get_or_init(|| Regex::new(r#"^<p( data-sourcepos="[^"]*")"#).unwrap());
+    re.replace_all(html, |caps: &regex::Captures| {
+        let run = &caps[0];
+        let images: Vec<&str> = re_image.find_iter(run).map(|image| image.as_str()).collect();
+        if images.len() < 2 {
+            return run.to_string();
+        }
+        let sourcepos = re_sourcepos.captures(run).map_or("", |caps| caps.get(1).unwrap().as_str());
+        format!("<div class=\"image-gallery\"{}>\n{}\n</div>\n", sourcepos, images.join("\n"))
     })
     .to_string()
 }
 
+/// Convert rendered HTML to readable plain text (e.g. for the clipboard).
+/// Drops non-text elements such as inline mermaid SVGs, keeps a blank line between
+/// blocks, tab-separates table cells, and decodes the entities comrak emits.
+pub fn html_to_plain_text(html: &str) -> String {
+    use std::sync::OnceLock;
+    static RE_SKIP: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_CELL: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_BLOCK_END: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_BLANK_LINES: OnceLock<regex::Regex> = OnceLock::new();
+    let re_skip = RE_SKIP.get_or_init(|| {
+        regex::Regex::new(r"(?is)<svg\b.*?</svg>|<style\b.*?</style>|<script\b.*?</script>").unwrap()
+    });
+    let re_cell = RE_CELL.get_or_init(|| regex::Regex::new(r"</t[dh]>\s*<t[dh][^>]*>").unwrap());
+    let re_block_end = RE_BLOCK_END.get_or_init(|| {
+        regex::Regex::new(r"</(p|h[1-6]|pre|blockquote|table|dl|figure)>").unwrap()
+    });
+    let re_blank_lines = RE_BLANK_LINES.get_or_init(|| regex::Regex::new(r"\n[ \t]*\n(\s*\n)+").unwrap());
+
+    let text = re_skip.replace_all(html, "");
+    let text = re_cell.replace_all(&text, "\t");
+    let text = re_block_end.replace_all(&text, "\n");
+    let text = crate::core::entities::decode_entities(&strip_html_tags(&text));
+    re_blank_lines.replace_all(&text, "\n\n").trim().to_string()
+}
+
 fn strip_html_tags(html: &str) -> String {
     use std::sync::OnceLock;
     static RE: OnceLock<regex::Regex> = OnceLock::new();
@@ -50,10 +653,63 @@ fn slugify(text: &str) -> String {
         .join("")
 }
 
+/// Decode percent-encoded URL path components (e.g. %20 -> space).
+pub fn percent_decode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let hex: String = chars.by_ref().take(2).collect();
+            if hex.len() == 2 {
+                if let Ok(byte) = u8::from_str_radix(&hex, 16) {
+                    result.push(byte as char);
+                    continue;
+                }
+            }
+            result.push('%');
+            result.push_str(&hex);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // --- expand_toc_markers tests ---
+
+    #[test]
+    fn toc_marker_expands_to_the_headings() {
+        let md = "# Guide\n\n[TOC]\n\n## Install\n\n### From source\n\n## Usage\n";
+        let expanded = expand_toc_markers(md, false, &RenderSettings::default());
+        assert_eq!(
+            expanded,
+            "# Guide\n\n- Guide\n  - Install\n    - From source\n  - Usage\n\n## Install\n\n### From source\n\n## Usage\n"
+        );
+        let html = expand_toc_markers(md, true, &RenderSettings::default());
+        assert_eq!(html.lines().count(), md.lines().count(), "the list must stay on the marker's line");
+        assert!(html.contains(
+            r##"<nav class="inline-toc"><ul><li><a href="#guide">Guide</a><ul><li><a href="#install">Install</a><ul><li><a href="#from-source">From source</a></li></ul></li><li><a href="#usage">Usage</a></li></ul></li></ul></nav>"##
+        ), "{}", html);
+        let rendered = parse_markdown(md, &RenderSettings::default());
+        assert!(rendered.contains(r##"<a href="#from-source">From source</a>"##));
+        assert!(!rendered.contains("[TOC]"));
+    }
+
+    #[test]
+    fn toc_marker_ignored_in_code_blocks() {
+        let md = "# A\n\n~~~\n[TOC]\n~~~\n\n```\n[[_TOC_]]\n```\n";
+        assert_eq!(expand_toc_markers(md, true, &RenderSettings::default()), md);
+    }
+
+    #[test]
+    fn toc_marker_removed_without_headings() {
+        assert_eq!(expand_toc_markers("intro\n\n[[_toc_]]\n\nend\n", false, &RenderSettings::default()), "intro\n\n\n\nend\n");
+    }
+
     // --- add_heading_ids tests ---
 
     #[test]
@@ -101,21 +757,21 @@ mod tests {
 
     #[test]
     fn parse_markdown_basic_paragraph() {
-        let result = parse_markdown("Hello world");
+        let result = parse_markdown("Hello world", &RenderSettings::default());
         assert!(result.contains("Hello world"));
         assert!(result.contains("<p>"));
     }
 
     #[test]
     fn parse_markdown_heading_gets_id() {
-        let result = parse_markdown("# My Title");
+        let result = parse_markdown("# My Title", &RenderSettings::default());
         assert!(result.contains(r#"id="my-title""#));
         assert!(result.contains("My Title"));
     }
 
     #[test]
     fn parse_markdown_multiple_headings_get_ids() {
-        let result = parse_markdown("# First\n## Second\n### Third");
+        let result = parse_markdown("# First\n## Second\n### Third", &RenderSettings::default());
         assert!(result.contains(r#"id="first""#));
         assert!(result.contains(r#"id="second""#));
         assert!(result.contains(r#"id="third""#));
@@ -124,23 +780,211 @@ mod tests {
     #[test]
     fn parse_markdown_table() {
         let md = "| A | B |\n|---|---|\n| 1 | 2 |";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<table>"));
         assert!(result.contains("<th>"));
         assert!(result.contains("<td>"));
     }
 
+    #[test]
+    fn parse_markdown_definition_list() {
+        let md = "Apple\n: A red fruit\n\nBanana\n: A yellow fruit\n: A phone in a cartoon\n";
+        let result = parse_markdown(md, &RenderSettings::default());
+        assert!(result.contains("<dl>"));
+        assert!(result.contains("<dt>Apple</dt>"));
+        assert!(result.contains("<dt>Banana</dt>"));
+        assert_eq!(result.matches("<dd>").count(), 3);
+        assert!(result.contains("A phone in a cartoon"));
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn parse_markdown_with_sourcepos_keeps_heading_ids_and_mermaid() {
+        let md = "# Title\n\ntext\n\n```mermaid\ngraph TD\n    A --> B\n```\n";
+        let result = parse_markdown_with_sourcepos(md, &RenderSettings::default(), Path::new("."));
+        assert_eq!(result.heading_lines, [1]);
+        let result = result.html;
+        assert!(result.contains(r#"<h1 data-sourcepos="1:1-1:7" id="title">"#), "got: {}", result);
+        assert!(result.contains(r#"<p data-sourcepos="3:1-3:4">"#), "got: {}", result);
+        assert!(result.contains(r#"class="mermaid-diagram" data-sourcepos="5:1-8:3""#), "got: {}", result);
+        assert!(!parse_markdown(md, &RenderSettings::default()).contains("data-sourcepos"));
+    }
+
+    // --- tab expansion tests ---
+
+    #[test]
+    fn expand_tabs_uses_tab_stops() {
+        assert_eq!(expand_tabs("\tx", 4), "    x");
+        assert_eq!(expand_tabs("ab\tc", 4), "ab  c");
+        assert_eq!(expand_tabs("abcd\te", 4), "abcd    e");
+        assert_eq!(expand_tabs("\t\tx", 2), "    x");
+        assert_eq!(expand_tabs("no tabs", 4), "no tabs");
+    }
+
+    #[test]
+    fn expand_code_tabs_only_touches_fenced_code() {
+        let md = "Intro\twith tab\n\n```rust\nfn main() {\n\tif true {\n\t\tprintln!(\"hi\");\n\t}\n}\n```\nAfter\ttab\n";
+        let expanded = expand_code_tabs(md, 4);
+        assert_eq!(
+            expanded,
+            "Intro\twith tab\n\n```rust\nfn main() {\n    if true {\n        println!(\"hi\");\n    }\n}\n```\nAfter\ttab\n"
+        );
+        assert!(expand_code_tabs(md, 2).contains("\n    println!"));
+    }
+
+    #[test]
+    fn parse_markdown_expands_tabs_in_code() {
+        let result = parse_markdown("```rust\nfn main() {\n\tlet x = 1;\n}\n```\n", &RenderSettings::default());
+        assert!(result.contains("\n    let x = 1;"), "got: {}", result);
+        assert!(!result.contains('\t'));
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn wrap_code_lines_numbers_restart_per_block() {
+        let html = parse_markdown("```rust\nlet a = 1;\nlet b = 2;\n```\n\ntext\n\n```\nsecond\n```\n", &RenderSettings::default());
+        let numbered = wrap_code_lines(&html, true);
+        assert!(numbered.contains(r#"<pre class="line-numbers"><code class="language-rust"><span class="line" data-line="1">let a = 1;</span>"#), "got: {}", numbered);
+        assert!(numbered.contains(r#"<span class="line" data-line="2">let b = 2;</span>"#));
+        assert!(numbered.contains(r#"<code><span class="line" data-line="1">second</span>"#), "got: {}", numbered);
+        assert!(!numbered.contains(r#"data-line="3""#));
+        assert!(numbered.contains("<p>text</p>"));
+        assert_eq!(wrap_code_lines(&html, false), html);
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn wrap_code_lines_marks_highlighted_lines() {
+        let html = parse_markdown("```rust {2}\na\nb\nc\n```\n", &RenderSettings::default());
+        let wrapped = wrap_code_lines(&html, false);
+        assert!(wrapped.contains(r#"<span class="line" data-line="1">a</span>"#), "got: {}", wrapped);
+        assert!(wrapped.contains(r#"<span class="line highlighted-line" data-line="2">b</span>"#), "got: {}", wrapped);
+        assert!(!wrapped.contains("line-numbers"));
+    }
+
+    // --- HTML comment tests ---
+
+    #[test]
+    fn html_comments_are_stripped_outside_code() {
+        assert_eq!(strip_html_comments("Before <!-- note to self --> after\n"), "Before  after\n");
+        assert_eq!(strip_html_comments("a\n<!--\nTODO: rewrite\n-->\nb\n"), "a\n\n\n\nb\n");
+        assert_eq!(strip_html_comments("x <!-- one --> y <!-- two\nlines --> z"), "x  y \n z");
+        // Code keeps its comments
+        let code = "```html\n<!-- keep -->\n```\nUse `<!-- -->` or ``a <!-- b``\n";
+        assert_eq!(strip_html_comments(code), code);
+        // An unclosed comment runs to the end, as in HTML
+        assert_eq!(strip_html_comments("text\n<!-- draft\nmore\n"), "text\n\n\n");
+    }
+
+    #[test]
+    fn rendered_html_has_no_comments() {
+        let html = parse_markdown("Visible\n\n<!-- hidden\nnote -->\n\n```\n<!-- shown -->\n```\n", &RenderSettings::default());
+        assert!(html.contains("Visible") && !html.contains("hidden"), "{}", html);
+        assert!(html.contains("&lt;!-- shown --&gt;"), "{}", html);
+    }
+
+    // --- reference autolink tests ---
+
+    const REPO: &str = "https://github.com/owner/repo";
+
+    #[test]
+    fn issue_user_and_commit_references_are_linked() {
+        assert_eq!(
+            autolink_references("Fixed in #42 by @ada-l.\n", REPO),
+            "Fixed in [#42](https://github.com/owner/repo/issues/42) by [@ada-l](https://github.com/ada-l).\n"
+        );
+        assert_eq!(
+            autolink_references("See a1b2c3d and (0123456789abcdef0123456789abcdef01234567)", REPO),
+            "See [a1b2c3d](https://github.com/owner/repo/commit/a1b2c3d) and \
+             ([0123456789abcdef0123456789abcdef01234567](https://github.com/owner/repo/commit/0123456789abcdef0123456789abcdef01234567))"
+        );
+    }
+
+    #[test]
+    fn reference_lookalikes_are_left_alone() {
+        let md = "Color #ffffff or #fff, mail me@example.com, &#123; and \\#7.\n\
+                  Numbers 2024010, words like defaced, id 123e4567-e89b-12d3-a456-426614174000.\n\
+                  Already [#5](https://example.com) and [see #6][ref], <span title=\"#7\">x</span>.\n\
+                  https://github.com/owner/repo/pull/8#issuecomment-1234567 and `#9 @me a1b2c3d`\n\n\
+                  ```\n#10 @you\n```\n\n    indented #11\n";
+        assert_eq!(autolink_references(md, REPO), md);
+    }
+
+    #[test]
+    fn references_need_a_repository() {
+        assert_eq!(link_references("Fixes #1\n", None), "Fixes #1\n");
+        assert_eq!(link_references("Fixes #1\n", Some(REPO)), format!("Fixes [#1]({}/issues/1)\n", REPO));
+        let md = "---\ntitle: Notes #3\nrepo_url: \"https://gitlab.com/group/project/\"\n---\nFixes #1\n";
+        assert_eq!(
+            link_references(md, None),
+            "---\ntitle: Notes #3\nrepo_url: \"https://gitlab.com/group/project/\"\n---\n\
+             Fixes [#1](https://gitlab.com/group/project/issues/1)\n"
+        );
+    }
+
+    // --- info string tests ---
+
+    #[test]
+    fn split_info_string_parses_line_spec() {
+        let (lang, lines) = split_info_string("rust {1,3-5}");
+        assert_eq!(lang, "rust");
+        assert_eq!(lines.into_iter().collect::<Vec<_>>(), vec![1, 3, 4, 5]);
+
+        let (lang, lines) = split_info_string("{2}");
+        assert_eq!(lang, "");
+        assert_eq!(lines.into_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn split_info_string_ignores_bad_specs() {
+        assert_eq!(split_info_string("rust"), ("rust", BTreeSet::new()));
+        assert_eq!(split_info_string("rust {5-3}"), ("rust", BTreeSet::new()));
+        assert_eq!(split_info_string("rust {a,2}"), ("rust", BTreeSet::new()));
+        assert_eq!(split_info_string("rust {0}"), ("rust", BTreeSet::new()));
+        assert_eq!(split_info_string("rust title=\"x\""), ("rust", BTreeSet::new()));
+    }
+
+    #[test]
+    fn parse_markdown_hides_line_spec_from_language() {
+        let result = parse_markdown("```rust {1,3}\nfn main() {}\n```\n", &RenderSettings::default());
+        assert!(result.contains(r#"class="language-rust""#), "got: {}", result);
+        assert!(!result.contains("language-rust {"));
+    }
+
+    // --- html_to_plain_text tests ---
+
+    #[test]
+    fn plain_text_keeps_block_structure() {
+        let html = parse_markdown("# Title\n\nSome **bold** & `<code>`.\n\n- one\n- two\n\nAfter list\n", &RenderSettings::default());
+        let text = html_to_plain_text(&html);
+        assert_eq!(text, "Title\n\nSome bold & <code>.\n\none\ntwo\n\nAfter list");
+    }
+
+    #[test]
+    fn plain_text_tab_separates_table_cells() {
+        let html = parse_markdown("| A | B |\n|---|---|\n| 1 | 2 |\n", &RenderSettings::default());
+        let text = html_to_plain_text(&html);
+        assert!(text.contains("A\tB"), "got: {:?}", text);
+        assert!(text.contains("1\t2"), "got: {:?}", text);
+    }
+
+    #[test]
+    fn plain_text_drops_inline_svg() {
+        let html = "<p>Before</p>\n<div class=\"mermaid-diagram\"><svg><style>.a{}</style><text>Node</text></svg></div>\n<p>After</p>\n";
+        assert_eq!(html_to_plain_text(html), "Before\n\nAfter");
+    }
+
     #[test]
     fn parse_markdown_tasklist() {
         let md = "- [x] Done\n- [ ] Todo";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("checkbox"));
     }
 
     #[test]
     fn parse_markdown_strikethrough() {
         let md = "This is ~~deleted~~ text.";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<del>"));
         assert!(result.contains("deleted"));
     }
@@ -149,7 +993,7 @@ mod tests {
     fn parse_markdown_mermaid_block_is_processed() {
         // A mermaid code block should be processed (either rendered or show error)
         let md = "```mermaid\ngraph LR\n  A-->B\n```";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         // The mermaid block should not remain as a raw code block with language-mermaid class
         // It should either be a rendered SVG diagram or a mermaid-error div
         assert!(
@@ -161,7 +1005,7 @@ mod tests {
 
     #[test]
     fn parse_markdown_empty_input() {
-        let result = parse_markdown("");
+        let result = parse_markdown("", &RenderSettings::default());
         // Empty input should produce empty or minimal HTML
         assert!(result.is_empty() || result.trim().is_empty());
     }
@@ -169,7 +1013,7 @@ mod tests {
     #[test]
     fn parse_markdown_code_block_not_mermaid() {
         let md = "```rust\nfn main() {}\n```";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<code"));
         assert!(!result.contains("mermaid-diagram"));
     }
@@ -180,7 +1024,7 @@ mod tests {
     fn parse_markdown_raw_html_img_preserved() {
         // Business docs often use raw HTML <img> tags for sizing
         let md = r#"<img src="chart.png" alt="Revenue chart" width="600" />"#;
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<img"), "Raw HTML <img> tags should be preserved, got: {}", result);
         assert!(result.contains("chart.png"), "Image src should be preserved, got: {}", result);
     }
@@ -188,7 +1032,7 @@ mod tests {
     #[test]
     fn parse_markdown_raw_html_img_with_attributes() {
         let md = r#"<p align="center"><img src="logo.png" alt="logo" width="200"/></p>"#;
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<img"), "Centered HTML image should be preserved, got: {}", result);
         assert!(result.contains("logo.png"), "Image src should be preserved, got: {}", result);
     }
@@ -197,10 +1041,48 @@ mod tests {
     fn parse_markdown_markdown_image_syntax_works() {
         // Standard markdown images should always work
         let md = "![alt text](image.png)";
-        let result = parse_markdown(md);
+        let result = parse_markdown(md, &RenderSettings::default());
         assert!(result.contains("<img"), "Markdown image should produce <img>, got: {}", result);
         assert!(result.contains("image.png"), "Image src should be present, got: {}", result);
     }
+
+    #[test]
+    fn consecutive_images_become_a_gallery() {
+        let md = "![one](1.png)\n![two](2.png)\n\n[![three](3.png)](big.png)\n\nText.\n\n![lone](4.png)\n\nSee ![inline](5.png) here.\n";
+        let html = parse_markdown(md, &RenderSettings::default());
+        assert_eq!(html.matches(r#"<div class="image-gallery">"#).count(), 1, "{}", html);
+        let gallery = &html[html.find("image-gallery").unwrap()..html.find("</div>").unwrap()];
+        for image in ["1.png", "2.png", r#"<a href="big.png"><img src="3.png""#] {
+            assert!(gallery.contains(image), "{} not in {}", image, gallery);
+        }
+        assert!(!gallery.contains("<p>"), "{}", gallery);
+        assert!(html.contains(r#"<p><img src="4.png" alt="lone" /></p>"#), "{}", html);
+        assert!(html.contains("<p>See <img"), "{}", html);
+    }
+
+    #[test]
+    fn italic_line_under_an_image_becomes_its_caption() {
+        let html = parse_markdown("![Chart](chart.png)\n*Figure 1: sales*\n\n![Logo](logo.png)\n\n_The logo_\n", &RenderSettings::default());
+        assert!(
+            html.contains("<figure class=\"image-figure\">\n<img src=\"chart.png\" alt=\"Chart\" />\n<figcaption>Figure 1: sales</figcaption>\n</figure>"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<figcaption>The logo</figcaption>"), "{}", html);
+        assert!(!html.contains("<em>"), "{}", html);
+
+        // Only a line that is all emphasis is a caption
+        for md in ["![a](a.png)\n*one* and *two*\n", "![a](a.png)\nSome *text*\n", "See ![a](a.png)\n*not a figure*\n"] {
+            assert!(!parse_markdown(md, &RenderSettings::default()).contains("<figure"), "{}", md);
+        }
+    }
+
+    #[test]
+    fn explicit_figures_pass_through() {
+        let md = "<figure>\n<img src=\"diagram.png\" alt=\"Flow\">\n<figcaption>How requests flow</figcaption>\n</figure>\n";
+        let html = parse_markdown(md, &RenderSettings::default());
+        assert!(html.contains("<figcaption>How requests flow</figcaption>"), "{}", html);
+    }
 }
 
 /// CSS for GitHub-like markdown rendering with dark/light theme support.
@@ -244,6 +1126,39 @@ body {
     margin: 0;
 }
 .sidebar ul { list-style: none; margin: 0; padding: 0; }
+.sidebar-footer {
+    font-size: 12px;
+    color: var(--blockquote);
+    padding: 16px 16px 0;
+    margin: 16px 0 0;
+    border-top: 1px solid var(--border);
+}
+.sidebar-meta {
+    font-size: 12px;
+    color: var(--blockquote);
+    padding: 4px 16px 0;
+    margin: 0;
+}
+.sidebar-meta:empty {
+    display: none;
+}
+.sidebar-recent { margin-top: 16px; }
+.recent-files button {
+    display: block;
+    width: 100%;
+    padding: 4px 16px;
+    border: none;
+    background: none;
+    color: var(--fg);
+    font: inherit;
+    font-size: 13px;
+    text-align: left;
+    white-space: nowrap;
+    overflow: hidden;
+    text-overflow: ellipsis;
+    cursor: pointer;
+}
+.recent-files button:hover { background: var(--sidebar-hover); }
 .sidebar li a {
     display: block;
     padding: 4px 16px;
@@ -267,6 +1182,23 @@ body {
 h1, h2, h3, h4, h5, h6 { margin-top: 24px; margin-bottom: 16px; font-weight: 600; line-height: 1.25; }
 h1 { font-size: 2em; padding-bottom: 0.3em; border-bottom: 1px solid var(--border); }
 h2 { font-size: 1.5em; padding-bottom: 0.3em; border-bottom: 1px solid var(--border); }
+h1[id], h2[id], h3[id], h4[id], h5[id], h6[id] { position: relative; }
+.heading-anchor {
+    position: absolute;
+    left: -1.4em;
+    padding-right: 0.4em;
+    font-size: 0.75em;
+    line-height: 1.65;
+    text-decoration: none;
+    opacity: 0;
+    transition: opacity 0.15s;
+}
+/* The icon is generated content so it stays out of search and copied text */
+.heading-anchor::before { content: "\1F517"; }
+h1:hover .heading-anchor, h2:hover .heading-anchor, h3:hover .heading-anchor,
+h4:hover .heading-anchor, h5:hover .heading-anchor, h6:hover .heading-anchor,
+.heading-anchor:focus { opacity: 0.7; }
+.heading-anchor:hover { opacity: 1; }
 code {
     font-family: ui-monospace, SFMono-Regular, "SF Mono", Menlo, Consolas, monospace;
     font-size: 85%;
@@ -282,9 +1214,31 @@ pre {
     line-height: 1.45;
 }
 pre code { background: transparent; padding: 0; font-size: 85%; }
+pre .highlighted-line {
+    display: inline-block;
+    min-width: 100%;
+    background: rgba(212, 167, 44, 0.2);
+}
+pre.line-numbers .line::before {
+    content: attr(data-line);
+    display: inline-block;
+    min-width: 2em;
+    margin-right: 1em;
+    padding-right: 0.5em;
+    border-right: 1px solid var(--border);
+    text-align: right;
+    color: var(--blockquote);
+    user-select: none;
+    -webkit-user-select: none;
+}
 table { border-collapse: collapse; width: 100%; margin: 16px 0; }
 th, td { border: 1px solid var(--border); padding: 6px 13px; }
 th { font-weight: 600; background: var(--code-bg); }
+/* --sortable-tables: headers sort their column, with the direction shown after them */
+table[data-sortable] th { cursor: pointer; user-select: none; }
+table[data-sortable] th[aria-sort="ascending"]::after { content: " \25B2"; font-size: 0.75em; }
+table[data-sortable] th[aria-sort="descending"]::after { content: " \25BC"; font-size: 0.75em; }
+table[data-sortable] th[aria-sort="none"]:hover::after { content: " \21C5"; font-size: 0.75em; opacity: 0.5; }
 blockquote {
     color: var(--blockquote);
     border-left: 4px solid var(--border);
@@ -295,8 +1249,34 @@ a { color: var(--link); text-decoration: none; }
 a:hover { text-decoration: underline; }
 hr { border: none; border-top: 1px solid var(--border); margin: 24px 0; }
 img { max-width: 100%; }
+.image-gallery {
+    display: grid;
+    grid-template-columns: repeat(auto-fill, minmax(min(240px, 100%), 1fr));
+    gap: 8px;
+    align-items: start;
+    margin-bottom: 16px;
+}
+.image-gallery img { display: block; width: 100%; height: auto; }
+/* Images with a caption: <figure>, or an image with an italic line under it */
+figure { margin: 0 0 16px; text-align: center; }
+figure img { display: block; margin: 0 auto; }
+figcaption { margin-top: 8px; color: var(--blockquote); font-size: 0.9em; font-style: italic; }
 ul, ol { padding-left: 2em; }
+details {
+    border: 1px solid var(--border);
+    border-radius: 6px;
+    padding: 8px 16px;
+    margin: 16px 0;
+}
+details > summary { cursor: pointer; font-weight: 600; }
+details[open] > summary { margin-bottom: 8px; padding-bottom: 8px; border-bottom: 1px solid var(--border); }
+dl { margin: 16px 0; padding: 0; }
+dl dt { font-weight: 600; font-style: italic; margin-top: 16px; }
+dl dd { margin: 0 0 16px; padding: 0 16px; }
+dl dd > p { margin: 0; }
 input[type="checkbox"] { margin-right: 0.5em; }
+/* Done tasks are muted and struck through, like the TUI */
+li:has(> input[type="checkbox"]:checked) { color: var(--blockquote); text-decoration: line-through; }
 .mermaid-diagram { text-align: center; margin: 16px 0; }
 .mermaid-diagram svg { max-width: 100%; height: auto; }
 .mermaid-error {
@@ -325,6 +1305,63 @@ input[type="checkbox"] { margin-right: 0.5em; }
 .mermaid-icon { margin-right: 6px; }
 .mermaid-fallback pre { margin: 0; border-radius: 0; }
 .mermaid-fallback code { font-size: 13px; color: var(--fg); }
+.mermaid-fallback .mermaid-source { border-top: 1px solid var(--border); }
+.mermaid-error-line { display: inline-block; width: 100%; background: rgba(248, 81, 73, 0.2); color: inherit; }
+/* Tabs */
+.tab-strip {
+    position: fixed;
+    top: 0;
+    left: 250px;
+    right: 0;
+    display: flex;
+    overflow-x: auto;
+    background: var(--sidebar-bg);
+    border-bottom: 1px solid var(--border);
+    z-index: 1000;
+    font-size: 13px;
+}
+.tab-strip:empty { display: none; }
+.tab-strip .tab {
+    display: flex;
+    align-items: center;
+    gap: 6px;
+    padding: 6px 8px 6px 12px;
+    border-right: 1px solid var(--border);
+    color: var(--blockquote);
+    cursor: pointer;
+    white-space: nowrap;
+}
+.tab-strip .tab:hover { background: var(--sidebar-hover); }
+.tab-strip .tab.active { background: var(--bg); color: var(--fg); }
+.tab-strip .tab-close {
+    border: none;
+    background: none;
+    color: inherit;
+    cursor: pointer;
+    font-size: 11px;
+    opacity: 0.6;
+}
+.tab-strip .tab-close:hover { opacity: 1; }
+/* Reading progress, along the top of the content */
+.reading-progress {
+    position: fixed;
+    top: 0;
+    left: 250px;
+    right: 0;
+    height: 3px;
+    background: var(--link);
+    transform: scaleX(0);
+    transform-origin: left;
+    transition: transform 0.1s linear;
+    pointer-events: none;
+    z-index: 1002;
+}
+body.has-tabs .content { padding-top: 64px; }
+body.has-tabs .content [id] { scroll-margin-top: 40px; }
+body.has-tabs .copy-toolbar { top: 44px; }
+/* Focus mode */
+body.focus-mode .doc-section { transition: opacity 0.2s; }
+body.focus-mode .doc-section:not(.focused) { opacity: 0.35; }
 /* Search */
 .search-bar {
     position: fixed;
@@ -364,6 +1401,94 @@ input[type="checkbox"] { margin-right: 0.5em; }
 }
 .search-bar button:hover { background: var(--sidebar-hover); }
 .search-bar .close-btn { margin-left: auto; }
+.search-bar input.invalid { border-color: #cf222e; }
+.search-bar .search-option { display: flex; align-items: center; color: var(--blockquote); cursor: pointer; white-space: nowrap; }
+.search-bar .search-option input { flex: none; margin: 0 4px 0 0; }
+.sidebar a.search-match { background: #ffd33d55; border-radius: 2px; }
 mark.search-highlight { background: #ffd33d55; color: inherit; border-radius: 2px; }
 mark.search-highlight.current { background: #ffd33d; color: #000; }
+/* Copy */
+.copy-toolbar {
+    position: fixed;
+    top: 12px;
+    right: 16px;
+    display: flex;
+    gap: 6px;
+    z-index: 1000;
+}
+.copy-toolbar button {
+    padding: 4px 8px;
+    border: 1px solid var(--border);
+    border-radius: 4px;
+    background: var(--code-bg);
+    color: var(--fg);
+    cursor: pointer;
+    font-size: 12px;
+    opacity: 0.6;
+}
+.copy-toolbar button:hover { opacity: 1; background: var(--sidebar-hover); }
+.toast {
+    position: fixed;
+    bottom: 56px;
+    right: 16px;
+    padding: 8px 14px;
+    border-radius: 6px;
+    background: var(--fg);
+    color: var(--bg);
+    font-size: 13px;
+    opacity: 0;
+    transition: opacity 0.2s;
+    pointer-events: none;
+    z-index: 1001;
+}
+.toast.visible { opacity: 1; }
+/* Shown while the file is removed, over the last content */
+.file-removed {
+    position: fixed;
+    bottom: 16px;
+    left: 266px;
+    padding: 8px 14px;
+    border: 1px solid #d4a72c;
+    border-radius: 6px;
+    background: #fff8c5;
+    color: #3b2300;
+    font-size: 13px;
+    z-index: 1001;
+}
+.file-removed[hidden] { display: none; }
+/* Lightbox */
+.content img, .mermaid-diagram svg { cursor: zoom-in; }
+.content a img { cursor: pointer; }
+.lightbox {
+    position: fixed;
+    inset: 0;
+    display: flex;
+    overflow: auto;
+    background: rgba(0, 0, 0, 0.85);
+    z-index: 2000;
+    cursor: zoom-out;
+}
+.lightbox[hidden] { display: none; }
+.lightbox > img, .lightbox > svg {
+    flex: none;
+    margin: auto;
+    max-width: none;
+    background: var(--bg);
+    user-select: none;
+}
+/* Accessibility */
+:focus-visible { outline: 2px solid var(--link); outline-offset: 2px; }
+body.high-contrast {
+    --bg: #000000; --fg: #ffffff; --code-bg: #000000; --border: #ffffff; --link: #ffff00;
+    --blockquote: #ffffff; --sidebar-bg: #000000; --sidebar-hover: #333333; --sidebar-active: #000000;
+}
+body.high-contrast a { text-decoration: underline; }
+body.high-contrast .sidebar li a.active { outline: 2px solid var(--link); outline-offset: -2px; }
+body.high-contrast pre, body.high-contrast code, body.high-contrast table,
+body.high-contrast th, body.high-contrast td, body.high-contrast blockquote { border: 1px solid var(--border); }
+body.high-contrast :focus-visible { outline-width: 3px; }
+@media (prefers-reduced-motion: reduce) {
+    html { scroll-behavior: auto; }
+    *, *::before, *::after { transition: none !important; animation: none !important; }
+}
 "#;
@@ -0,0 +1,428 @@
+/// Greek letters, operators and relations written as a single control word.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("oint", "∮"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("infty", "∞"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "·"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("approx", "≈"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("equiv", "≡"),
+    ("propto", "∝"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("mid", "∣"),
+    ("angle", "∠"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("implies", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("prime", "′"),
+    ("degree", "°"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("aleph", "ℵ"),
+];
+
+/// Operator names set upright in LaTeX, shown as the plain word.
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "lim", "limsup", "liminf", "min", "max", "sup", "inf", "det", "gcd",
+    "deg", "dim", "ker", "arg", "mod", "Pr",
+];
+
+/// Render a TeX math expression such as `\sum_{i=0}^n x_i` as plain Unicode
+/// (`∑ᵢ₌₀ⁿ xᵢ`): Greek letters and operators become their symbols, scripts use
+/// the Unicode superscript and subscript letters where they all exist (and
+/// `^(…)` / `_(…)` otherwise), and `\frac` / `\sqrt` are written inline.
+/// Returns `None` for anything it cannot approximate — environments, unknown
+/// commands, unbalanced braces — so the caller can show the source instead.
+pub fn to_unicode(tex: &str) -> Option<String> {
+    let mut parser = Parser { chars: tex.chars().collect(), pos: 0 };
+    let text = parser.sequence(None)?;
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Atoms and scripts up to `close` (consumed) or the end of the input.
+    fn sequence(&mut self, close: Option<char>) -> Option<String> {
+        let mut out = String::new();
+        while let Some(c) = self.chars.get(self.pos).copied() {
+            if Some(c) == close {
+                self.pos += 1;
+                return Some(out);
+            }
+            match c {
+                '^' | '_' => {
+                    self.pos += 1;
+                    let argument = self.argument()?;
+                    out.push_str(&script(&argument, c == '^'));
+                }
+                _ => out.push_str(&self.atom()?),
+            }
+        }
+        close.is_none().then_some(out)
+    }
+
+    /// A group, a command with its arguments, or a single character.
+    fn atom(&mut self) -> Option<String> {
+        let c = *self.chars.get(self.pos)?;
+        self.pos += 1;
+        match c {
+            '{' => self.sequence(Some('}')),
+            '\\' => self.command(),
+            '}' | '&' | '#' | '%' | '$' => None,
+            '-' => Some("−".to_string()),
+            c if c.is_whitespace() => Some(" ".to_string()),
+            c => Some(c.to_string()),
+        }
+    }
+
+    /// The argument of a command or script: a group or the next single atom.
+    fn argument(&mut self) -> Option<String> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        match self.chars.get(self.pos) {
+            Some('^' | '_') | None => None,
+            Some(_) => self.atom(),
+        }
+    }
+
+    /// An optional `[...]` argument, as in `\sqrt[3]{x}`.
+    fn optional_argument(&mut self) -> Option<String> {
+        if self.chars.get(self.pos) != Some(&'[') {
+            return None;
+        }
+        let close = self.chars[self.pos..].iter().position(|&c| c == ']')?;
+        let argument = self.chars[self.pos + 1..self.pos + close].iter().collect();
+        self.pos += close + 1;
+        Some(argument)
+    }
+
+    /// The command after a backslash.
+    fn command(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            // A control symbol: escaped punctuation or spacing
+            let c = *self.chars.get(self.pos)?;
+            self.pos += 1;
+            return match c {
+                ',' | ':' | ';' | ' ' => Some(" ".to_string()),
+                '!' => Some(String::new()),
+                '{' | '}' | '$' | '%' | '&' | '#' | '_' | '|' => Some(c.to_string()),
+                _ => None,
+            };
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                Some(format!("{}/{}", parenthesize(&numerator), parenthesize(&denominator)))
+            }
+            "sqrt" => {
+                let root = match self.optional_argument().as_deref().map(str::trim) {
+                    None | Some("2") => "√",
+                    Some("3") => "∛",
+                    Some("4") => "∜",
+                    Some(_) => return None,
+                };
+                Some(format!("{}{}", root, parenthesize(&self.argument()?)))
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mathrm" | "mathit" | "mathbf" | "mathsf"
+            | "boldsymbol" | "operatorname" => self.argument(),
+            "mathbb" => self.argument()?.chars().map(double_struck).collect(),
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "displaystyle" | "limits" => Some(String::new()),
+            "quad" | "qquad" => Some(" ".to_string()),
+            "lbrace" => Some("{".to_string()),
+            "rbrace" => Some("}".to_string()),
+            "vert" => Some("|".to_string()),
+            "Vert" => Some("‖".to_string()),
+            name if FUNCTIONS.contains(&name) => Some(name.to_string()),
+            name => SYMBOLS.iter().find(|(symbol, _)| *symbol == name).map(|(_, unicode)| unicode.to_string()),
+        }
+    }
+}
+
+/// `text` as a superscript (or subscript), in Unicode script letters when every
+/// character has one.
+fn script(text: &str, superscript: bool) -> String {
+    let text = text.trim();
+    let shifted: Option<String> = text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| script_char(c, superscript))
+        .collect();
+    match shifted {
+        Some(shifted) if !shifted.is_empty() => shifted,
+        _ => format!("{}{}", if superscript { '^' } else { '_' }, parenthesize(text)),
+    }
+}
+
+/// Wrap `text` in parentheses unless it is a single term.
+fn parenthesize(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > 1 && !text.chars().all(char::is_alphanumeric) {
+        format!("({})", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// The Unicode superscript (or subscript) form of `c`, where there is one.
+pub fn script_char(c: char, superscript: bool) -> Option<char> {
+    if superscript { superscript_char(c) } else { subscript_char(c) }
+}
+
+fn superscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        '′' | '\'' => '′',
+        '∗' | '*' => '*',
+        _ => return None,
+    })
+}
+
+fn subscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        _ => return None,
+    })
+}
+
+fn double_struck(c: char) -> Option<char> {
+    Some(match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractions_are_written_inline() {
+        assert_eq!(to_unicode(r"\frac{1}{2}").as_deref(), Some("1/2"));
+        assert_eq!(to_unicode(r"\frac{a+b}{2c}").as_deref(), Some("(a+b)/2c"));
+        assert_eq!(to_unicode(r"x = \frac{-b \pm \sqrt{b^2 - 4ac}}{2a}").as_deref(), Some("x = (−b ± √(b² − 4ac))/2a"));
+    }
+
+    #[test]
+    fn sums_take_their_bounds_as_scripts() {
+        assert_eq!(to_unicode(r"\sum_{i=0}^n x_i").as_deref(), Some("∑ᵢ₌₀ⁿ xᵢ"));
+        assert_eq!(to_unicode(r"\int_0^\infty e^{-x} dx").as_deref(), Some("∫₀^∞ e⁻ˣ dx"));
+        assert_eq!(to_unicode(r"\lim_{x \to 0} f(x)").as_deref(), Some("lim_(x → 0) f(x)"));
+    }
+
+    #[test]
+    fn greek_letters_and_operators() {
+        assert_eq!(to_unicode(r"\alpha + \beta \leq \Gamma").as_deref(), Some("α + β ≤ Γ"));
+        assert_eq!(to_unicode(r"\forall x \in \mathbb{R}, x^2 \geq 0").as_deref(), Some("∀ x ∈ ℝ, x² ≥ 0"));
+    }
+
+    #[test]
+    fn unknown_constructs_are_not_approximated() {
+        assert_eq!(to_unicode(r"\begin{pmatrix} a & b \end{pmatrix}"), None);
+        assert_eq!(to_unicode(r"\frac{1}{2"), None);
+        assert_eq!(to_unicode(r"\unknowncommand x"), None);
+        assert_eq!(to_unicode(r"x^"), None);
+    }
+}
@@ -1,50 +1,342 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
 use regex::Regex;
 
+/// Keywords of the diagram types mermaid-rs-renderer draws, matched against the
+/// start of the first line as the renderer does. `zenuml` parses but comes out empty,
+/// so it is left to the fallbacks.
+const DIAGRAM_KEYWORDS: &[&str] = &[
+    "flowchart", "graph", "sequencediagram", "classdiagram", "statediagram", "erdiagram", "pie", "mindmap",
+    "journey", "timeline", "gantt", "requirementdiagram", "gitgraph", "c4", "sankey", "quadrantchart", "block",
+    "packet", "kanban", "architecture", "radar", "treemap", "xychart",
+];
+
+/// The diagram types that get fixups of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiagramKind {
+    Flowchart,
+    Sequence,
+    Class,
+    State,
+    Other,
+}
+
+/// The diagram type and the 1-based line naming it, skipping front matter, `%%`
+/// comments and `%%{init}%%` directives. Errors for types the renderer doesn't draw.
+fn diagram_kind(source: &str) -> Result<DiagramKind, MermaidError> {
+    let mut in_front_matter = false;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line == "---" && (i == 0 || in_front_matter) {
+            in_front_matter = !in_front_matter;
+            continue;
+        }
+        if in_front_matter || line.is_empty() || line.starts_with("%%") {
+            continue;
+        }
+        let keyword = line.split(|c: char| c.is_whitespace() || c == ':' || c == ';').next().unwrap_or(line);
+        let lower = keyword.to_ascii_lowercase();
+        if !DIAGRAM_KEYWORDS.iter().any(|known| lower.starts_with(known)) {
+            return Err(MermaidError {
+                message: format!("`{}` diagrams aren't supported by the native renderer", keyword),
+                panicked: false,
+                line: Some(i + 1),
+            });
+        }
+        return Ok(match lower.as_str() {
+            "flowchart" | "graph" => DiagramKind::Flowchart,
+            "sequencediagram" => DiagramKind::Sequence,
+            "classdiagram" => DiagramKind::Class,
+            _ if lower.starts_with("statediagram") => DiagramKind::State,
+            _ => DiagramKind::Other,
+        });
+    }
+    Err(MermaidError::new("empty diagram".to_string()))
+}
+
 /// Preprocess mermaid source to fix known incompatibilities with mermaid-rs-renderer.
 /// This increases the success rate of the native Rust renderer across all backends.
-fn preprocess_mermaid_source(source: &str) -> String {
+fn preprocess_mermaid_source(source: &str, kind: DiagramKind) -> String {
     let mut result = String::with_capacity(source.len());
     for line in source.lines() {
-        let processed = line
-            // Replace HTML line breaks in node labels with spaces
-            .replace("<br/>", " ")
-            .replace("<br>", " ")
-            .replace("<br />", " ")
+        // Replace HTML line breaks in labels and messages with spaces
+        let mut processed = line.replace("<br/>", " ").replace("<br>", " ").replace("<br />", " ");
+        if kind == DiagramKind::Flowchart {
             // Replace bidirectional arrows (not supported) with unidirectional
-            .replace("<-->", "---")
-            .replace("x--x", "---")
-            .replace("o--o", "---");
+            processed = processed.replace("<-->", "---").replace("x--x", "---").replace("o--o", "---");
+        }
         result.push_str(&processed);
         result.push('\n');
     }
     result
 }
 
+/// The renderer's layout engine crashes on labelled edges in flowcharts, state and
+/// class diagrams. This rewrites each labelled edge into a small node carrying the
+/// label, joined to both ends. `None` if there was nothing to rewrite.
+fn labels_as_nodes(source: &str, kind: DiagramKind) -> Option<String> {
+    use std::sync::OnceLock;
+    // `A -->|label| B` and `A -- label --> B` (also `==`, `-.` and `.->` styles)
+    static FLOW_PIPE: OnceLock<Regex> = OnceLock::new();
+    static FLOW_TEXT: OnceLock<Regex> = OnceLock::new();
+    // `A --> B : label`
+    static STATE: OnceLock<Regex> = OnceLock::new();
+    // `A "1" <|-- "*" B : label`
+    static CLASS: OnceLock<Regex> = OnceLock::new();
+    let flow_pipe = FLOW_PIPE.get_or_init(|| {
+        Regex::new(r"^(\s*)(\S.*?)\s*([<ox]?(?:-{2,}|={2,}|-\.+-)[->ox]?)\s*\|([^|]*)\|\s*(\S.*?)\s*;?$").unwrap()
+    });
+    let flow_text = FLOW_TEXT.get_or_init(|| {
+        Regex::new(r"^(\s*)(\S.*?)\s+(--|==|-\.)\s+([^|]+?)\s+(-{2,}[->ox]|={2,}[=>]|\.-+>?)\s*(\S.*?)\s*;?$").unwrap()
+    });
+    let state = STATE.get_or_init(|| Regex::new(r"^(\s*)(\S+)\s*-->\s*(\S+)\s*:\s*(.+?)\s*$").unwrap());
+    let class = CLASS.get_or_init(|| {
+        Regex::new(r#"^(\s*)(\w+)\s*(?:"([^"]*)"\s*)?(<\||\*|o|<)?(--|\.\.)(\|>|\*|o|>)?\s*(?:"([^"]*)"\s*)?(\w+)\s*(?::\s*(.+?))?\s*$"#).unwrap()
+    });
+
+    let mut labels = 0;
+    let mut next_label = || {
+        labels += 1;
+        format!("mdrLabel{}", labels)
+    };
+    let mut result = String::with_capacity(source.len());
+    for line in source.lines() {
+        let rewritten = match kind {
+            DiagramKind::Flowchart => {
+                if let Some(caps) = flow_pipe.captures(line) {
+                    let arrow = &caps[3];
+                    let line_style = if arrow.contains('=') { "===" } else if arrow.contains('.') { "-.-" } else { "---" };
+                    let label = caps[4].trim().trim_matches('"');
+                    let node = next_label();
+                    Some(format!(
+                        "{indent}{} {} {node}[\"{}\"]\n{indent}{node} {} {}",
+                        &caps[2], line_style, label.replace('"', "#quot;"), arrow.trim_start_matches(['<', 'o', 'x']), &caps[5],
+                        indent = &caps[1],
+                    ))
+                } else if let Some(caps) = flow_text.captures(line) {
+                    let (line_style, arrow) = match &caps[3] {
+                        "==" => ("===", caps[5].to_string()),
+                        "-." => ("-.-", format!("-{}", &caps[5])),
+                        _ => ("---", caps[5].to_string()),
+                    };
+                    let node = next_label();
+                    Some(format!(
+                        "{indent}{} {} {node}[\"{}\"]\n{indent}{node} {} {}",
+                        &caps[2], line_style, caps[4].trim_matches('"').replace('"', "#quot;"), arrow, &caps[6],
+                        indent = &caps[1],
+                    ))
+                } else {
+                    None
+                }
+            }
+            DiagramKind::State => state.captures(line).map(|caps| {
+                let node = next_label();
+                format!(
+                    "{indent}{} --> {node}\n{indent}{node} --> {}\n{indent}state \"{}\" as {node}",
+                    &caps[2], &caps[3], caps[4].replace('"', "'"),
+                    indent = &caps[1],
+                )
+            }),
+            DiagramKind::Class => class.captures(line).and_then(|caps| {
+                let text: Vec<&str> = [3, 9, 7].iter().filter_map(|&i| caps.get(i)).map(|m| m.as_str().trim()).collect();
+                if text.is_empty() {
+                    return None;
+                }
+                let node = next_label();
+                let (head, body, tail) = (
+                    caps.get(4).map_or("", |m| m.as_str()),
+                    &caps[5],
+                    caps.get(6).map_or("", |m| m.as_str()),
+                );
+                Some(format!(
+                    "{indent}{} {head}{body} {node}\n{indent}{node} {body}{tail} {}\n{indent}class {node}[\"{}\"]",
+                    &caps[2], &caps[8], text.join(" ").replace('"', "'"),
+                    indent = &caps[1],
+                ))
+            }),
+            DiagramKind::Sequence | DiagramKind::Other => None,
+        };
+        result.push_str(rewritten.as_deref().unwrap_or(line));
+        result.push('\n');
+    }
+    (labels > 0).then_some(result)
+}
+
+/// Why the native renderer could not draw a diagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MermaidError {
+    pub message: String,
+    /// The renderer crashed on the input rather than rejecting it.
+    pub panicked: bool,
+    /// 1-based line of the diagram source where rendering starts to fail, if it
+    /// could be pinned down.
+    pub line: Option<usize>,
+}
+
+impl std::error::Error for MermaidError {}
+
+impl MermaidError {
+    fn new(message: String) -> Self {
+        MermaidError { message, panicked: false, line: None }
+    }
+}
+
+impl fmt::Display for MermaidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.panicked {
+            write!(f, "renderer crashed: {}", self.message)?;
+        } else {
+            write!(f, "{}", self.message)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Extensions of files that hold one mermaid diagram. Used as an image, such a file
+/// is drawn as the diagram: `![flow](flow.mmd)`.
+const DIAGRAM_FILE_EXTENSIONS: &[&str] = &["mmd", "mermaid"];
+
+/// Whether `path` is a mermaid diagram file.
+pub fn is_diagram_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| DIAGRAM_FILE_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+/// The path a mermaid block names when all it holds is `@file <path>`.
+fn included_file(source: &str) -> Option<&str> {
+    let path = source.trim().strip_prefix("@file")?;
+    (path.starts_with([' ', '\t']) && !path.contains('\n')).then(|| path.trim())
+}
+
+/// The diagram a mermaid block draws: its own source, or the file it names with
+/// `@file`, relative to `base_dir`. Like local images, the file has to be inside
+/// `base_dir`. Without a `base_dir` the block is taken as written.
+fn block_source<'a>(source: &'a str, base_dir: Option<&Path>) -> Result<Cow<'a, str>, MermaidError> {
+    let (Some(path), Some(base_dir)) = (included_file(source), base_dir) else {
+        return Ok(Cow::Borrowed(source));
+    };
+    let file = base_dir.join(path);
+    if let (Ok(canonical), Ok(canonical_base)) = (file.canonicalize(), base_dir.canonicalize()) {
+        if !canonical.starts_with(&canonical_base) {
+            return Err(MermaidError::new(format!("{} is outside the document's directory", path)));
+        }
+    }
+    read_diagram_file(&file).map(Cow::Owned)
+}
+
+/// Render a mermaid block, reading the diagram from its file first for `@file` blocks.
+/// Along with the result comes the diagram source, for fallbacks to show.
+pub fn render_block<'a>(block: &'a str, base_dir: Option<&Path>) -> (Cow<'a, str>, Result<String, MermaidError>) {
+    match block_source(block, base_dir) {
+        Ok(source) => {
+            let result = render_mermaid_to_svg(&source);
+            (source, result)
+        }
+        Err(error) => (Cow::Borrowed(block), Err(error)),
+    }
+}
+
+/// Read a diagram file, with `\n` line endings and a final newline like the body
+/// of a fenced block.
+fn read_diagram_file(file: &Path) -> Result<String, MermaidError> {
+    std::fs::read_to_string(file)
+        .map(|source| {
+            let mut source = source.replace("\r\n", "\n");
+            if !source.ends_with('\n') {
+                source.push('\n');
+            }
+            source
+        })
+        .map_err(|e| MermaidError::new(format!("could not read {}: {}", file.display(), e)))
+}
+
+/// Render the diagram file at `file` to SVG.
+pub fn render_diagram_file(file: &Path) -> Result<String, MermaidError> {
+    render_mermaid_to_svg(&read_diagram_file(file)?)
+}
+
+/// The diagram files `markdown` pulls in, through `@file` blocks or as images,
+/// resolved against `base_dir`. The file watcher reloads when any of them changes.
+pub fn diagram_files(markdown: &str, base_dir: &Path) -> Vec<PathBuf> {
+    use std::sync::OnceLock;
+    static BLOCK_RE: OnceLock<Regex> = OnceLock::new();
+    static IMAGE_RE: OnceLock<Regex> = OnceLock::new();
+    let block_re = BLOCK_RE.get_or_init(|| Regex::new(r"```mermaid[ \t]*\r?\n([^`]*?)```").unwrap());
+    let image_re = IMAGE_RE.get_or_init(|| Regex::new(r"!\[[^\]]*\]\(<?([^)\s>]+)>?[^)]*\)").unwrap());
+
+    let included = block_re.captures_iter(markdown).filter_map(|caps| included_file(caps.get(1)?.as_str()));
+    let images = image_re.captures_iter(markdown)
+        .filter_map(|caps| caps.get(1))
+        .map(|m| m.as_str())
+        .filter(|src| is_diagram_file(Path::new(src)));
+    let mut files: Vec<PathBuf> = Vec::new();
+    for file in included.chain(images).map(|path| base_dir.join(path)) {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    files
+}
+
 /// Render a single mermaid diagram source to SVG.
 /// First preprocesses the source to fix common incompatibilities,
 /// then catches panics from mermaid-rs-renderer (which can panic on some inputs).
 /// Suppresses stderr to prevent panic backtraces from corrupting TUI terminal output.
-pub fn render_mermaid_to_svg(source: &str) -> Result<String, String> {
+pub fn render_mermaid_to_svg(source: &str) -> Result<String, MermaidError> {
     // Suppress stderr during rendering — the mermaid renderer can print panic
     // backtraces/errors to stderr which corrupts the terminal in TUI mode.
     let _stderr_guard = suppress_stderr();
 
+    let kind = diagram_kind(source)?;
     // Try with preprocessed source first (fixes common syntax issues)
-    let preprocessed = preprocess_mermaid_source(source);
-    let preprocessed_clone = preprocessed.clone();
-    match std::panic::catch_unwind(|| mermaid_rs_renderer::render(&preprocessed_clone)) {
-        Ok(Ok(svg)) => return Ok(svg),
-        _ => {}
+    let preprocessed = preprocess_mermaid_source(source, kind);
+    if let Ok(svg) = try_render(&preprocessed) {
+        return Ok(svg);
+    }
+    if let Some(relabelled) = labels_as_nodes(&preprocessed, kind) {
+        if let Ok(svg) = try_render(&relabelled) {
+            return Ok(svg);
+        }
     }
     // Fall back to original source (in case preprocessing made things worse)
-    let source = source.to_string();
-    match std::panic::catch_unwind(|| mermaid_rs_renderer::render(&source)) {
+    try_render(source).map_err(|error| MermaidError { line: failing_line(source), ..error })
+}
+
+/// One attempt at rendering, with panics turned into errors.
+fn try_render(source: &str) -> Result<String, MermaidError> {
+    match std::panic::catch_unwind(|| mermaid_rs_renderer::render(source)) {
         Ok(Ok(svg)) => Ok(svg),
-        Ok(Err(e)) => Err(format!("{}", e)),
-        Err(_) => Err("mermaid renderer panicked (unsupported diagram syntax)".to_string()),
+        Ok(Err(e)) => Err(MermaidError::new(format!("{:#}", e))),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unsupported diagram syntax".to_string());
+            Err(MermaidError { message, panicked: true, line: None })
+        }
     }
 }
 
+/// Diagrams longer than this aren't searched for the line that breaks them.
+const MAX_BISECT_LINES: usize = 200;
+
+/// The renderer reports no positions, so find the first line whose addition makes
+/// the diagram fail by rendering ever longer beginnings of it.
+fn failing_line(source: &str) -> Option<usize> {
+    let lines: Vec<&str> = source.lines().collect();
+    if lines.len() > MAX_BISECT_LINES {
+        return None;
+    }
+    (1..=lines.len()).find(|&count| try_render(&lines[..count].join("\n")).is_err())
+}
+
 /// Temporarily redirect stderr to /dev/null. Restores on drop.
 /// This prevents mermaid-rs-renderer panic output from corrupting TUI display.
 struct StderrGuard {
@@ -87,50 +379,178 @@ fn suppress_stderr() -> StderrGuard {
 
 /// Process HTML from comrak: find mermaid code blocks and replace with rendered SVG.
 /// Mermaid blocks appear as: <pre><code class="language-mermaid">...</code></pre>
-pub fn process_mermaid_blocks(html: &str) -> String {
+/// (with a `data-sourcepos` attribute on `<pre>` when source positions are enabled,
+/// which is carried over to the replacement). `@file` blocks are read from `base_dir`.
+/// Without `render` (`--no-mermaid`) the blocks stay as they are: ordinary code.
+pub fn process_mermaid_blocks(html: &str, render: bool, base_dir: Option<&Path>) -> String {
+    if !render {
+        return html.to_string();
+    }
     use std::sync::OnceLock;
     static RE: OnceLock<Regex> = OnceLock::new();
-    let re = RE.get_or_init(|| Regex::new(r#"<pre><code class="language-mermaid">([\s\S]*?)</code></pre>"#).unwrap());
+    let re = RE.get_or_init(|| {
+        Regex::new(r#"<pre( data-sourcepos="[^"]*")?><code[^>]*\bclass="language-mermaid"[^>]*>([\s\S]*?)</code></pre>"#).unwrap()
+    });
 
     re.replace_all(html, |caps: &regex::Captures| {
-        let source = html_decode(&caps[1]);
-        match render_mermaid_to_svg(&source) {
-            Ok(svg) => format!(r#"<div class="mermaid-diagram">{}</div>"#, svg),
-            Err(_) => format!(
-                r#"<pre class="mermaid">{}</pre>"#,
-                html_encode(&source)
+        let pos = caps.get(1).map_or("", |m| m.as_str());
+        let block = html_decode(&caps[2]);
+        let (source, result) = render_block(&block, base_dir);
+        match result {
+            Ok(svg) => format!(r#"<div class="mermaid-diagram"{}>{}</div>"#, pos, svg),
+            // mermaid.js gets another go at the `.mermaid` block; the listing below
+            // it marks the line the native renderer stumbled on
+            Err(error) => format!(
+                r#"<div class="mermaid-fallback"{}><div class="mermaid-fallback-header"><span class="mermaid-icon">◇</span>Mermaid Diagram: {}</div><pre class="mermaid">{}</pre>{}</div>"#,
+                pos,
+                html_encode(&error.to_string()),
+                html_encode(&source),
+                error.line.map_or_else(String::new, |line| source_listing(&source, line)),
             ),
         }
     })
     .to_string()
 }
 
+/// The diagram source as a code listing with `error_line` (1-based) marked.
+fn source_listing(source: &str, error_line: usize) -> String {
+    let lines: Vec<String> = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i + 1 == error_line {
+                format!(r#"<mark class="mermaid-error-line">{}</mark>"#, html_encode(line))
+            } else {
+                html_encode(line)
+            }
+        })
+        .collect();
+    format!(r#"<pre class="mermaid-source"><code>{}</code></pre>"#, lines.join("\n"))
+}
+
 /// Pre-process markdown for egui: find ```mermaid blocks, render to SVG,
 /// convert to base64 PNG data URI, replace block with image reference.
+/// `@file` blocks are read from `base_dir`.
+/// Without `settings.render_mermaid` (`--no-mermaid`) the blocks are left as code.
 #[cfg(feature = "egui-backend")]
-pub fn preprocess_mermaid_for_egui(markdown: &str) -> String {
+pub fn preprocess_mermaid_for_egui(markdown: &str, settings: &crate::core::RenderSettings, base_dir: &Path) -> String {
+    if !settings.render_mermaid {
+        return markdown.to_string();
+    }
     use std::sync::OnceLock;
     static RE: OnceLock<Regex> = OnceLock::new();
     let re = RE.get_or_init(|| Regex::new(r"```mermaid\n([\s\S]*?)```").unwrap());
 
     re.replace_all(markdown, |caps: &regex::Captures| {
-        let source = &caps[1];
-        match render_mermaid_to_svg(source) {
+        let (source, result) = render_block(&caps[1], Some(base_dir));
+        let source = source.as_ref();
+        match result {
             Ok(svg) => match svg_to_png_base64(&svg) {
-                Ok(b64) => format!("![mermaid diagram](data:image/png;base64,{})", b64),
+                Ok(b64) => {
+                    let data_uri = format!("data:image/png;base64,{}", b64);
+                    crate::core::svg_cache::SVG_SOURCES.insert(&data_uri, &svg);
+                    format!("![mermaid diagram]({})", data_uri)
+                }
                 Err(_) => format!("> **◇ Mermaid Diagram** *(SVG to PNG conversion failed)*\n\n```\n{}```", source),
             },
-            Err(_) => format!("> **◇ Mermaid Diagram** *(unsupported by native renderer)*\n\n```\n{}```", source),
+            Err(error) => {
+                if settings.mermaid_fallback {
+                    match render_with_mmdc(source) {
+                        Ok(b64) => return format!("![mermaid diagram](data:image/png;base64,{})", b64),
+                        Err(e) => crate::vlog!("mermaid: mmdc fallback failed: {}", e),
+                    }
+                }
+                let at = match error.line.and_then(|line| source.lines().nth(line - 1)) {
+                    Some(text) => format!("\n>\n> Line {}: `` {} ``", error.line.unwrap_or_default(), text.trim()),
+                    None => String::new(),
+                };
+                format!("> **◇ Mermaid Diagram** *(not rendered: {})*{}\n\n```\n{}```", error, at, source)
+            }
         }
     })
     .to_string()
 }
 
+/// Render a diagram file for egui as a PNG data URI, remembering its SVG for zooming.
+#[cfg(feature = "egui-backend")]
+pub fn diagram_file_to_png_data_uri(file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let svg = render_diagram_file(file)?;
+    let data_uri = format!("data:image/png;base64,{}", svg_to_png_base64(&svg)?);
+    crate::core::svg_cache::SVG_SOURCES.insert(&data_uri, &svg);
+    Ok(data_uri)
+}
+
+/// Render `source` with the mermaid CLI for `--mermaid-fallback`, as a base64 PNG.
+/// Every run starts a headless browser, so results are cached by diagram source.
+#[cfg(feature = "egui-backend")]
+fn render_with_mmdc(source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use crate::core::svg_cache::SvgCache;
+    static CACHE: SvgCache<String> = SvgCache::new();
+    let program = if cfg!(target_os = "windows") { "mmdc.cmd" } else { "mmdc" };
+    CACHE.get_or_insert_with(source, 1.0, || run_mmdc(program, source))
+}
+
+/// Run `program` (mmdc, or a stand-in with the same arguments) on `source`.
+#[cfg(feature = "egui-backend")]
+fn run_mmdc(program: &str, source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use base64::Engine;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    source.hash(&mut hasher);
+    let dir = std::env::temp_dir().join(format!("mdr-mmdc-{}-{:x}", std::process::id(), hasher.finish()));
+    std::fs::create_dir_all(&dir)?;
+    let input = dir.join("diagram.mmd");
+    let output = dir.join("diagram.png");
+    std::fs::write(&input, source)?;
+
+    let result = std::process::Command::new(program)
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        // A white background keeps the default theme's dark text readable in dark mode
+        .args(["--backgroundColor", "white", "--scale", "2", "--quiet"])
+        .stdin(std::process::Stdio::null())
+        .output();
+    let png: Result<Vec<u8>, Box<dyn std::error::Error>> = match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(format!("{} not found; install it with `npm install -g @mermaid-js/mermaid-cli`", program).into())
+        }
+        Err(e) => Err(e.into()),
+        Ok(run) if !run.status.success() => {
+            let stderr = String::from_utf8_lossy(&run.stderr);
+            Err(format!("{} exited with {}: {}", program, run.status, stderr.trim()).into())
+        }
+        Ok(_) => std::fs::read(&output).map_err(Into::into),
+    };
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(base64::engine::general_purpose::STANDARD.encode(png?))
+}
+
 /// Convert SVG string to PNG and return as base64-encoded string.
 /// Scales down large SVGs to fit within GPU texture limits (max 8192px per side).
+/// Results are cached by SVG content so reloads don't re-rasterize unchanged diagrams.
+#[cfg(feature = "egui-backend")]
+pub(crate) fn svg_to_png_base64(svg: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use crate::core::svg_cache::SvgCache;
+    static CACHE: SvgCache<String> = SvgCache::new();
+    CACHE.get_or_insert_with(svg, 1.0, || render_svg_to_png_base64(svg))
+}
+
 #[cfg(feature = "egui-backend")]
-fn svg_to_png_base64(svg: &str) -> Result<String, Box<dyn std::error::Error>> {
+fn render_svg_to_png_base64(svg: &str) -> Result<String, Box<dyn std::error::Error>> {
     use base64::Engine;
+
+    // Never scale up, only down to fit the texture limit
+    let png_data = rasterize_svg(svg, 1.0)?.0.encode_png()?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(&png_data))
+}
+
+/// Rasterize an SVG at `scale`, lowered as needed to fit within GPU texture
+/// limits (max 8192px per side). Returns the pixmap and the scale actually used.
+#[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+pub fn rasterize_svg(svg: &str, scale: f32) -> Result<(tiny_skia::Pixmap, f32), Box<dyn std::error::Error>> {
     use std::sync::{Arc, OnceLock};
 
     // Max texture size for egui/GPU — keep well under the 16384 hard limit
@@ -155,11 +575,11 @@ fn svg_to_png_base64(svg: &str) -> Result<String, Box<dyn std::error::Error>> {
         return Err("SVG has zero dimensions".into());
     }
 
-    // Scale down if either dimension exceeds the limit
+    // Scale down if either dimension would exceed the limit
     let scale = {
         let scale_w = MAX_TEXTURE_SIZE as f32 / svg_w;
         let scale_h = MAX_TEXTURE_SIZE as f32 / svg_h;
-        scale_w.min(scale_h).min(1.0) // never scale up, only down
+        scale_w.min(scale_h).min(scale)
     };
 
     let width = (svg_w * scale) as u32;
@@ -173,9 +593,7 @@ fn svg_to_png_base64(svg: &str) -> Result<String, Box<dyn std::error::Error>> {
         .ok_or("Failed to create pixmap")?;
     let transform = tiny_skia::Transform::from_scale(scale, scale);
     resvg::render(&tree, transform, &mut pixmap.as_mut());
-
-    let png_data = pixmap.encode_png()?;
-    Ok(base64::engine::general_purpose::STANDARD.encode(&png_data))
+    Ok((pixmap, scale))
 }
 
 fn html_decode(s: &str) -> String {
@@ -240,7 +658,7 @@ mod tests {
     #[test]
     fn preprocess_removes_html_breaks() {
         let source = "graph LR\n  A[Line 1<br/>Line 2]-->B";
-        let result = preprocess_mermaid_source(source);
+        let result = preprocess_mermaid_source(source, DiagramKind::Flowchart);
         assert!(!result.contains("<br/>"));
         assert!(result.contains("Line 1 Line 2"));
     }
@@ -248,7 +666,7 @@ mod tests {
     #[test]
     fn preprocess_converts_bidirectional_arrows() {
         let source = "graph LR\n  A<-->B";
-        let result = preprocess_mermaid_source(source);
+        let result = preprocess_mermaid_source(source, DiagramKind::Flowchart);
         assert!(!result.contains("<-->"));
         assert!(result.contains("A---B"));
     }
@@ -256,11 +674,77 @@ mod tests {
     #[test]
     fn preprocess_leaves_valid_syntax_unchanged() {
         let source = "graph LR\n  A-->B\n  B-->C";
-        let result = preprocess_mermaid_source(source);
+        let result = preprocess_mermaid_source(source, DiagramKind::Flowchart);
         assert!(result.contains("A-->B"));
         assert!(result.contains("B-->C"));
     }
 
+    #[test]
+    fn preprocess_keeps_sequence_arrows() {
+        let source = "sequenceDiagram\n  A<<-->>B: sync";
+        assert!(preprocess_mermaid_source(source, DiagramKind::Sequence).contains("A<<-->>B"));
+    }
+
+    #[test]
+    fn diagram_kind_comes_from_the_first_keyword() {
+        assert_eq!(diagram_kind("graph LR\n  A-->B"), Ok(DiagramKind::Flowchart));
+        assert_eq!(diagram_kind("%% comment\n\nsequenceDiagram\n  A->>B: hi"), Ok(DiagramKind::Sequence));
+        assert_eq!(diagram_kind("---\ntitle: Pets\n---\npie title Pets"), Ok(DiagramKind::Other));
+        assert_eq!(diagram_kind("%%{init: {'theme':'dark'}}%%\nstateDiagram-v2"), Ok(DiagramKind::State));
+        let error = diagram_kind("\nzenuml\n  A.b()").unwrap_err();
+        assert_eq!(error.to_string(), "`zenuml` diagrams aren't supported by the native renderer (line 2)");
+    }
+
+    #[test]
+    fn edge_labels_become_nodes() {
+        let flow = labels_as_nodes("graph TD\n  A -->|yes| B\n  A -. maybe .-> C", DiagramKind::Flowchart).unwrap();
+        assert_eq!(
+            flow,
+            "graph TD\n  A --- mdrLabel1[\"yes\"]\n  mdrLabel1 --> B\n  A -.- mdrLabel2[\"maybe\"]\n  mdrLabel2 -.-> C\n"
+        );
+        let state = labels_as_nodes("stateDiagram-v2\n  A --> B : go", DiagramKind::State).unwrap();
+        assert_eq!(state, "stateDiagram-v2\n  A --> mdrLabel1\n  mdrLabel1 --> B\n  state \"go\" as mdrLabel1\n");
+        let class = labels_as_nodes("classDiagram\n  A \"1\" <|-- \"*\" B : has", DiagramKind::Class).unwrap();
+        assert_eq!(class, "classDiagram\n  A <|-- mdrLabel1\n  mdrLabel1 -- B\n  class mdrLabel1[\"1 has *\"]\n");
+        assert_eq!(labels_as_nodes("graph TD\n  A --> B", DiagramKind::Flowchart), None);
+    }
+
+    /// Diagrams as they are commonly pasted: each must either render or fall back
+    /// with a message, never panic.
+    #[test]
+    fn common_diagram_types_render_or_fall_back() {
+        let diagrams = [
+            "sequenceDiagram\n  autonumber\n  participant A as Alice\n  A->>+B: Hello<br/>Bob\n  Note over A,B: a note\n  B-->>-A: Hi\n  loop Every minute\n    A-)B: ping\n  end\n  alt ok\n    A->>B: yes\n  else\n    A->>B: no\n  end",
+            "sequenceDiagram\n  A<<->>B: both ways",
+            "classDiagram\n  class Animal~T~ {\n    <<interface>>\n    +String name\n    +eat(food) bool\n  }\n  Animal <|-- Duck\n  Animal \"1\" --> \"*\" Leg : has\n  note for Duck \"can fly\"",
+            "classDiagram\n  A <|.. B\n  C *-- D : owns\n  E o-- F",
+            "pie title Pets\n  \"Dogs\" : 386\n  \"Cats\" : 85.5",
+            "pie showData\n  title Elements\n  \"Calcium\" : 42.96\n  \"Potassium\" : 50.05",
+            "stateDiagram-v2\n  [*] --> Still\n  Still --> Moving : push\n  Moving --> Crash\n  Crash --> [*]\n  state Moving {\n    [*] --> Slow\n    Slow --> Fast\n  }\n  note right of Crash : boom",
+            "stateDiagram-v2\n  state if <<choice>>\n  S --> if\n  if --> A: yes\n  if --> B: no",
+            CRASHING_DIAGRAM,
+            "zenuml\n  A.b()",
+        ];
+        for source in diagrams {
+            match render_mermaid_to_svg(source) {
+                Ok(svg) => assert!(svg.contains("<svg"), "{}", source),
+                Err(error) => assert!(!error.message.is_empty(), "{}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn labelled_edges_render_natively() {
+        for (source, label) in [
+            ("graph TD\n  A -->|approve| B", "approve"),
+            ("stateDiagram-v2\n  Still --> Moving : push", "push"),
+            ("classDiagram\n  Animal --> Leg : has", "has"),
+        ] {
+            let svg = render_mermaid_to_svg(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+            assert!(svg.contains(label), "{}", source);
+        }
+    }
+
     // --- render_mermaid_to_svg tests ---
 
     #[test]
@@ -276,7 +760,7 @@ mod tests {
             }
             Err(e) => {
                 // If it errors, the error should be descriptive
-                assert!(!e.is_empty(), "Error message should not be empty");
+                assert!(!e.message.is_empty(), "Error message should not be empty");
             }
         }
     }
@@ -295,7 +779,7 @@ mod tests {
         // Result can be Ok or Err but must not panic
         match result {
             Ok(_) => {} // Some renderers may be lenient
-            Err(e) => assert!(!e.is_empty()),
+            Err(e) => assert!(!e.message.is_empty()),
         }
     }
 
@@ -312,7 +796,7 @@ mod tests {
     #[test]
     fn process_mermaid_blocks_no_mermaid() {
         let html = "<p>Hello</p><pre><code class=\"language-rust\">fn main() {}</code></pre>";
-        let result = process_mermaid_blocks(html);
+        let result = process_mermaid_blocks(html, true, None);
         assert_eq!(result, html);
     }
 
@@ -320,7 +804,7 @@ mod tests {
     fn process_mermaid_blocks_replaces_mermaid_code() {
         let html = r#"<p>Before</p><pre><code class="language-mermaid">graph LR
   A--&gt;B</code></pre><p>After</p>"#;
-        let result = process_mermaid_blocks(html);
+        let result = process_mermaid_blocks(html, true, None);
         // The mermaid code block should be replaced
         assert!(!result.contains(r#"class="language-mermaid""#),
             "Mermaid code block should be replaced, got: {}", result);
@@ -338,7 +822,7 @@ mod tests {
     #[test]
     fn process_mermaid_blocks_preserves_non_mermaid_content() {
         let html = "<h1>Title</h1><p>Content</p>";
-        let result = process_mermaid_blocks(html);
+        let result = process_mermaid_blocks(html, true, None);
         assert_eq!(result, html);
     }
 
@@ -346,7 +830,7 @@ mod tests {
     fn process_mermaid_blocks_error_contains_source() {
         // Use obviously invalid mermaid that will produce an error
         let html = r#"<pre><code class="language-mermaid">not valid %%% !@#</code></pre>"#;
-        let result = process_mermaid_blocks(html);
+        let result = process_mermaid_blocks(html, true, None);
         if result.contains("mermaid-fallback") {
             // Fallback div should contain the original source
             assert!(result.contains("Mermaid Diagram"));
@@ -356,23 +840,98 @@ mod tests {
         // If it somehow renders successfully, that's also fine
     }
 
+    /// Crashes mermaid-rs-renderer 0.1 on its second line.
+    const CRASHING_DIAGRAM: &str = "erDiagram\n  A ||--o{ B : has";
+
+    #[test]
+    fn render_mermaid_crash_reports_the_panic_and_line() {
+        let error = render_mermaid_to_svg(CRASHING_DIAGRAM).unwrap_err();
+        assert!(error.panicked);
+        assert!(error.message.contains("unwrap()"), "{:?}", error);
+        assert_eq!(error.line, Some(2));
+        assert!(error.to_string().starts_with("renderer crashed: "));
+        assert!(error.to_string().ends_with(" (line 2)"));
+    }
+
+    #[test]
+    fn process_mermaid_blocks_fallback_shows_the_error_and_marks_the_line() {
+        let html = format!(r#"<pre><code class="language-mermaid">{}</code></pre>"#, html_encode(CRASHING_DIAGRAM));
+        let result = process_mermaid_blocks(&html, true, None);
+        assert!(result.contains("Mermaid Diagram: renderer crashed: "), "{}", result);
+        assert!(result.contains(r#"<pre class="mermaid">erDiagram"#), "{}", result);
+        assert!(result.contains(r#"<mark class="mermaid-error-line">  A ||--o{ B : has</mark>"#), "{}", result);
+    }
+
+    #[test]
+    fn process_mermaid_blocks_without_rendering_keeps_the_code() {
+        let html = r#"<pre data-sourcepos="1:1-3:3"><code class="language-mermaid">graph LR
+  A--&gt;B</code></pre>"#;
+        assert_eq!(process_mermaid_blocks(html, false, None), html);
+    }
+
+    #[test]
+    fn included_file_needs_a_lone_file_line() {
+        assert_eq!(included_file("@file diagrams/flow.mmd\n"), Some("diagrams/flow.mmd"));
+        assert_eq!(included_file("  @file\tflow.mmd  "), Some("flow.mmd"));
+        assert_eq!(included_file("@filed flow.mmd"), None);
+        assert_eq!(included_file("@file flow.mmd\ngraph TD"), None);
+        assert_eq!(included_file("graph TD\n  A-->B"), None);
+    }
+
+    #[test]
+    fn file_blocks_are_read_from_the_base_dir() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_mermaid_include_{}", std::process::id()));
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("flow.mmd"), "graph LR\r\n  A-->B").unwrap();
+        std::fs::write(dir.join("secret.mmd"), "graph LR\n  X-->Y\n").unwrap();
+
+        let (source, result) = render_block("@file flow.mmd\n", Some(&docs));
+        assert_eq!(source, "graph LR\n  A-->B\n");
+        assert!(result.is_ok(), "{:?}", result);
+
+        let (source, result) = render_block("@file missing.mmd\n", Some(&docs));
+        assert_eq!(source, "@file missing.mmd\n");
+        assert!(result.unwrap_err().message.starts_with("could not read "));
+
+        let error = render_block("@file ../secret.mmd", Some(&docs)).1.unwrap_err();
+        assert_eq!(error.message, "../secret.mmd is outside the document's directory");
+
+        let html = r#"<pre data-sourcepos="3:1-5:3"><code class="language-mermaid">@file flow.mmd
+</code></pre>"#;
+        let result = process_mermaid_blocks(html, true, Some(&docs));
+        assert!(result.starts_with(r#"<div class="mermaid-diagram" data-sourcepos="3:1-5:3"><svg"#), "{}", result);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diagram_files_lists_file_blocks_and_images() {
+        let md = "```mermaid\n@file a.mmd\n```\n\n![flow](img/b.mermaid \"Flow\") ![logo](logo.png)\n\n```mermaid\ngraph TD\n```\n\n![again](a.mmd)\n";
+        let base = Path::new("/docs");
+        assert_eq!(diagram_files(md, base), vec![base.join("a.mmd"), base.join("img/b.mermaid")]);
+        assert!(is_diagram_file(Path::new("x.MMD")));
+        assert!(!is_diagram_file(Path::new("x.md")));
+    }
+
     // --- egui-specific tests ---
 
     #[cfg(feature = "egui-backend")]
     mod egui_tests {
         use super::super::*;
+        use crate::core::RenderSettings;
 
         #[test]
         fn preprocess_mermaid_for_egui_no_mermaid() {
             let md = "# Title\n\nSome text\n\n```rust\nfn main() {}\n```";
-            let result = preprocess_mermaid_for_egui(md);
+            let result = preprocess_mermaid_for_egui(md, &RenderSettings::default(), Path::new("."));
             assert_eq!(result, md);
         }
 
         #[test]
         fn preprocess_mermaid_for_egui_replaces_block() {
             let md = "Before\n\n```mermaid\ngraph LR\n  A-->B\n```\n\nAfter";
-            let result = preprocess_mermaid_for_egui(md);
+            let result = preprocess_mermaid_for_egui(md, &RenderSettings::default(), Path::new("."));
             // The mermaid block should be replaced with either an image or error message
             assert!(!result.contains("```mermaid"),
                 "Mermaid block should be replaced, got: {}", result);
@@ -383,10 +942,68 @@ mod tests {
         #[test]
         fn preprocess_mermaid_for_egui_error_shows_source() {
             let md = "```mermaid\nnot valid mermaid\n```";
-            let result = preprocess_mermaid_for_egui(md);
+            let result = preprocess_mermaid_for_egui(md, &RenderSettings::default(), Path::new("."));
             if result.contains("error") || result.contains("Error") {
                 assert!(result.contains("not valid mermaid"));
             }
         }
+
+        #[test]
+        fn preprocess_mermaid_for_egui_error_names_the_line() {
+            let md = format!("```mermaid\n{}\n```", super::CRASHING_DIAGRAM);
+            let result = preprocess_mermaid_for_egui(&md, &RenderSettings::default(), Path::new("."));
+            assert!(result.contains("*(not rendered: renderer crashed: "), "{}", result);
+            assert!(result.contains("> Line 2: `` A ||--o{ B : has ``"), "{}", result);
+        }
+
+        #[test]
+        fn preprocess_mermaid_for_egui_without_rendering_keeps_the_code() {
+            let md = "```mermaid\ngraph LR\n  A-->B\n```\n";
+            assert_eq!(preprocess_mermaid_for_egui(md, &RenderSettings { render_mermaid: false, ..RenderSettings::default() }, Path::new(".")), md);
+        }
+
+        #[test]
+        fn preprocess_mermaid_for_egui_remembers_the_svg() {
+            let result = preprocess_mermaid_for_egui("```mermaid\ngraph LR\n  A-->B\n```\n", &RenderSettings::default(), Path::new("."));
+            if let Some(start) = result.find("data:image/png") {
+                let data_uri = &result[start..result.rfind(')').unwrap()];
+                let svg = crate::core::svg_cache::SVG_SOURCES.get(data_uri).expect("svg source recorded");
+                assert!(svg.contains("<svg"));
+            }
+        }
+
+        #[test]
+        fn mmdc_missing_says_how_to_install_it() {
+            let error = run_mmdc("mdr-no-such-mmdc", "graph LR\n  A-->B").unwrap_err();
+            assert!(error.to_string().contains("@mermaid-js/mermaid-cli"), "{}", error);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn mmdc_output_is_read_back_as_base64() {
+            use base64::Engine;
+            use std::os::unix::fs::PermissionsExt;
+
+            // A stand-in that copies its input to the --output path
+            let dir = std::env::temp_dir().join(format!("mdr_test_fake_mmdc_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let script = dir.join("mmdc");
+            std::fs::write(&script, "#!/bin/sh\ncp \"$2\" \"$4\"\n").unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let b64 = run_mmdc(script.to_str().unwrap(), "erDiagram").unwrap();
+            let output = base64::engine::general_purpose::STANDARD.decode(b64).unwrap();
+            assert_eq!(output, b"erDiagram");
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn rasterize_svg_scales_up_to_the_texture_limit() {
+            let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50"><rect width="100" height="50"/></svg>"#;
+            let (pixmap, scale) = rasterize_svg(svg, 3.0).unwrap();
+            assert_eq!((pixmap.width(), pixmap.height(), scale), (300, 150, 3.0));
+            let (pixmap, scale) = rasterize_svg(svg, 1000.0).unwrap();
+            assert_eq!((pixmap.width(), scale), (8192, 81.92));
+        }
     }
 }
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How fresh a document is, for `--show-meta`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocMeta {
+    /// When the file was last written.
+    pub modified: Option<SystemTime>,
+    /// The last commit that touched the file, if it is tracked by git.
+    pub commit: Option<Commit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// Abbreviated commit hash.
+    pub hash: String,
+    pub author: String,
+    pub time: SystemTime,
+}
+
+impl DocMeta {
+    /// Read the modification time of `path` and ask git for its last commit.
+    /// Outside a repository, or without git installed, there is no commit.
+    pub fn gather(path: &Path) -> Self {
+        let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        DocMeta { modified, commit: last_commit(path) }
+    }
+}
+
+impl fmt::Display for DocMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now = SystemTime::now();
+        let mut parts = Vec::new();
+        if let Some(modified) = self.modified {
+            parts.push(format!("modified {}", age(now.duration_since(modified).unwrap_or_default())));
+        }
+        if let Some(commit) = &self.commit {
+            let since = now.duration_since(commit.time).unwrap_or_default();
+            parts.push(format!("{} by {}, {}", commit.hash, commit.author, age(since)));
+        }
+        write!(f, "{}", parts.join(" · "))
+    }
+}
+
+/// The last commit touching `path`, from `git log` run in the file's directory.
+fn last_commit(path: &Path) -> Option<Commit> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["log", "-1", "--format=%h%x00%an%x00%ct", "--"])
+        .arg(path.file_name()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        crate::vlog!("meta: no git history for {}", path.display());
+        return None;
+    }
+    parse_log(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the `hash NUL author NUL unix-time` line printed by [`last_commit`]'s
+/// `git log`. Untracked files print nothing.
+fn parse_log(output: &str) -> Option<Commit> {
+    let mut fields = output.trim_end().split('\0');
+    let hash = fields.next().filter(|hash| !hash.is_empty())?.to_string();
+    let author = fields.next()?.to_string();
+    let seconds = fields.next()?.parse().ok()?;
+    Some(Commit { hash, author, time: UNIX_EPOCH + Duration::from_secs(seconds) })
+}
+
+/// A rough, human-sized rendering of how long ago something happened.
+fn age(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (count, unit) = match secs {
+        0..60 => return "just now".to_string(),
+        60..3600 => (secs / 60, "min"),
+        3600..86_400 => (secs / 3600, "hour"),
+        86_400..2_592_000 => (secs / 86_400, "day"),
+        2_592_000..31_536_000 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    let plural = if count == 1 || unit == "min" { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_read_naturally() {
+        assert_eq!(age(Duration::from_secs(5)), "just now");
+        assert_eq!(age(Duration::from_secs(150)), "2 min ago");
+        assert_eq!(age(Duration::from_secs(3600)), "1 hour ago");
+        assert_eq!(age(Duration::from_secs(3 * 86_400)), "3 days ago");
+        assert_eq!(age(Duration::from_secs(400 * 86_400)), "1 year ago");
+    }
+
+    #[test]
+    fn git_log_output_is_parsed() {
+        let commit = parse_log("a1b2c3d\0Ada Lovelace\x001700000000\n").unwrap();
+        assert_eq!(commit.hash, "a1b2c3d");
+        assert_eq!(commit.author, "Ada Lovelace");
+        assert_eq!(commit.time, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(parse_log(""), None);
+
+        let meta = DocMeta { modified: None, commit: Some(commit) };
+        assert!(meta.to_string().starts_with("a1b2c3d by Ada Lovelace, "), "{}", meta);
+    }
+
+    #[test]
+    fn files_outside_git_still_get_their_modification_time() {
+        let path = std::env::temp_dir().join(format!("mdr_test_meta_{}.md", std::process::id()));
+        std::fs::write(&path, "# Fresh\n").unwrap();
+        let meta = DocMeta::gather(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(meta.modified.is_some());
+        assert!(meta.to_string().starts_with("modified just now"), "{}", meta);
+    }
+}
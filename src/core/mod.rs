@@ -1,11 +1,38 @@
+pub mod check;
+pub mod config;
+pub mod details;
+pub mod diagram_export;
+pub mod diagram_tools;
+pub mod editor;
+pub mod entities;
+pub mod highlight;
+#[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+pub mod history;
 pub mod icon;
+pub mod io;
 pub mod markdown;
+#[cfg(feature = "tui-backend")]
+pub mod math;
+pub mod meta;
 pub mod mermaid;
+#[cfg(feature = "tui-backend")]
+pub mod png_export;
+pub mod recent;
+pub mod render_failure;
 pub mod search;
+pub mod stats;
+pub mod svg_cache;
+#[cfg(any(feature = "egui-backend", feature = "webview-backend"))]
+pub mod tab;
+#[cfg(feature = "tui-backend")]
+pub mod theme;
 pub mod toc;
 pub mod watcher;
+pub mod window;
 
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 static VERBOSE: AtomicBool = AtomicBool::new(false);
 
@@ -17,6 +44,136 @@ pub fn verbose() -> bool {
     VERBOSE.load(Ordering::Relaxed)
 }
 
+/// How documents are drawn, gathered from the settings file and the command line in
+/// one place and handed by reference to the backend and from there to the renderers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// Color theme (`--theme`).
+    pub theme: config::Theme,
+    /// Draw the TUI without colors (`--no-color`).
+    pub no_color: bool,
+    /// Show the highlighted markdown source instead of rendering it (`--raw`).
+    pub raw: bool,
+    /// Columns a tab in a code block expands to.
+    pub tab_width: usize,
+    /// Give fenced code blocks a line-number gutter.
+    pub code_line_numbers: bool,
+    /// Deepest heading level the table of contents shows (1-6).
+    pub toc_depth: usize,
+    /// Let bold lines stand in for headings in the table of contents of documents
+    /// that have almost none (`--toc-heuristic`).
+    pub toc_heuristic: bool,
+    /// Draw mermaid blocks as diagrams rather than code (off with `--no-mermaid`).
+    pub render_mermaid: bool,
+    /// Hand diagrams the native renderer can't draw to the mermaid CLI (`--mermaid-fallback`).
+    pub mermaid_fallback: bool,
+    /// Code blocks drawn by external programs (`--enable`).
+    pub diagram_tools: Vec<diagram_tools::DiagramTool>,
+    /// Take an italic line under an image as its caption (off with `--no-image-captions`).
+    pub image_captions: bool,
+    /// Show the file's modification time and last git commit (`--show-meta`).
+    pub show_meta: bool,
+    /// Link `#123`, `@user` and commit hashes to this repository (`--repo-url`).
+    pub repo_url: Option<String>,
+    /// Text attributes the TUI leaves out.
+    pub disabled_attributes: config::DisabledAttributes,
+    /// Start with everything but the section being read dimmed (`--focus`).
+    pub focus_mode: bool,
+    /// Let table columns be sorted by clicking their headers (`--sortable-tables`, webview).
+    pub sortable_tables: bool,
+    /// Show how far down the document the view is along the top (off with `--no-progress-bar`, webview).
+    pub progress_bar: bool,
+    /// How the webview scrolls to headings and search matches (`--scroll-behavior`);
+    /// instant when the system asks for reduced motion either way.
+    pub scroll_behavior: config::ScrollBehavior,
+    /// List, task and blockquote markers the TUI draws (`--ascii` and the glyph flags).
+    #[cfg(feature = "tui-backend")]
+    pub glyphs: theme::Glyphs,
+    /// Terminal image protocol for the TUI (`--image-protocol`).
+    pub image_protocol: config::ImageProtocol,
+    /// Keep the view at the end of the document as the file grows (`--follow`).
+    pub follow: bool,
+    /// How long the TUI waits for input before checking for file changes (`--tui-poll`).
+    pub poll_interval: Duration,
+    /// Drop the TUI panel borders and hide the TOC until it is focused (`--compact`).
+    pub compact: bool,
+    /// Ease TUI page, heading and search jumps over a few frames (`--tui-smooth-scroll`).
+    pub tui_smooth_scroll: bool,
+    /// Directory relative image paths resolve against for every file, instead of
+    /// the one each file is in (`--base-dir`).
+    pub base_dir: Option<PathBuf>,
+    /// Editor to open files in instead of `$VISUAL` / `$EDITOR` (`--editor`).
+    pub editor: Option<String>,
+    /// How long the file watcher waits for writes to settle before reloading.
+    pub reload_debounce: Duration,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            theme: config::Theme::Auto,
+            no_color: false,
+            raw: false,
+            tab_width: 4,
+            code_line_numbers: false,
+            toc_depth: 6,
+            toc_heuristic: false,
+            render_mermaid: true,
+            mermaid_fallback: false,
+            diagram_tools: Vec::new(),
+            image_captions: true,
+            show_meta: false,
+            repo_url: None,
+            disabled_attributes: config::DisabledAttributes::default(),
+            focus_mode: false,
+            sortable_tables: false,
+            progress_bar: true,
+            scroll_behavior: config::ScrollBehavior::Smooth,
+            #[cfg(feature = "tui-backend")]
+            glyphs: theme::Glyphs::default(),
+            image_protocol: config::ImageProtocol::Auto,
+            follow: false,
+            poll_interval: Duration::from_millis(100),
+            compact: false,
+            tui_smooth_scroll: false,
+            base_dir: None,
+            editor: None,
+            reload_debounce: Duration::from_millis(300),
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The TUI theme to use: `monochrome` with `--no-color`, else `--theme`.
+    pub fn tui_theme(&self) -> config::Theme {
+        if self.no_color { config::Theme::Monochrome } else { self.theme }
+    }
+
+    /// The directory relative image paths in `file` resolve against: `base_dir`,
+    /// or else the absolute directory holding `file`.
+    pub fn base_dir(&self, file: &Path) -> PathBuf {
+        if let Some(dir) = &self.base_dir {
+            return dir.clone();
+        }
+        // Canonicalize first: a bare "README.md" has an empty parent, which would
+        // resolve images against whatever the working directory happens to be
+        let canonical_file = std::fs::canonicalize(file)
+            .unwrap_or_else(|_| {
+                std::env::current_dir()
+                    .map(|cwd| cwd.join(file))
+                    .unwrap_or_else(|_| file.to_path_buf())
+            });
+        canonical_file.parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+    }
+}
+
+/// Whether the `NO_COLOR` environment variable asks for output without colors.
+pub fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
 /// Log a message if verbose mode is enabled.
 #[macro_export]
 macro_rules! vlog {
@@ -0,0 +1,208 @@
+use ratatui::style::{Color, Modifier};
+use ratatui::text::Line;
+
+/// Width and height of one character cell, in pixels.
+const CELL_WIDTH: f32 = 8.4;
+const CELL_HEIGHT: f32 = 20.0;
+const FONT_SIZE: f32 = 14.0;
+/// Distance from the top of a cell to the text baseline.
+const BASELINE: f32 = 15.0;
+/// Margin around the text, in pixels.
+const PADDING: f32 = 16.0;
+/// Rows rasterized at a time, keeping each tile well inside the rasterizer's size limit.
+const TILE_ROWS: usize = 200;
+/// Longest document written out; the rest is replaced by a note.
+const MAX_ROWS: usize = 800;
+
+const FONT_FAMILY: &str = "DejaVu Sans Mono, Menlo, Consolas, Liberation Mono, monospace";
+
+/// Draw rendered lines as a single tall PNG, `columns` cells wide, in a monospace
+/// font on a light or dark background. Longer lines are cut off like in a terminal,
+/// and documents over [`MAX_ROWS`] rows are cut short with a note saying so.
+pub fn render_png(lines: &[Line], columns: usize, light: bool) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (background, foreground) = if light { ("#ffffff", "#1f2328") } else { ("#0d1117", "#e6edf3") };
+    let mut rows: Vec<Line> = lines.iter().take(MAX_ROWS).cloned().collect();
+    if lines.len() > MAX_ROWS {
+        rows.push(Line::from(""));
+        rows.push(Line::styled(
+            format!("… {} more lines (PNG export stops at {})", lines.len() - MAX_ROWS, MAX_ROWS),
+            Color::DarkGray,
+        ));
+    }
+
+    let (width, height) = image_size(rows.len().max(1), columns);
+    let mut image = tiny_skia::Pixmap::new(width, height).ok_or("image too large")?;
+    image.fill(parse_hex(background));
+    for (index, tile) in rows.chunks(TILE_ROWS).enumerate() {
+        let svg = tile_svg(tile, columns, background, foreground);
+        let (pixmap, _) = crate::core::mermaid::rasterize_svg(&svg, 1.0)?;
+        let top = PADDING as i32 + (index * TILE_ROWS) as i32 * CELL_HEIGHT as i32;
+        image.draw_pixmap(0, top, pixmap.as_ref(), &tiny_skia::PixmapPaint::default(), tiny_skia::Transform::identity(), None);
+    }
+    Ok(image.encode_png()?)
+}
+
+/// Pixel size of an image holding `rows` rows of `columns` cells, margins included.
+fn image_size(rows: usize, columns: usize) -> (u32, u32) {
+    let width = columns as f32 * CELL_WIDTH + 2.0 * PADDING;
+    let height = rows as f32 * CELL_HEIGHT + 2.0 * PADDING;
+    (width.ceil() as u32, height.ceil() as u32)
+}
+
+/// SVG for a run of rows: full image width, no vertical margin.
+/// Each span is placed at its own column so font metrics can't push text out of line.
+fn tile_svg(rows: &[Line], columns: usize, background: &str, foreground: &str) -> String {
+    let (width, _) = image_size(0, columns);
+    let height = rows.len() as f32 * CELL_HEIGHT;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}"><rect width="{w}" height="{h}" fill="{background}"/><g font-family="{FONT_FAMILY}" font-size="{FONT_SIZE}" xml:space="preserve">"#,
+        w = width,
+        h = height,
+    );
+    for (row, line) in rows.iter().enumerate() {
+        let top = row as f32 * CELL_HEIGHT;
+        let mut column = 0;
+        for span in &line.spans {
+            let cells = span.width();
+            let x = PADDING + column as f32 * CELL_WIDTH;
+            column += cells;
+            let style = line.style.patch(span.style);
+            let mut fg = style.fg.and_then(hex_color).unwrap_or_else(|| foreground.to_string());
+            let mut bg = style.bg.and_then(hex_color);
+            if style.add_modifier.contains(Modifier::REVERSED) {
+                let swapped = bg.unwrap_or_else(|| background.to_string());
+                bg = Some(std::mem::replace(&mut fg, swapped));
+            }
+            if let Some(bg) = bg {
+                svg.push_str(&format!(
+                    r#"<rect x="{x:.1}" y="{top}" width="{:.1}" height="{CELL_HEIGHT}" fill="{bg}"/>"#,
+                    cells as f32 * CELL_WIDTH
+                ));
+            }
+            if span.content.trim().is_empty() {
+                continue;
+            }
+            let mut attrs = format!(r#" x="{x:.1}" y="{}" fill="{fg}""#, top + BASELINE);
+            if style.add_modifier.contains(Modifier::BOLD) {
+                attrs.push_str(r#" font-weight="bold""#);
+            }
+            if style.add_modifier.contains(Modifier::ITALIC) {
+                attrs.push_str(r#" font-style="italic""#);
+            }
+            if style.add_modifier.contains(Modifier::DIM) {
+                attrs.push_str(r#" opacity="0.6""#);
+            }
+            let decoration = match (
+                style.add_modifier.contains(Modifier::UNDERLINED),
+                style.add_modifier.contains(Modifier::CROSSED_OUT),
+            ) {
+                (true, true) => Some("underline line-through"),
+                (true, false) => Some("underline"),
+                (false, true) => Some("line-through"),
+                (false, false) => None,
+            };
+            if let Some(decoration) = decoration {
+                attrs.push_str(&format!(r#" text-decoration="{}""#, decoration));
+            }
+            svg.push_str(&format!("<text{}>{}</text>", attrs, escape_xml(&span.content)));
+        }
+    }
+    svg.push_str("</g></svg>");
+    svg
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Terminal color as `#rrggbb`, using the xterm palette for named and indexed colors.
+/// `None` for the terminal default.
+fn hex_color(color: Color) -> Option<String> {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00), (0xcd, 0x31, 0x31), (0x0d, 0xbc, 0x79), (0xe5, 0xe5, 0x10),
+        (0x24, 0x72, 0xc8), (0xbc, 0x3f, 0xbc), (0x11, 0xa8, 0xcd), (0xe5, 0xe5, 0xe5),
+        (0x66, 0x66, 0x66), (0xf1, 0x4c, 0x4c), (0x23, 0xd1, 0x8b), (0xf5, 0xf5, 0x43),
+        (0x3b, 0x8e, 0xea), (0xd6, 0x70, 0xd6), (0x29, 0xb8, 0xdb), (0xff, 0xff, 0xff),
+    ];
+    let (r, g, b) = match color {
+        Color::Reset => return None,
+        Color::Black => ANSI[0],
+        Color::Red => ANSI[1],
+        Color::Green => ANSI[2],
+        Color::Yellow => ANSI[3],
+        Color::Blue => ANSI[4],
+        Color::Magenta => ANSI[5],
+        Color::Cyan => ANSI[6],
+        Color::Gray => ANSI[7],
+        Color::DarkGray => ANSI[8],
+        Color::LightRed => ANSI[9],
+        Color::LightGreen => ANSI[10],
+        Color::LightYellow => ANSI[11],
+        Color::LightBlue => ANSI[12],
+        Color::LightMagenta => ANSI[13],
+        Color::LightCyan => ANSI[14],
+        Color::White => ANSI[15],
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) if i < 16 => ANSI[i as usize],
+        Color::Indexed(i) if i < 232 => {
+            // 6x6x6 color cube
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let i = i - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        Color::Indexed(i) => {
+            let gray = 8 + (i - 232) * 10;
+            (gray, gray, gray)
+        }
+    };
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+fn parse_hex(hex: &str) -> tiny_skia::Color {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    tiny_skia::Color::from_rgba8(channel(1), channel(3), channel(5), 255)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Style;
+    use ratatui::text::Span;
+
+    #[test]
+    fn png_is_sized_by_columns_and_rows() {
+        let lines = vec![Line::from("# Title"), Line::from(""), Line::from("body")];
+        let png = render_png(&lines, 80, false).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), image_size(3, 80));
+        assert_eq!(image_size(3, 80), (704, 92));
+    }
+
+    #[test]
+    fn long_documents_are_cut_short_with_a_note() {
+        let lines = vec![Line::from("row"); MAX_ROWS + 5];
+        let png = render_png(&lines, 20, true).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.height(), image_size(MAX_ROWS + 2, 20).1);
+    }
+
+    #[test]
+    fn tile_svg_places_styled_spans_by_column() {
+        let line = Line::from(vec![
+            Span::raw("ab"),
+            Span::styled("<c>", Style::default().fg(Color::Red).bg(Color::Rgb(1, 2, 3)).add_modifier(Modifier::BOLD)),
+        ]);
+        let svg = tile_svg(&[line], 10, "#000000", "#ffffff");
+        assert!(svg.contains(r##"<text x="16.0" y="15" fill="#ffffff">ab</text>"##), "{}", svg);
+        assert!(svg.contains(r##"<rect x="32.8" y="0" width="25.2" height="20" fill="#010203"/>"##), "{}", svg);
+        assert!(svg.contains(r##"fill="#cd3131" font-weight="bold">&lt;c&gt;</text>"##), "{}", svg);
+    }
+
+    #[test]
+    fn hex_colors_follow_the_xterm_palette() {
+        assert_eq!(hex_color(Color::Reset), None);
+        assert_eq!(hex_color(Color::Indexed(196)).as_deref(), Some("#ff0000"));
+        assert_eq!(hex_color(Color::Indexed(244)).as_deref(), Some("#808080"));
+        assert_eq!(hex_color(Color::Indexed(1)), hex_color(Color::Red));
+    }
+}
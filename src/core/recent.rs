@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+/// How many recently opened files are remembered.
+const MAX_RECENT: usize = 10;
+
+/// Files opened in the GUI backends, most recent first. Files that have since
+/// been deleted are left out.
+pub fn recent_files() -> Vec<PathBuf> {
+    recent_files_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|list| parse(&list))
+        .unwrap_or_default()
+}
+
+/// Move `file` to the top of the recent files and save the list.
+pub fn add_recent_file(file: &Path) {
+    let Some(path) = recent_files_path() else {
+        return;
+    };
+    let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let list = with_recent(recent_files(), file);
+    let lines: String = list.iter().map(|file| format!("{}\n", file.display())).collect();
+    let result = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|()| std::fs::write(&path, lines));
+    if let Err(e) = result {
+        crate::vlog!("recent: could not save {}: {}", path.display(), e);
+    }
+}
+
+/// Ask for a markdown file in the native file dialog, starting in the directory of
+/// the most recent file. `None` if the dialog was cancelled.
+#[cfg(any(feature = "egui-backend", feature = "webview-backend"))]
+pub fn pick_markdown_file() -> Option<PathBuf> {
+    let mut dialog = rfd::FileDialog::new()
+        .set_title("Open Markdown file")
+        .add_filter("Markdown", &["md", "markdown"])
+        .add_filter("All files", &["*"]);
+    if let Some(dir) = recent_files().first().and_then(|file| file.parent()) {
+        dialog = dialog.set_directory(dir);
+    }
+    dialog.pick_file()
+}
+
+/// `list` with `file` moved (or added) to the front, at most [`MAX_RECENT`] long.
+fn with_recent(mut list: Vec<PathBuf>, file: PathBuf) -> Vec<PathBuf> {
+    list.retain(|recent| *recent != file);
+    list.insert(0, file);
+    list.truncate(MAX_RECENT);
+    list
+}
+
+/// The saved list: one path per line.
+fn parse(list: &str) -> Vec<PathBuf> {
+    list.lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .filter(|file| file.exists())
+        .take(MAX_RECENT)
+        .collect()
+}
+
+fn recent_files_path() -> Option<PathBuf> {
+    Some(super::config::config_dir()?.join("recent-files"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopened_files_move_to_the_top() {
+        let list = vec![PathBuf::from("/a.md"), PathBuf::from("/b.md"), PathBuf::from("/c.md")];
+        assert_eq!(
+            with_recent(list, PathBuf::from("/b.md")),
+            [PathBuf::from("/b.md"), PathBuf::from("/a.md"), PathBuf::from("/c.md")]
+        );
+        let full: Vec<PathBuf> = (0..MAX_RECENT).map(|i| PathBuf::from(format!("/{}.md", i))).collect();
+        let list = with_recent(full, PathBuf::from("/new.md"));
+        assert_eq!(list.len(), MAX_RECENT);
+        assert_eq!(list[0], PathBuf::from("/new.md"));
+        assert_eq!(list[MAX_RECENT - 1], PathBuf::from(format!("/{}.md", MAX_RECENT - 2)));
+    }
+
+    #[test]
+    fn deleted_files_are_forgotten() {
+        let here = std::env::current_dir().unwrap().join("Cargo.toml");
+        let list = format!("{}\n\n/surely/not/here.md\n", here.display());
+        assert_eq!(parse(&list), [here]);
+    }
+}
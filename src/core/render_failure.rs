@@ -0,0 +1,24 @@
+use std::fmt;
+
+use crate::core::mermaid::MermaidError;
+
+/// Something a render fell back on instead of drawing, collected for `--strict`.
+#[derive(Debug)]
+pub enum RenderFailure {
+    /// A mermaid block that didn't render; its source is shown instead.
+    Mermaid(MermaidError),
+    /// An image that is missing or couldn't be decoded; its alt text is shown instead.
+    Image { url: String, error: String },
+    /// A rendered diagram whose SVG couldn't be rasterized.
+    Rasterize(String),
+}
+
+impl fmt::Display for RenderFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderFailure::Mermaid(error) => write!(f, "mermaid diagram not rendered: {}", error),
+            RenderFailure::Image { url, error } => write!(f, "image '{}' not loaded: {}", url, error),
+            RenderFailure::Rasterize(error) => write!(f, "mermaid diagram not rasterized: {}", error),
+        }
+    }
+}
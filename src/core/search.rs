@@ -1,3 +1,8 @@
+use comrak::nodes::NodeValue;
+use comrak::{parse_document, Arena, Options};
+
+use crate::core::toc::TocEntry;
+
 /// Represents a match found in text content.
 pub struct SearchResult {
     pub line_index: usize,
@@ -7,6 +12,12 @@ pub struct SearchResult {
 
 /// Search for a query string in content, returning all matches.
 /// When case_sensitive is false, performs case-insensitive matching.
+///
+/// ```
+/// let matches = mdr::search::search_text("Intro\nRust and rust", "rust", false);
+/// assert_eq!(matches.len(), 2);
+/// assert_eq!((matches[1].line_index, matches[1].byte_offset), (1, 9));
+/// ```
 pub fn search_text(content: &str, query: &str, case_sensitive: bool) -> Vec<SearchResult> {
     if query.is_empty() {
         return Vec::new();
@@ -26,12 +37,91 @@ pub fn search_text(content: &str, query: &str, case_sensitive: bool) -> Vec<Sear
                 byte_offset: start + pos,
                 length: query.len(),
             });
-            start += pos + 1;
+            // Past the matched character, which may take more than one byte
+            start += pos + line[start + pos..].chars().next().map_or(1, char::len_utf8);
         }
     }
     results
 }
 
+/// Matches of `query` in the markdown source for `--grep`, one `line:col: context`
+/// string each (both 1-based, the column counted in characters), in document order. With `skip_code`, matches inside
+/// fenced or indented code blocks are left out.
+///
+/// ```
+/// let lines = mdr::search::grep_lines("# Rust\n\n```\nrust\n```\n", "rust", false, true);
+/// assert_eq!(lines, ["1:3: # Rust"]);
+/// ```
+pub fn grep_lines(content: &str, query: &str, case_sensitive: bool, skip_code: bool) -> Vec<String> {
+    let code = if skip_code { code_block_lines(content) } else { Vec::new() };
+    let lines: Vec<&str> = content.lines().collect();
+    search_text(content, query, case_sensitive)
+        .into_iter()
+        .filter(|result| !code.contains(&result.line_index))
+        .map(|result| {
+            let line = lines[result.line_index];
+            // The offset is into the line as searched, lowercased unless case-sensitive
+            let searched = if case_sensitive { line.to_string() } else { line.to_lowercase() };
+            let column = searched.get(..result.byte_offset).map_or(0, |before| before.chars().count()) + 1;
+            format!("{}:{}: {}", result.line_index + 1, column, line)
+        })
+        .collect()
+}
+
+/// 0-based indices of the source lines that belong to code blocks, fences included.
+fn code_block_lines(content: &str) -> Vec<usize> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, content, &Options::default());
+    root.descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::CodeBlock(_)))
+        .flat_map(|node| {
+            let pos = node.data.borrow().sourcepos;
+            pos.start.line - 1..pos.end.line
+        })
+        .collect()
+}
+
+/// Indices of the `lines` of rendered text that contain `query` (case-insensitive),
+/// ignoring the formatting still left in them: emphasis, code and strikethrough
+/// markers are dropped, table cell bars count as spaces and runs of whitespace
+/// as one, so "bold text" finds `**bold** text` and `bold │ text`.
+///
+/// ```
+/// let lines = ["Some **bold** text", "`code` | cell"];
+/// assert_eq!(mdr::search::search_plain(lines, "bold text"), vec![0]);
+/// assert_eq!(mdr::search::search_plain(lines, "code cell"), vec![1]);
+/// ```
+pub fn search_plain<'a>(lines: impl IntoIterator<Item = &'a str>, query: &str) -> Vec<usize> {
+    let query = plain_text(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+    lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, line)| plain_text(line).contains(&query))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// `text` lowercased without inline markdown markers, with table bars and runs of
+/// whitespace collapsed to single spaces. Underscores inside words are kept.
+fn plain_text(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut plain = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let inside_word = i > 0 && chars[i - 1].is_alphanumeric() && chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+        match c {
+            '*' | '`' | '~' => {}
+            '_' if !inside_word => {}
+            '|' | '│' => plain.push(' '),
+            c if c.is_whitespace() => plain.push(' '),
+            c => plain.extend(c.to_lowercase()),
+        }
+    }
+    plain.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
 /// Find which line indices contain matches (deduplicated).
 pub fn matching_lines(content: &str, query: &str) -> Vec<usize> {
     if query.is_empty() {
@@ -46,6 +136,65 @@ pub fn matching_lines(content: &str, query: &str) -> Vec<usize> {
         .collect()
 }
 
+/// Indices of the TOC entries whose heading text contains `query` (case-insensitive).
+pub fn matching_toc_entries(entries: &[TocEntry], query: &str) -> Vec<usize> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let query_lower = query.to_lowercase();
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.text.to_lowercase().contains(&query_lower))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Score `text` against a fuzzy `query`: every query character must appear in
+/// `text` in order (case-insensitive, whitespace in the query ignored).
+/// Higher is better; consecutive matches and matches at word starts score extra.
+/// Returns None when `text` doesn't contain the query as a subsequence.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev_match: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let is_word_start = |i: usize| i == 0 || !text[i - 1].is_alphanumeric();
+        // Prefer continuing the current run, then the start of a later word
+        let found = if prev_match.is_some() && text.get(pos) == Some(&q) {
+            pos
+        } else {
+            let first = pos + text[pos..].iter().position(|&c| c == q)?;
+            (first..text.len())
+                .find(|&i| text[i] == q && is_word_start(i))
+                .unwrap_or(first)
+        };
+        score += 1;
+        if prev_match.is_some_and(|prev| prev + 1 == found) {
+            score += 5;
+        }
+        if is_word_start(found) {
+            score += 3;
+        }
+        prev_match = Some(found);
+        pos = found + 1;
+    }
+    // Prefer shorter candidates when the match is otherwise equal
+    Some(score * 100 - text.len() as i32)
+}
+
+/// Indices of `candidates` matching `query`, best score first (ties keep input order).
+pub fn fuzzy_rank<'a>(query: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut scored: Vec<(usize, i32)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, text)| fuzzy_score(query, text).map(|score| (i, score)))
+        .collect();
+    scored.sort_by_key(|&(i, score)| (std::cmp::Reverse(score), i));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,6 +245,50 @@ mod tests {
         assert_eq!(results[2].line_index, 2);
     }
 
+    #[test]
+    fn grep_lines_lists_every_match_with_its_column() {
+        let md = "Rust and rust\nnothing here\n  trust\n";
+        assert_eq!(grep_lines(md, "rust", false, false), ["1:1: Rust and rust", "1:10: Rust and rust", "3:4:   trust"]);
+        assert_eq!(grep_lines(md, "Rust", true, false), ["1:1: Rust and rust"]);
+        assert!(grep_lines(md, "missing", false, false).is_empty());
+    }
+
+    #[test]
+    fn grep_lines_handles_non_ascii_queries() {
+        let md = "café éé
+École
+";
+        assert_eq!(grep_lines(md, "é", false, false), ["1:4: café éé", "1:6: café éé", "1:7: café éé", "2:1: École"]);
+        assert_eq!(grep_lines(md, "éé", true, false), ["1:6: café éé"]);
+    }
+
+    #[test]
+    fn grep_lines_can_skip_code_blocks() {
+        let md = "todo: prose\n\n```\n// todo: fenced\n```\n\n    todo: indented\n\n`todo` inline\n";
+        assert_eq!(grep_lines(md, "todo", false, false).len(), 4);
+        assert_eq!(grep_lines(md, "todo", false, true), ["1:1: todo: prose", "9:2: `todo` inline"]);
+    }
+
+    #[test]
+    fn search_plain_ignores_inline_formatting() {
+        let lines = [
+            "**bold** text and *more*",
+            "`inline_code()` call",
+            "~~old~~ new",
+            "Name │ snake_case │ __init__",
+            "| **Key** | Value |",
+        ];
+        assert_eq!(search_plain(lines, "bold text"), vec![0]);
+        assert_eq!(search_plain(lines, "BOLD TEXT AND MORE"), vec![0]);
+        assert_eq!(search_plain(lines, "inline_code() call"), vec![1]);
+        assert_eq!(search_plain(lines, "old new"), vec![2]);
+        assert_eq!(search_plain(lines, "name snake_case"), vec![3]);
+        assert_eq!(search_plain(lines, "snakecase"), Vec::<usize>::new());
+        assert_eq!(search_plain(lines, "init"), vec![3]);
+        assert_eq!(search_plain(lines, "key value"), vec![4]);
+        assert!(search_plain(lines, "**").is_empty());
+    }
+
     #[test]
     fn matching_lines_basic() {
         let lines = matching_lines("foo\nbar\nfoo bar", "foo");
@@ -112,4 +305,30 @@ mod tests {
         let lines = matching_lines("FOO\nbar\nFoo", "foo");
         assert_eq!(lines, vec![0, 2]);
     }
+
+    #[test]
+    fn toc_entries_match_case_insensitively() {
+        let entries = crate::core::toc::extract_headings("# Install\n\n## Configuration\n\n## Config files\n");
+        assert_eq!(matching_toc_entries(&entries, "CONFIG"), vec![1, 2]);
+        assert!(matching_toc_entries(&entries, "").is_empty());
+        assert!(matching_toc_entries(&entries, "usage").is_empty());
+    }
+
+    // --- fuzzy matching tests ---
+
+    #[test]
+    fn fuzzy_score_requires_subsequence() {
+        assert!(fuzzy_score("inst", "Installation").is_some());
+        assert!(fuzzy_score("tkb", "TUI keybindings").is_some());
+        assert!(fuzzy_score("bk", "TUI keybindings").is_none());
+        assert!(fuzzy_score("", "anything").is_some());
+    }
+
+    #[test]
+    fn fuzzy_rank_prefers_contiguous_and_word_start_matches() {
+        let headings = ["Configuration", "Contributing guide", "Usage", "Config"];
+        assert_eq!(fuzzy_rank("config", headings), vec![3, 0]);
+        assert_eq!(fuzzy_rank("cg", headings), vec![1, 3, 0]);
+        assert!(fuzzy_rank("xyz", headings).is_empty());
+    }
 }
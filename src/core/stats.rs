@@ -0,0 +1,140 @@
+use std::fmt;
+
+/// Reading speed assumed when estimating reading time.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Length statistics for a markdown document's prose.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocStats {
+    pub words: usize,
+    /// Characters of prose, excluding line breaks.
+    pub chars: usize,
+    pub reading_minutes: usize,
+}
+
+impl fmt::Display for DocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} words · {} min read", self.words, self.reading_minutes)
+    }
+}
+
+/// Count words and characters in the prose of a document.
+/// Fenced code blocks and a leading front matter block are skipped, and tokens
+/// without any letter or digit (list bullets, `|`, `#`, `--`) don't count as words.
+pub fn document_stats(content: &str) -> DocStats {
+    let mut words = 0;
+    let mut chars = 0;
+    // Opening fence of the code block we're inside: (fence char, length)
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in skip_front_matter(content).lines() {
+        let trimmed = line.trim_start();
+        if let Some((marker, len)) = fence_marker(trimmed) {
+            match fence {
+                None => {
+                    fence = Some((marker, len));
+                    continue;
+                }
+                Some((open, open_len)) if open == marker && len >= open_len && trimmed.trim_end().chars().all(|c| c == marker) => {
+                    fence = None;
+                    continue;
+                }
+                Some(_) => {}
+            }
+        }
+        if fence.is_some() {
+            continue;
+        }
+
+        chars += line.chars().count();
+        words += line
+            .split_whitespace()
+            .filter(|token| token.chars().any(char::is_alphanumeric))
+            .count();
+    }
+
+    DocStats {
+        words,
+        chars,
+        reading_minutes: words.div_ceil(WORDS_PER_MINUTE),
+    }
+}
+
+/// The fence character and run length if `line` starts a ``` or ~~~ fence.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then_some((marker, len))
+}
+
+/// Strip a leading `---` (YAML) or `+++` (TOML) front matter block.
+fn skip_front_matter(content: &str) -> &str {
+    for delimiter in ["---", "+++"] {
+        let Some(rest) = content.strip_prefix(delimiter) else {
+            continue;
+        };
+        let Some(rest) = rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")) else {
+            continue;
+        };
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            offset += line.len();
+            if line.trim_end() == delimiter {
+                return &rest[offset..];
+            }
+        }
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_plain_words() {
+        let stats = document_stats("Hello world, this is mdr.\n");
+        assert_eq!(stats.words, 5);
+        assert_eq!(stats.chars, 25);
+        assert_eq!(stats.reading_minutes, 1);
+    }
+
+    #[test]
+    fn code_fences_excluded() {
+        let md = "Intro text\n\n```rust\nfn main() { println!(\"lots of words here\"); }\n```\n\n~~~~\nmore code\n```\nstill code\n~~~~\nOutro\n";
+        assert_eq!(document_stats(md).words, 3);
+    }
+
+    #[test]
+    fn punctuation_and_markers_not_counted() {
+        let md = "# Title\n\n- item one\n* item two\n\n| a | b |\n|---|---|\n\n-- — ... !\n";
+        assert_eq!(document_stats(md).words, 7);
+    }
+
+    #[test]
+    fn front_matter_excluded() {
+        let md = "---\ntitle: Lots of front matter words\ntags: [a, b]\n---\nBody text\n";
+        assert_eq!(document_stats(md).words, 2);
+        let toml = "+++\ntitle = \"ignored words\"\n+++\nBody\n";
+        assert_eq!(document_stats(toml).words, 1);
+    }
+
+    #[test]
+    fn horizontal_rule_is_not_front_matter() {
+        let md = "Some text\n\n---\n\nMore text\n";
+        assert_eq!(document_stats(md).words, 4);
+    }
+
+    #[test]
+    fn reading_time_rounds_up() {
+        assert_eq!(document_stats("").reading_minutes, 0);
+        let words = "word ".repeat(201);
+        assert_eq!(document_stats(&words).reading_minutes, 2);
+    }
+
+    #[test]
+    fn unterminated_fence_hides_rest() {
+        let md = "one two\n```\nthree four\n";
+        assert_eq!(document_stats(md).words, 2);
+    }
+}
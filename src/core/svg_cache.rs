@@ -0,0 +1,138 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+/// Maximum number of rasterized SVGs kept per cache before it is flushed.
+/// Live reload of a document with changing diagrams would otherwise grow without bound.
+const MAX_ENTRIES: usize = 256;
+
+/// Content-hash-keyed cache for rasterized SVGs.
+/// Keyed on the raw SVG bytes plus the target scale, so unchanged SVGs reuse the
+/// previous raster on reload while a scale change still invalidates correctly.
+pub struct SvgCache<T> {
+    entries: OnceLock<Mutex<HashMap<u64, T>>>,
+}
+
+impl<T: Clone> SvgCache<T> {
+    pub const fn new() -> Self {
+        Self { entries: OnceLock::new() }
+    }
+
+    /// Return the cached raster for `svg` at `scale`, or render and store it.
+    /// Failed renders are not cached.
+    pub fn get_or_insert_with<E>(&self, svg: &str, scale: f32, render: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let key = cache_key(svg, scale);
+        let entries = self.entries.get_or_init(|| Mutex::new(HashMap::new()));
+        if let Some(hit) = entries.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            return Ok(hit.clone());
+        }
+        let value = render()?;
+        let mut map = entries.lock().unwrap_or_else(|e| e.into_inner());
+        if map.len() >= MAX_ENTRIES {
+            map.clear();
+        }
+        map.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+impl<T: Clone> Default for SvgCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The SVG each rasterized PNG data URI was rendered from, so the egui zoom
+/// viewer can rasterize it again at whatever scale it is being viewed.
+#[cfg(feature = "egui-backend")]
+pub struct SvgSources {
+    entries: OnceLock<Mutex<HashMap<u64, std::sync::Arc<str>>>>,
+}
+
+#[cfg(feature = "egui-backend")]
+impl SvgSources {
+    pub const fn new() -> Self {
+        Self { entries: OnceLock::new() }
+    }
+
+    pub fn insert(&self, data_uri: &str, svg: &str) {
+        let entries = self.entries.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut map = entries.lock().unwrap_or_else(|e| e.into_inner());
+        if map.len() >= MAX_ENTRIES {
+            map.clear();
+        }
+        map.insert(cache_key(data_uri, 1.0), svg.into());
+    }
+
+    pub fn get(&self, data_uri: &str) -> Option<std::sync::Arc<str>> {
+        let entries = self.entries.get()?;
+        entries.lock().unwrap_or_else(|e| e.into_inner()).get(&cache_key(data_uri, 1.0)).cloned()
+    }
+}
+
+#[cfg(feature = "egui-backend")]
+impl Default for SvgSources {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sources of the diagrams and SVG images shown by the egui backend.
+#[cfg(feature = "egui-backend")]
+pub static SVG_SOURCES: SvgSources = SvgSources::new();
+
+fn cache_key(svg: &str, scale: f32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    svg.hash(&mut hasher);
+    scale.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn same_svg_and_scale_renders_once() {
+        let cache: SvgCache<String> = SvgCache::new();
+        let calls = Cell::new(0);
+        for _ in 0..3 {
+            let out: Result<String, ()> = cache.get_or_insert_with("<svg/>", 2.0, || {
+                calls.set(calls.get() + 1);
+                Ok("png".to_string())
+            });
+            assert_eq!(out.unwrap(), "png");
+        }
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn scale_change_invalidates() {
+        let cache: SvgCache<f32> = SvgCache::new();
+        let a: Result<f32, ()> = cache.get_or_insert_with("<svg/>", 1.0, || Ok(1.0));
+        let b: Result<f32, ()> = cache.get_or_insert_with("<svg/>", 2.0, || Ok(2.0));
+        assert_eq!(a.unwrap(), 1.0);
+        assert_eq!(b.unwrap(), 2.0);
+    }
+
+    #[cfg(feature = "egui-backend")]
+    #[test]
+    fn svg_sources_map_data_uris_back() {
+        let sources = SvgSources::new();
+        assert!(sources.get("data:image/png;base64,AAAA").is_none());
+        sources.insert("data:image/png;base64,AAAA", "<svg/>");
+        assert_eq!(sources.get("data:image/png;base64,AAAA").as_deref(), Some("<svg/>"));
+        assert!(sources.get("data:image/png;base64,BBBB").is_none());
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache: SvgCache<u8> = SvgCache::new();
+        let first: Result<u8, &str> = cache.get_or_insert_with("<bad/>", 1.0, || Err("boom"));
+        assert!(first.is_err());
+        let second: Result<u8, &str> = cache.get_or_insert_with("<bad/>", 1.0, || Ok(7));
+        assert_eq!(second.unwrap(), 7);
+    }
+}
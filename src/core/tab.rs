@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use super::watcher::{watch_file, WatchGuard};
+use super::RenderSettings;
+
+/// A file open in a tab of the GUI backends, and the watch that reloads it.
+pub struct Tab {
+    pub file_path: PathBuf,
+    /// Where its relative image and diagram paths resolve from.
+    pub base_dir: PathBuf,
+    pub watcher: WatchGuard,
+}
+
+impl Tab {
+    pub fn open(file_path: PathBuf, settings: &RenderSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let base_dir = settings.base_dir(&file_path);
+        let watcher = watch_file(&file_path, settings.reload_debounce)?;
+        Ok(Tab { file_path, base_dir, watcher })
+    }
+
+    /// Label for the tab strip: the file name, or the path if it has none.
+    pub fn title(&self) -> String {
+        self.file_path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.file_path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_the_file_name_as_title() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_tab_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("guide.md");
+        std::fs::write(&file, "# Guide\n").unwrap();
+
+        let tab = Tab::open(file.clone(), &RenderSettings::default()).unwrap();
+        assert_eq!(tab.title(), "guide.md");
+        assert_eq!(tab.base_dir, dir.canonicalize().unwrap());
+        assert!(Tab::open(dir.join("missing.md"), &RenderSettings::default()).is_err());
+        drop(tab);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
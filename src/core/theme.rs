@@ -0,0 +1,359 @@
+use ratatui::style::{Color, Modifier, Style};
+
+use super::config::{DisabledAttributes, Theme};
+
+/// Colors of the terminal UI. Each slot is a base style that the renderer adds
+/// its own modifiers to (headings are made bold, links underlined, ...), so a
+/// theme can leave colors out entirely and still keep the document readable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuiTheme {
+    /// Heading text by level, `headings[0]` being `#`.
+    pub headings: [Style; 6],
+    /// Body text that is given an explicit style, such as table cells.
+    pub text: Style,
+    /// Fenced code block text.
+    pub code: Style,
+    /// Inline code spans.
+    pub inline_code: Style,
+    /// Lines picked out by a `{2-4}` spec in a code block's info string.
+    pub code_highlight: Style,
+    /// Keys written as `<kbd>Ctrl</kbd>`.
+    pub kbd: Style,
+    /// Text highlighted with `<mark>`.
+    pub mark: Style,
+    pub link: Style,
+    /// Image placeholders shown when an image can't be drawn.
+    pub image: Style,
+    pub blockquote: Style,
+    /// Bar and title of `> [!NOTE]`, `[!TIP]`, `[!IMPORTANT]`, `[!WARNING]` and
+    /// `[!CAUTION]` alerts, in that order.
+    pub alerts: [Style; 5],
+    /// Bullets, ordered list numbers and `<details>` summaries.
+    pub list_marker: Style,
+    pub task_done: Style,
+    pub task_open: Style,
+    /// Rules, table separators, strikethrough, line numbers and the help bar.
+    pub muted: Style,
+    pub search_match: Style,
+    /// The search match that `n`/`N` is currently on.
+    pub search_current: Style,
+    pub border: Style,
+    /// Border of the pane that has keyboard focus.
+    pub border_focused: Style,
+    /// Selected row in the TOC and the heading palette.
+    pub selection: Style,
+    /// TOC entry of the section being read.
+    pub toc_active: Style,
+    /// Bottom bar while typing a search or heading query.
+    pub prompt: Style,
+    /// Syntect theme that colors the `--raw` source view; `None` leaves it plain.
+    pub syntax_theme: Option<&'static str>,
+    /// Attributes left out wherever the renderer would add them (`--no-bold`,
+    /// `--no-italic`, `--no-underline`).
+    pub disabled: Modifier,
+    /// Markers of list items, tasks and quotes.
+    pub glyphs: Glyphs,
+}
+
+impl Default for TuiTheme {
+    fn default() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            headings: [
+                fg(Color::Cyan),
+                fg(Color::Blue),
+                fg(Color::Yellow),
+                fg(Color::Magenta),
+                fg(Color::DarkGray),
+                fg(Color::DarkGray),
+            ],
+            text: fg(Color::White),
+            code: fg(Color::Green),
+            inline_code: fg(Color::Green).bg(Color::Rgb(30, 30, 30)),
+            code_highlight: Style::default().bg(Color::DarkGray),
+            kbd: fg(Color::White).bg(Color::Rgb(60, 60, 60)),
+            mark: fg(Color::Black).bg(Color::Rgb(230, 200, 80)),
+            link: fg(Color::Blue),
+            image: fg(Color::Magenta),
+            blockquote: fg(Color::Gray),
+            alerts: [fg(Color::LightBlue), fg(Color::LightGreen), fg(Color::LightMagenta), fg(Color::Yellow), fg(Color::LightRed)],
+            list_marker: fg(Color::Cyan),
+            task_done: fg(Color::Green),
+            task_open: fg(Color::Yellow),
+            muted: fg(Color::DarkGray),
+            search_match: Style::default().bg(Color::Rgb(80, 80, 0)),
+            search_current: Style::default().bg(Color::Yellow).fg(Color::Black),
+            border: fg(Color::DarkGray),
+            border_focused: fg(Color::Cyan),
+            selection: Style::default().bg(Color::DarkGray).fg(Color::White),
+            toc_active: fg(Color::Green),
+            prompt: fg(Color::Yellow).bg(Color::Rgb(40, 40, 40)),
+            syntax_theme: Some("base16-ocean.dark"),
+            disabled: Modifier::empty(),
+            glyphs: Glyphs::default(),
+        }
+    }
+}
+
+impl TuiTheme {
+    /// The built-in theme for `--theme`, or `None` for `auto`, which the caller
+    /// resolves.
+    pub fn for_theme(theme: Theme) -> Option<Self> {
+        match theme {
+            Theme::Auto => None,
+            Theme::Default => Some(Self::default()),
+            Theme::Light => Some(Self::light()),
+            Theme::Monochrome => Some(Self::monochrome()),
+            Theme::Solarized => Some(Self::solarized()),
+            Theme::HighContrast => Some(Self::high_contrast()),
+        }
+    }
+
+    /// The default palette reworked for light backgrounds: no yellow or cyan
+    /// text, and dark text on pale highlights.
+    pub fn light() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            headings: [
+                fg(Color::Blue),
+                fg(Color::Magenta),
+                fg(Color::Red),
+                fg(Color::Green),
+                fg(Color::DarkGray),
+                fg(Color::DarkGray),
+            ],
+            text: fg(Color::Black),
+            code: fg(Color::Green),
+            inline_code: fg(Color::Red).bg(Color::Rgb(235, 235, 235)),
+            code_highlight: Style::default().bg(Color::Rgb(250, 240, 190)),
+            kbd: fg(Color::Black).bg(Color::Rgb(220, 220, 220)),
+            mark: fg(Color::Black).bg(Color::Rgb(255, 230, 120)),
+            link: fg(Color::Blue),
+            image: fg(Color::Magenta),
+            blockquote: fg(Color::DarkGray),
+            alerts: [fg(Color::Blue), fg(Color::Green), fg(Color::Magenta), fg(Color::Rgb(0x9a, 0x67, 0x00)), fg(Color::Red)],
+            list_marker: fg(Color::Blue),
+            task_done: fg(Color::Green),
+            task_open: fg(Color::Red),
+            muted: fg(Color::DarkGray),
+            search_match: Style::default().bg(Color::Rgb(255, 240, 150)),
+            search_current: Style::default().bg(Color::Rgb(255, 190, 0)).fg(Color::Black),
+            border: fg(Color::Gray),
+            border_focused: fg(Color::Blue),
+            selection: Style::default().bg(Color::Rgb(210, 220, 240)).fg(Color::Black),
+            toc_active: fg(Color::Green),
+            prompt: fg(Color::Black).bg(Color::Rgb(230, 230, 230)),
+            syntax_theme: Some("InspiredGitHub"),
+            disabled: Modifier::empty(),
+            glyphs: Glyphs::default(),
+        }
+    }
+
+    /// No colors at all: structure comes from bold, dim, underline and reverse
+    /// video, which read the same on light and dark terminals.
+    pub fn monochrome() -> Self {
+        let plain = Style::default();
+        let dim = plain.add_modifier(Modifier::DIM);
+        let reversed = plain.add_modifier(Modifier::REVERSED);
+        Self {
+            headings: [plain, plain, plain, plain, dim, dim],
+            text: plain,
+            code: plain,
+            inline_code: plain.add_modifier(Modifier::BOLD),
+            code_highlight: reversed,
+            kbd: reversed,
+            mark: reversed,
+            link: plain,
+            image: dim,
+            blockquote: dim,
+            alerts: [plain, plain, plain, plain, plain],
+            list_marker: plain,
+            task_done: dim,
+            task_open: plain,
+            muted: dim,
+            search_match: plain.add_modifier(Modifier::UNDERLINED),
+            search_current: reversed,
+            border: dim,
+            border_focused: plain,
+            selection: reversed,
+            toc_active: plain,
+            prompt: reversed,
+            syntax_theme: None,
+            disabled: Modifier::empty(),
+            glyphs: Glyphs::default(),
+        }
+    }
+
+    /// [`TuiTheme::monochrome`] without dim text, for low-vision reading: everything
+    /// is in the terminal's own foreground, with reverse video for highlights.
+    pub fn high_contrast() -> Self {
+        let plain = Style::default();
+        Self {
+            headings: [plain; 6],
+            image: plain.add_modifier(Modifier::UNDERLINED),
+            blockquote: plain,
+            task_done: plain,
+            muted: plain,
+            border: plain,
+            border_focused: plain.add_modifier(Modifier::BOLD),
+            toc_active: plain.add_modifier(Modifier::REVERSED),
+            ..Self::monochrome()
+        }
+    }
+
+    /// The Solarized accent colors, which keep their contrast on both the
+    /// light and the dark Solarized backgrounds (and most others).
+    pub fn solarized() -> Self {
+        const BASE01: Color = Color::Rgb(0x58, 0x6e, 0x75);
+        const BASE1: Color = Color::Rgb(0x93, 0xa1, 0xa1);
+        const BASE2: Color = Color::Rgb(0xee, 0xe8, 0xd5);
+        const BASE3: Color = Color::Rgb(0xfd, 0xf6, 0xe3);
+        const YELLOW: Color = Color::Rgb(0xb5, 0x89, 0x00);
+        const ORANGE: Color = Color::Rgb(0xcb, 0x4b, 0x16);
+        const MAGENTA: Color = Color::Rgb(0xd3, 0x36, 0x82);
+        const VIOLET: Color = Color::Rgb(0x6c, 0x71, 0xc4);
+        const BLUE: Color = Color::Rgb(0x26, 0x8b, 0xd2);
+        const CYAN: Color = Color::Rgb(0x2a, 0xa1, 0x98);
+        const GREEN: Color = Color::Rgb(0x85, 0x99, 0x00);
+        const RED: Color = Color::Rgb(0xdc, 0x32, 0x2f);
+        let fg = |color| Style::default().fg(color);
+        Self {
+            headings: [fg(BLUE), fg(CYAN), fg(YELLOW), fg(MAGENTA), fg(VIOLET), fg(BASE01)],
+            text: Style::default(),
+            code: fg(GREEN),
+            inline_code: fg(ORANGE),
+            code_highlight: Style::default().bg(BASE1),
+            kbd: fg(BASE3).bg(BASE01),
+            mark: fg(BASE01).bg(BASE2),
+            link: fg(BLUE),
+            image: fg(MAGENTA),
+            blockquote: fg(VIOLET),
+            alerts: [fg(BLUE), fg(GREEN), fg(VIOLET), fg(YELLOW), fg(RED)],
+            list_marker: fg(CYAN),
+            task_done: fg(GREEN),
+            task_open: fg(YELLOW),
+            muted: fg(BASE01),
+            search_match: Style::default().bg(YELLOW).fg(BASE3),
+            search_current: Style::default().bg(ORANGE).fg(BASE3),
+            border: fg(BASE01),
+            border_focused: fg(BLUE),
+            selection: Style::default().bg(BLUE).fg(BASE3),
+            toc_active: fg(GREEN),
+            prompt: fg(YELLOW),
+            syntax_theme: Some("Solarized (dark)"),
+            disabled: Modifier::empty(),
+            glyphs: Glyphs::default(),
+        }
+    }
+
+    /// This theme with bold, italic and/or underline turned off.
+    pub fn without_attributes(mut self, disabled: DisabledAttributes) -> Self {
+        let DisabledAttributes { bold, italic, underline } = disabled;
+        for (off, modifier) in [(bold, Modifier::BOLD), (italic, Modifier::ITALIC), (underline, Modifier::UNDERLINED)] {
+            self.disabled.set(modifier, off);
+        }
+        self
+    }
+
+    /// This theme with other list, task and quote markers.
+    pub fn with_glyphs(mut self, glyphs: Glyphs) -> Self {
+        self.glyphs = glyphs;
+        self
+    }
+
+    /// `style` without the attributes this theme leaves out.
+    pub fn restrict(&self, mut style: Style) -> Style {
+        style.add_modifier.remove(self.disabled);
+        style
+    }
+
+    /// Style for a heading of the given level (1–6); deeper levels share level 6's.
+    pub fn heading(&self, level: u8) -> Style {
+        self.headings[(level.clamp(1, 6) - 1) as usize]
+    }
+}
+
+/// The markers drawn in front of list items, tasks and quoted lines, for
+/// fonts and terminals without the default ones (`--ascii`, `--bullet`...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyphs {
+    pub bullet: String,
+    /// Follows the number of an ordered list item: `.` for `1.`, `)` for `1)`.
+    pub ordered_suffix: char,
+    /// Checkboxes of done, open and in-between (`[-]`, `[~]`, `[/]`) tasks.
+    pub tasks: [String; 3],
+    /// Bar drawn once per level of a blockquote, and along alerts.
+    pub quote: String,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Self {
+            bullet: "•".to_string(),
+            ordered_suffix: '.',
+            tasks: ["☑".to_string(), "☐".to_string(), "◐".to_string()],
+            quote: "▎".to_string(),
+        }
+    }
+}
+
+impl Glyphs {
+    /// Plain ASCII markers, which every terminal and font can show.
+    pub fn ascii() -> Self {
+        Self {
+            bullet: "-".to_string(),
+            ordered_suffix: '.',
+            tasks: ["[x]".to_string(), "[ ]".to_string(), "[-]".to_string()],
+            quote: ">".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(theme: &TuiTheme) -> Vec<Style> {
+        let mut slots = theme.headings.to_vec();
+        slots.extend(theme.alerts);
+        slots.extend([
+            theme.text, theme.code, theme.inline_code, theme.code_highlight, theme.kbd, theme.mark, theme.link, theme.image,
+            theme.blockquote, theme.list_marker, theme.task_done, theme.task_open, theme.muted,
+            theme.search_match, theme.search_current, theme.border, theme.border_focused,
+            theme.selection, theme.toc_active, theme.prompt,
+        ]);
+        slots
+    }
+
+    #[test]
+    fn every_cli_theme_exists() {
+        use clap::ValueEnum;
+        for &theme in Theme::value_variants().iter().filter(|&&theme| theme != Theme::Auto) {
+            assert!(TuiTheme::for_theme(theme).is_some(), "missing theme {:?}", theme);
+        }
+        assert_eq!(TuiTheme::for_theme(Theme::Default), Some(TuiTheme::default()));
+        assert!(TuiTheme::for_theme(Theme::Auto).is_none());
+    }
+
+    #[test]
+    fn monochrome_sets_no_colors() {
+        assert!(slots(&TuiTheme::monochrome()).iter().all(|s| s.fg.is_none() && s.bg.is_none()));
+    }
+
+    #[test]
+    fn alert_colors_are_distinct() {
+        for theme in [TuiTheme::default(), TuiTheme::light(), TuiTheme::solarized()] {
+            for (i, a) in theme.alerts.iter().enumerate() {
+                assert!(a.fg.is_some());
+                assert!(theme.alerts[i + 1..].iter().all(|b| b.fg != a.fg), "{:?}", theme.alerts);
+            }
+        }
+    }
+
+    #[test]
+    fn heading_levels_are_clamped() {
+        let theme = TuiTheme::solarized();
+        assert_eq!(theme.heading(1), theme.headings[0]);
+        assert_eq!(theme.heading(9), theme.headings[5]);
+    }
+}
@@ -1,15 +1,38 @@
 use comrak::{parse_document, Arena, Options};
-use comrak::nodes::NodeValue;
+use comrak::nodes::{AstNode, NodeValue};
+
+use crate::core::RenderSettings;
 
 #[derive(Debug, Clone)]
 pub struct TocEntry {
     pub level: u8,
     pub text: String,
+    /// Slug of the heading's `id`. Empty for bold lines listed by `--toc-heuristic`,
+    /// which have no anchor in the rendered page.
     pub anchor: String,
+    /// 1-based source line the heading starts on.
+    pub line: usize,
+    /// A bold line standing in for a heading (`--toc-heuristic`), rather than a heading.
+    pub bold_line: bool,
+}
+
+/// Documents with fewer real headings than this get bold lines in their TOC
+/// with `--toc-heuristic`.
+const HEURISTIC_HEADING_LIMIT: usize = 2;
+
+/// Extract table of contents entries from markdown content, with bold lines
+/// standing in for headings when `settings.toc_heuristic` asks for them.
+pub fn extract_toc(content: &str, settings: &RenderSettings) -> Vec<TocEntry> {
+    extract_toc_with(content, settings.toc_heuristic)
+}
+
+/// The headings of markdown content, for looking up anchors and sections.
+pub fn extract_headings(content: &str) -> Vec<TocEntry> {
+    extract_toc_with(content, false)
 }
 
-/// Extract table of contents entries from markdown content.
-pub fn extract_toc(content: &str) -> Vec<TocEntry> {
+/// [`extract_toc`], with `heuristic` in place of the `--toc-heuristic` setting.
+fn extract_toc_with(content: &str, heuristic: bool) -> Vec<TocEntry> {
     let arena = Arena::new();
     let mut options = Options::default();
     options.extension.strikethrough = true;
@@ -22,17 +45,89 @@ pub fn extract_toc(content: &str) -> Vec<TocEntry> {
     let mut entries = Vec::new();
 
     for node in root.descendants() {
-        if let NodeValue::Heading(heading) = &node.data.borrow().value {
+        let data = node.data.borrow();
+        if let NodeValue::Heading(heading) = &data.value {
             let level = heading.level;
             let text = collect_text(node);
             let anchor = slugify(&text);
-            entries.push(TocEntry { level, text, anchor });
+            let line = data.sourcepos.start.line;
+            entries.push(TocEntry { level, text, anchor, line, bold_line: false });
         }
     }
 
+    if heuristic && entries.len() < HEURISTIC_HEADING_LIMIT {
+        // Bold lines rank just below the shallowest real heading, such as a title
+        let level = entries.iter().map(|entry| entry.level + 1).min().unwrap_or(1).min(6);
+        entries.extend(root.children().filter_map(|node| {
+            let text = bold_line(node)?;
+            let line = node.data.borrow().sourcepos.start.line;
+            Some(TocEntry { level, text, anchor: String::new(), line, bold_line: true })
+        }));
+        entries.sort_by_key(|entry| entry.line);
+    }
+
     entries
 }
 
+/// The text of a top-level paragraph whose whole first line is bold, such as
+/// `**Action items**` or `**Attendees**:`, which many notes use as a heading.
+fn bold_line<'a>(node: &'a AstNode<'a>) -> Option<String> {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return None;
+    }
+    let strong = node.first_child()?;
+    if !matches!(strong.data.borrow().value, NodeValue::Strong) {
+        return None;
+    }
+    let line_ends = match strong.next_sibling() {
+        None => true,
+        Some(next) => match &next.data.borrow().value {
+            NodeValue::SoftBreak | NodeValue::LineBreak => true,
+            NodeValue::Text(text) => text.trim() == ":" && next.next_sibling().is_none_or(|after| {
+                matches!(after.data.borrow().value, NodeValue::SoftBreak | NodeValue::LineBreak)
+            }),
+            _ => false,
+        },
+    };
+    let text = collect_text(strong).trim().trim_end_matches(':').trim_end().to_string();
+    (line_ends && !text.is_empty()).then_some(text)
+}
+
+/// Index of the entry whose section contains 1-based source `line`:
+/// the last heading starting at or above it. None before the first heading.
+pub fn active_entry(entries: &[TocEntry], line: usize) -> Option<usize> {
+    entries.iter().rposition(|entry| entry.line <= line)
+}
+
+/// Index of the heading whose anchor is `anchor`, for opening a document at
+/// `file.md#anchor`. A leading `#` and percent-encoding are ignored, and so is case.
+pub fn find_anchor(entries: &[TocEntry], anchor: &str) -> Option<usize> {
+    let anchor = crate::core::markdown::percent_decode(anchor.trim_start_matches('#')).to_lowercase();
+    entries.iter().position(|entry| !entry.anchor.is_empty() && entry.anchor == anchor)
+}
+
+/// Source lines of the section containing `line`, for focus mode: from its heading
+/// up to (not including) the next heading, or the lines above the first heading.
+/// The last section runs to `usize::MAX`.
+pub fn focus_range(entries: &[TocEntry], line: usize) -> std::ops::Range<usize> {
+    let active = active_entry(entries, line);
+    let start = active.map_or(1, |i| entries[i].line);
+    let end = entries.get(active.map_or(0, |i| i + 1)).map_or(usize::MAX, |entry| entry.line);
+    start..end
+}
+
+/// Indices of the entries a TOC limited to `depth` levels shows.
+/// Indices stay those of `entries`, so clicks still map to the right heading.
+pub fn shown_entries(entries: &[TocEntry], depth: usize) -> Vec<usize> {
+    (0..entries.len()).filter(|&i| entries[i].level as usize <= depth).collect()
+}
+
+/// Position in `shown` of the row that stands for entry `index`: the entry itself,
+/// or the nearest shown one above it when it is too deep to be listed.
+pub fn shown_position(shown: &[usize], index: usize) -> Option<usize> {
+    shown.iter().rposition(|&i| i <= index)
+}
+
 /// Collect all text content from a node and its children.
 fn collect_text<'a>(node: &'a comrak::arena_tree::Node<'a, std::cell::RefCell<comrak::nodes::Ast>>) -> String {
     let mut text = String::new();
@@ -113,19 +208,19 @@ mod tests {
 
     #[test]
     fn extract_toc_empty_input() {
-        let entries = extract_toc("");
+        let entries = extract_headings("");
         assert!(entries.is_empty());
     }
 
     #[test]
     fn extract_toc_no_headings() {
-        let entries = extract_toc("Just some paragraph text.\n\nAnother paragraph.");
+        let entries = extract_headings("Just some paragraph text.\n\nAnother paragraph.");
         assert!(entries.is_empty());
     }
 
     #[test]
     fn extract_toc_single_h1() {
-        let entries = extract_toc("# Hello World");
+        let entries = extract_headings("# Hello World");
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].level, 1);
         assert_eq!(entries[0].text, "Hello World");
@@ -135,7 +230,7 @@ mod tests {
     #[test]
     fn extract_toc_multiple_levels() {
         let md = "# Title\n## Section\n### Subsection\n#### Deep";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries.len(), 4);
         assert_eq!(entries[0].level, 1);
         assert_eq!(entries[1].level, 2);
@@ -146,7 +241,7 @@ mod tests {
     #[test]
     fn extract_toc_heading_with_inline_code() {
         let md = "# The `main` function";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].text, "The main function");
     }
@@ -154,7 +249,7 @@ mod tests {
     #[test]
     fn extract_toc_heading_with_special_chars() {
         let md = "## Hello, World! (2024)";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].anchor, "hello-world-2024");
     }
@@ -162,7 +257,7 @@ mod tests {
     #[test]
     fn extract_toc_mixed_content_and_headings() {
         let md = "Some intro text.\n\n# First\n\nParagraph here.\n\n## Second\n\nMore text.";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries.len(), 2);
         assert_eq!(entries[0].text, "First");
         assert_eq!(entries[1].text, "Second");
@@ -171,18 +266,93 @@ mod tests {
     #[test]
     fn extract_toc_h5_and_h6() {
         let md = "##### Level 5\n###### Level 6";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries.len(), 2);
         assert_eq!(entries[0].level, 5);
         assert_eq!(entries[1].level, 6);
     }
 
+    #[test]
+    fn extract_toc_records_source_lines() {
+        let md = "Intro\n\n# First\n\ntext\n\nSetext\n------\n\n> ## Quoted\n";
+        let lines: Vec<usize> = extract_headings(md).iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 7, 10]);
+    }
+
+    #[test]
+    fn active_entry_is_last_heading_at_or_above_line() {
+        let entries = extract_headings("intro\n\n# A\n\ntext\n\n## B\n\nmore\n");
+        assert_eq!(active_entry(&entries, 1), None);
+        assert_eq!(active_entry(&entries, 3), Some(0));
+        assert_eq!(active_entry(&entries, 6), Some(0));
+        assert_eq!(active_entry(&entries, 7), Some(1));
+        assert_eq!(active_entry(&entries, 100), Some(1));
+    }
+
+    #[test]
+    fn focus_range_spans_the_section_around_a_line() {
+        let entries = extract_headings("intro\n\n# A\n\ntext\n\n## B\n\nmore\n");
+        assert_eq!(focus_range(&entries, 1), 1..3);
+        assert_eq!(focus_range(&entries, 5), 3..7);
+        assert_eq!(focus_range(&entries, 9), 7..usize::MAX);
+        assert_eq!(focus_range(&[], 4), 1..usize::MAX);
+    }
+
+    #[test]
+    fn find_anchor_matches_heading_slugs() {
+        let entries = extract_headings("# Getting Started\n\n## Café menu\n\n## Getting Started\n");
+        assert_eq!(find_anchor(&entries, "getting-started"), Some(0));
+        assert_eq!(find_anchor(&entries, "#Getting-Started"), Some(0));
+        // A repeated heading shares its slug, so the first one wins
+        assert_eq!(entries[2].anchor, "getting-started");
+        assert_eq!(find_anchor(&entries, "café-menu"), Some(1));
+        assert_eq!(find_anchor(&entries, "getting%2Dstarted"), Some(0));
+        assert_eq!(find_anchor(&entries, "missing"), None);
+        assert_eq!(find_anchor(&entries, ""), None);
+    }
+
+    #[test]
+    fn shown_entries_keep_indices_of_the_full_toc() {
+        let md = "# A\n\n### A1\n\n## B\n\n##### B1\n\n## C\n";
+        let entries = extract_headings(md);
+        let shown = shown_entries(&entries, 2);
+        assert_eq!(shown, vec![0, 2, 4]);
+        // Clicking the second row goes to heading B on line 5
+        assert_eq!(entries[shown[1]].text, "B");
+        assert_eq!(entries[shown[1]].line, 5);
+        // Hidden headings are represented by the shown one above them
+        assert_eq!(shown_position(&shown, 1), Some(0));
+        assert_eq!(shown_position(&shown, 3), Some(1));
+        assert_eq!(shown_position(&shown, 4), Some(2));
+        assert_eq!(shown_entries(&entries, 6).len(), entries.len());
+    }
+
     #[test]
     fn extract_toc_preserves_order() {
         let md = "## B\n# A\n### C";
-        let entries = extract_toc(md);
+        let entries = extract_headings(md);
         assert_eq!(entries[0].text, "B");
         assert_eq!(entries[1].text, "A");
         assert_eq!(entries[2].text, "C");
     }
+
+    #[test]
+    fn bold_lines_stand_in_for_missing_headings() {
+        let md = "# Standup notes\n\n**Attendees**:\nAna, Bo\n\n**Action items**\n\n- **Bo** to fix CI\n\n\
+                  Some **bold** words.\n\n**Decisions** were made quickly.\n";
+        let settings = RenderSettings { toc_heuristic: true, ..RenderSettings::default() };
+        let entries = extract_toc(md, &settings);
+        let listed: Vec<(u8, &str, usize)> = entries.iter().map(|e| (e.level, e.text.as_str(), e.line)).collect();
+        assert_eq!(listed, [(1, "Standup notes", 1), (2, "Attendees", 3), (2, "Action items", 6)]);
+        assert!(entries[1].bold_line && entries[1].anchor.is_empty());
+        assert!(!entries[0].bold_line);
+
+        // Off by default, and real headings win when there are enough of them
+        assert_eq!(extract_toc(md, &RenderSettings::default()).len(), 1);
+        let headed = "# A\n\n## B\n\n**Not a heading**\n";
+        assert_eq!(extract_toc_with(headed, true).len(), 2);
+
+        let untitled = extract_toc_with("**One**\n\ntext\n\n**Two**\n", true);
+        assert_eq!(untitled.iter().map(|e| (e.level, e.line)).collect::<Vec<_>>(), [(1, 1), (1, 5)]);
+    }
 }
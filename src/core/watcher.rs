@@ -1,20 +1,102 @@
-use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
-use std::path::Path;
+use notify::RecommendedWatcher;
+use notify_debouncer_mini::{new_debouncer, DebouncedEventKind, Debouncer};
+use regex::Regex;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Receiver};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-/// Start watching a file for changes with 300ms debounce.
-/// Returns a Receiver that gets a () signal on each change.
-pub fn watch_file(path: &Path) -> Result<Receiver<()>, Box<dyn std::error::Error>> {
+/// What happened to a watched file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileEvent {
+    /// It (or one of its dependencies) was written, or came back after being removed.
+    Changed,
+    /// It is gone, e.g. deleted by a branch switch; the last content is still shown.
+    Removed,
+}
+
+/// A running file watch. Dropping it stops watching.
+pub struct WatchGuard {
+    rx: Receiver<FileEvent>,
+    /// The watched file, resolved when the watch started.
+    path: PathBuf,
+    /// Set while the file is missing, so its return is noticed even if its
+    /// directory was removed and recreated along with it.
+    removed: Mutex<bool>,
+    debouncer: Mutex<Debouncer<RecommendedWatcher>>,
+    /// Files the document pulls in (such as `.mmd` diagrams) that also count as changes.
+    dependencies: Arc<Mutex<Vec<PathBuf>>>,
+    /// Directories being watched, so each is only added once.
+    dirs: Mutex<Vec<PathBuf>>,
+}
+
+impl WatchGuard {
+    /// What happened to the file since the last call, if anything. Any number of
+    /// pending signals are coalesced into the latest one. While the file is
+    /// removed this checks whether it is back, and reports that as a change.
+    pub fn poll(&self) -> Option<FileEvent> {
+        let latest = self.rx.try_iter().last();
+        let mut removed = self.removed.lock().unwrap();
+        if latest == Some(FileEvent::Removed) {
+            *removed = true;
+        }
+        if !*removed || !self.path.exists() {
+            return latest;
+        }
+        // Watch the directory again in case it was recreated too
+        *removed = false;
+        if let Some(parent) = self.path.parent() {
+            let _ = self.debouncer.lock().unwrap().watcher().watch(parent, notify::RecursiveMode::NonRecursive);
+        }
+        Some(FileEvent::Changed)
+    }
+
+    /// Whether the file is gone: [`poll`](Self::poll) reported it removed and it
+    /// hasn't come back yet. Meanwhile the backends keep its last content shown,
+    /// under a notice.
+    pub fn is_removed(&self) -> bool {
+        *self.removed.lock().unwrap()
+    }
+
+    /// Also report changes to `files`, replacing any earlier dependencies. Files
+    /// that don't exist are skipped.
+    pub fn watch_dependencies(&self, files: &[PathBuf]) {
+        let files: Vec<PathBuf> = files.iter().filter_map(|file| file.canonicalize().ok()).collect();
+        let mut dirs = self.dirs.lock().unwrap();
+        let mut debouncer = self.debouncer.lock().unwrap();
+        for dir in files.iter().filter_map(|file| file.parent()) {
+            if !dirs.iter().any(|watched| watched == dir)
+                && debouncer.watcher().watch(dir, notify::RecursiveMode::NonRecursive).is_ok()
+            {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+        *self.dependencies.lock().unwrap() = files;
+    }
+}
+
+/// Start watching a file for changes. Events are coalesced until the file has
+/// been quiet for `debounce`.
+pub fn watch_file(path: &Path, debounce: Duration) -> Result<WatchGuard, Box<dyn std::error::Error>> {
     let (tx, rx) = mpsc::channel();
     let path = path.canonicalize()?;
     let watch_path = path.clone();
+    let dependencies = Arc::new(Mutex::new(Vec::new()));
+    let watched_dependencies = Arc::clone(&dependencies);
 
-    let mut debouncer = new_debouncer(Duration::from_millis(300), move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
+    let mut debouncer = new_debouncer(debounce, move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
         if let Ok(events) = res {
+            let dependencies = watched_dependencies.lock().unwrap();
             for event in &events {
-                if event.kind == DebouncedEventKind::Any && event.path == path {
-                    let _ = tx.send(());
+                if event.kind != DebouncedEventKind::Any {
+                    continue;
+                }
+                if event.path == path {
+                    let _ = tx.send(if path.exists() { FileEvent::Changed } else { FileEvent::Removed });
+                    return;
+                }
+                if dependencies.contains(&event.path) {
+                    let _ = tx.send(FileEvent::Changed);
                     return;
                 }
             }
@@ -24,8 +106,263 @@ pub fn watch_file(path: &Path) -> Result<Receiver<()>, Box<dyn std::error::Error
     let parent = watch_path.parent().unwrap_or(&watch_path);
     debouncer.watcher().watch(parent, notify::RecursiveMode::NonRecursive)?;
 
-    // Leak the debouncer so it lives for the program duration
-    std::mem::forget(debouncer);
+    Ok(WatchGuard {
+        rx,
+        path: watch_path.clone(),
+        removed: Mutex::new(false),
+        debouncer: Mutex::new(debouncer),
+        dependencies,
+        dirs: Mutex::new(vec![parent.to_path_buf()]),
+    })
+}
+
+/// Which files under a `--watch-dir` directory are shown: markdown files whose
+/// path relative to the directory matches an `--include` glob (if any are given)
+/// and no `--exclude` glob.
+pub struct FileFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl FileFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        FileFilter {
+            include: include.iter().map(|glob| glob_regex(glob)).collect(),
+            exclude: exclude.iter().map(|glob| glob_regex(glob)).collect(),
+        }
+    }
+
+    /// Whether the file at `relative` (to the watched directory) is shown.
+    pub fn matches(&self, relative: &Path) -> bool {
+        let is_markdown = relative.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ["md", "markdown"].iter().any(|md| ext.eq_ignore_ascii_case(md)));
+        let path = relative.to_string_lossy().replace('\\', "/");
+        is_markdown
+            && (self.include.is_empty() || self.include.iter().any(|re| re.is_match(&path)))
+            && !self.exclude.iter().any(|re| re.is_match(&path))
+    }
+}
+
+/// Compile a glob to a regex over `/`-separated relative paths. `*` and `?` stay
+/// within one path component and `**` spans any number of them. A glob without a
+/// `/` matches a file or directory name at any depth, and matching a directory
+/// matches everything in it.
+fn glob_regex(glob: &str) -> Regex {
+    let glob = glob.trim_start_matches("./");
+    let mut re = String::from(if glob.contains('/') { "^" } else { "(^|/)" });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.next_if_eq(&'/').is_some() {
+                    re.push_str("(.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push_str("(/|$)");
+    Regex::new(&re).expect("an escaped glob is a valid regex")
+}
+
+/// A running recursive directory watch. Dropping it stops watching.
+pub struct DirWatchGuard {
+    rx: Receiver<PathBuf>,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl DirWatchGuard {
+    /// The file that changed most recently since the last call, if any.
+    pub fn changed_file(&self) -> Option<PathBuf> {
+        self.rx.try_iter().last()
+    }
+}
+
+/// Watch `dir` and everything below it for changes to files `filter` lets through.
+/// A burst of saves across several files, settled for `debounce`, reports only the
+/// one written last.
+pub fn watch_dir(dir: &Path, filter: FileFilter, debounce: Duration) -> Result<DirWatchGuard, Box<dyn std::error::Error>> {
+    let (tx, rx) = mpsc::channel();
+    let dir = dir.canonicalize()?;
+    let root = dir.clone();
+
+    let mut debouncer = new_debouncer(debounce, move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
+        let Ok(events) = res else {
+            return;
+        };
+        let latest = events.iter()
+            .filter(|event| event.path.is_file())
+            .filter(|event| event.path.strip_prefix(&root).is_ok_and(|relative| filter.matches(relative)))
+            .max_by_key(|event| modified(&event.path));
+        if let Some(event) = latest {
+            let _ = tx.send(event.path.clone());
+        }
+    })?;
+    debouncer.watcher().watch(&dir, notify::RecursiveMode::Recursive)?;
+
+    Ok(DirWatchGuard { rx, _debouncer: debouncer })
+}
+
+/// The most recently modified file under `dir` that `filter` lets through,
+/// skipping hidden directories such as `.git`.
+pub fn newest_file(dir: &Path, filter: &FileFilter) -> Option<PathBuf> {
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.strip_prefix(dir).is_ok_and(|relative| filter.matches(relative)) {
+                let time = modified(&path);
+                if newest.as_ref().is_none_or(|(newest_time, _)| time > *newest_time) {
+                    newest = Some((time, path));
+                }
+            }
+        }
+    }
+    newest.map(|(_, path)| path)
+}
+
+fn modified(path: &Path) -> SystemTime {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_a_write() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_watcher_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("doc.md");
+        std::fs::write(&file, "# One\n").unwrap();
+
+        let guard = watch_file(&file, Duration::from_millis(50)).unwrap();
+        assert_eq!(guard.poll(), None);
+        std::fs::write(&file, "# Two\n").unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while guard.poll() != Some(FileEvent::Changed) {
+            assert!(std::time::Instant::now() < deadline, "no change reported");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        drop(guard);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_a_write_to_a_dependency() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_watch_dependency_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("diagrams")).unwrap();
+        let file = dir.join("doc.md");
+        let diagram = dir.join("diagrams/flow.mmd");
+        std::fs::write(&file, "```mermaid\n@file diagrams/flow.mmd\n```\n").unwrap();
+        std::fs::write(&diagram, "graph TD\n  A --> B\n").unwrap();
+
+        let guard = watch_file(&file, Duration::from_millis(50)).unwrap();
+        guard.watch_dependencies(std::slice::from_ref(&diagram));
+        std::fs::write(&diagram, "graph TD\n  A --> C\n").unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while guard.poll() != Some(FileEvent::Changed) {
+            assert!(std::time::Instant::now() < deadline, "no change reported");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        drop(guard);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_removal_then_reattaches_when_recreated() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_watch_remove_{}", std::process::id()));
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        let file = docs.join("doc.md");
+        std::fs::write(&file, "# One\n").unwrap();
+        let guard = watch_file(&file, Duration::from_millis(50)).unwrap();
+        let wait_for = |expected: FileEvent| {
+            let deadline = std::time::Instant::now() + Duration::from_secs(5);
+            while guard.poll() != Some(expected) {
+                assert!(std::time::Instant::now() < deadline, "no {:?} reported", expected);
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        };
+
+        std::fs::remove_file(&file).unwrap();
+        wait_for(FileEvent::Removed);
+        assert!(guard.is_removed());
+        std::fs::write(&file, "# Two\n").unwrap();
+        wait_for(FileEvent::Changed);
+        assert!(!guard.is_removed());
+
+        // Its directory going away and coming back, as in a branch switch
+        std::fs::remove_dir_all(&docs).unwrap();
+        wait_for(FileEvent::Removed);
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(&file, "# Three\n").unwrap();
+        wait_for(FileEvent::Changed);
+        std::fs::write(&file, "# Four\n").unwrap();
+        wait_for(FileEvent::Changed);
+        drop(guard);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filter_takes_markdown_and_applies_globs() {
+        let all = FileFilter::new(&[], &[]);
+        assert!(all.matches(Path::new("docs/guide.md")));
+        assert!(all.matches(Path::new("NOTES.Markdown")));
+        assert!(!all.matches(Path::new("src/main.rs")));
+
+        let filter = FileFilter::new(&["docs/**".to_string()], &["drafts".to_string(), "*.tmp.md".to_string()]);
+        assert!(filter.matches(Path::new("docs/guide.md")));
+        assert!(filter.matches(Path::new("docs/api/index.md")));
+        assert!(!filter.matches(Path::new("README.md")));
+        assert!(!filter.matches(Path::new("docs/drafts/idea.md")));
+        assert!(!filter.matches(Path::new("docs/x.tmp.md")));
+
+        let names = FileFilter::new(&["guide?.md".to_string()], &[]);
+        assert!(names.matches(Path::new("a/b/guide2.md")));
+        assert!(!names.matches(Path::new("a/b/guide10.md")));
+    }
+
+    #[test]
+    fn watch_dir_reports_the_changed_file() {
+        let dir = std::env::temp_dir().join(format!("mdr_test_watch_dir_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.md"), "# A\n").unwrap();
+        std::fs::write(dir.join("sub/b.md"), "# B\n").unwrap();
+        assert!(newest_file(&dir, &FileFilter::new(&[], &[])).is_some());
+
+        let guard = watch_dir(&dir, FileFilter::new(&[], &[]), Duration::from_millis(50)).unwrap();
+        std::fs::write(dir.join("sub/notes.txt"), "ignored").unwrap();
+        std::fs::write(dir.join("sub/b.md"), "# B again\n").unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let changed = loop {
+            if let Some(path) = guard.changed_file() {
+                break path;
+            }
+            assert!(std::time::Instant::now() < deadline, "no change reported");
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(changed, dir.join("sub/b.md").canonicalize().unwrap());
+        drop(guard);
 
-    Ok(rx)
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
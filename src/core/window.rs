@@ -0,0 +1,46 @@
+/// Inner size of a window opened without `--window-size` or a remembered geometry.
+pub const DEFAULT_SIZE: (f64, f64) = (1100.0, 900.0);
+/// Smallest inner size a window opens at; smaller requests are clamped up to it.
+pub const MIN_SIZE: (f64, f64) = (400.0, 300.0);
+
+/// How a GUI window opens, as asked for on the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WindowOptions {
+    /// Inner size in logical pixels.
+    pub size: Option<(f64, f64)>,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    /// Keep the window above others from the start (it can still be toggled off).
+    pub always_on_top: bool,
+}
+
+impl WindowOptions {
+    /// Whether any of the geometry flags were given. They then win over the geometry
+    /// remembered from the last session, which is left as it was.
+    pub fn is_explicit(&self) -> bool {
+        self.size.is_some() || self.maximized || self.fullscreen
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` size such as `1200x800`, clamping each side to [`MIN_SIZE`].
+pub fn parse_size(s: &str) -> Option<(f64, f64)> {
+    let (width, height) = s.trim().split_once(['x', 'X'])?;
+    let width: u32 = width.trim().parse().ok()?;
+    let height: u32 = height.trim().parse().ok()?;
+    Some((f64::from(width).max(MIN_SIZE.0), f64::from(height).max(MIN_SIZE.1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_parse_and_clamp_to_the_minimum() {
+        assert_eq!(parse_size("1200x800"), Some((1200.0, 800.0)));
+        assert_eq!(parse_size("1024X768"), Some((1024.0, 768.0)));
+        assert_eq!(parse_size("100x50"), Some(MIN_SIZE));
+        assert_eq!(parse_size("1200"), None);
+        assert_eq!(parse_size("wide x tall"), None);
+        assert_eq!(parse_size("-5x800"), None);
+    }
+}
@@ -0,0 +1,22 @@
+//! Markdown rendering from the `mdr` viewer, usable as a library.
+//!
+//! The rendering backends (egui, webview, TUI) stay in the binary; this crate
+//! exposes the backend-independent [`core`]: GitHub-flavored markdown to HTML
+//! with mermaid diagrams, table of contents extraction, search, and link
+//! checking. The most used modules are re-exported at the crate root:
+//!
+//! ```
+//! let settings = mdr::core::RenderSettings::default();
+//! let html = mdr::markdown::parse_markdown("# Hello\n\nSome *markdown*.", &settings);
+//! assert!(html.contains(r#"<h1 id="hello">"#));
+//!
+//! let toc = mdr::toc::extract_toc("# Guide\n\n## Install\n", &settings);
+//! assert_eq!(toc[1].anchor, "install");
+//! ```
+//!
+//! Rendering options, such as the tab width and the table of contents depth,
+//! come from a [`core::RenderSettings`] passed to each call.
+
+pub mod core;
+
+pub use crate::core::{check, markdown, mermaid, search, stats, toc};
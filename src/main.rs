@@ -1,17 +1,26 @@
 mod backend;
-mod core;
 
-use clap::Parser;
+use mdr::core::config::{self, ImageProtocol, ScrollBehavior, Theme};
+use mdr::{core, vlog};
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use std::io::{self, IsTerminal, Read};
 use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "mdr", version, about = "Lightweight Markdown viewer with live reload")]
 struct Cli {
-    /// Markdown file to render (use '-' or pipe via stdin)
+    /// Markdown file to render (use '-' or pipe via stdin); append `:LINE` to open at a source line,
+    /// or `#ANCHOR` at a heading
     file: Option<PathBuf>,
 
+    /// More markdown files to open as tabs (egui and webview; the TUI shows the first file)
+    #[arg(value_name = "MORE")]
+    more_files: Vec<PathBuf>,
+
     /// Rendering backend to use: egui (native GUI), webview (HTML), tui (terminal)
     #[arg(short, long, default_value = "auto", value_parser = parse_backend)]
     backend: String,
@@ -23,6 +32,245 @@ struct Cli {
     /// List available backends and exit
     #[arg(long)]
     list_backends: bool,
+
+    /// Terminal image protocol for the TUI, detected with `auto`; `none` shows no images
+    #[arg(long, value_enum, default_value_t = ImageProtocol::Auto)]
+    image_protocol: ImageProtocol,
+
+    /// Color theme for the TUI: `auto` is light or dark to match the terminal
+    /// (`high-contrast` also applies in the webview)
+    #[arg(long, value_enum, default_value_t = Theme::Auto)]
+    theme: Theme,
+
+    /// Render the TUI without colors, using only bold, underline and similar styles (also set by NO_COLOR)
+    #[arg(long)]
+    no_color: bool,
+
+    /// Never draw bold text in the TUI (headings, strong emphasis, ...)
+    #[arg(long)]
+    no_bold: bool,
+
+    /// Never draw italic text in the TUI (emphasis, blockquotes, ...), for terminals
+    /// that show italic as reverse video or not at all
+    #[arg(long)]
+    no_italic: bool,
+
+    /// Never underline text in the TUI (links, the top heading, ...)
+    #[arg(long)]
+    no_underline: bool,
+
+    /// Start in focus mode: dim everything but the section at the top of the view
+    /// (toggled with d in the TUI, Ctrl+D in egui and the webview)
+    #[arg(long)]
+    focus: bool,
+
+    /// Sort tables by a column when its header is clicked: ascending, descending, then back
+    /// to the document order (webview only; the TUI and egui keep tables as written)
+    #[arg(long)]
+    sortable_tables: bool,
+
+    /// Hide the bar along the top of the webview that shows how far down the document
+    /// the view is
+    #[arg(long)]
+    no_progress_bar: bool,
+
+    /// How the webview scrolls to headings and search matches
+    /// (always instant when the system asks for reduced motion)
+    #[arg(long, value_name = "BEHAVIOR", value_enum, default_value_t = ScrollBehavior::Smooth)]
+    scroll_behavior: ScrollBehavior,
+
+    /// Don't take an italic line right under an image as its caption; `<figure>`
+    /// captions are still shown
+    #[arg(long)]
+    no_image_captions: bool,
+
+    /// Draw ```dot and ```plantuml blocks with Graphviz and PlantUML from PATH
+    /// (comma-separated: dot, plantuml); a block whose tool is missing stays code
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',', value_parser = config::parse_diagram_tool)]
+    enable: Vec<core::diagram_tools::DiagramTool>,
+
+    /// Draw list, task and blockquote markers in plain ASCII in the TUI (`-`, `[x]`,
+    /// `>`), for terminals and fonts without the Unicode ones
+    #[arg(long)]
+    ascii: bool,
+
+    /// Marker of bullet list items in the TUI [default: •, or - with --ascii]
+    #[arg(long, value_name = "GLYPH", value_parser = parse_glyph)]
+    bullet: Option<String>,
+
+    /// How ordered list numbers end in the TUI: `dot` (1.) or `paren` (1))
+    #[arg(long, value_name = "STYLE", value_parser = parse_ordered_style)]
+    ordered_style: Option<char>,
+
+    /// Checkboxes of done, open and partial tasks in the TUI, comma-separated
+    /// [default: ☑,☐,◐, or [x],[ ],[-] with --ascii]
+    #[arg(long, value_name = "DONE,OPEN,PARTIAL", value_parser = parse_task_glyphs)]
+    task_glyphs: Option<[String; 3]>,
+
+    /// Bar drawn along blockquotes and alerts in the TUI [default: ▎, or > with --ascii]
+    #[arg(long, value_name = "GLYPH", value_parser = parse_glyph)]
+    quote_glyph: Option<String>,
+
+    /// Show the markdown source with syntax highlighting instead of rendering it
+    #[arg(long, visible_alias = "no-render")]
+    raw: bool,
+
+    /// Columns a tab expands to inside code blocks
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = config::parse_tab_width)]
+    tab_width: usize,
+
+    /// Deepest heading level listed in the table of contents (1-6)
+    #[arg(long, value_name = "N", default_value_t = 6, value_parser = config::parse_toc_depth, visible_alias = "max-heading-depth")]
+    toc_depth: usize,
+
+    /// In documents with fewer than two headings, list paragraphs that start with
+    /// a bold line (`**Section**`) in the table of contents too
+    #[arg(long)]
+    toc_heuristic: bool,
+
+    /// Number the lines of fenced code blocks (webview and TUI)
+    #[arg(long)]
+    code_line_numbers: bool,
+
+    /// Milliseconds the file must be quiet before reloading (10-5000). Lower reloads
+    /// sooner after each save; higher coalesces bursts of writes into one render
+    #[arg(long, value_name = "MS", default_value_t = 300, value_parser = config::parse_reload_debounce)]
+    reload_debounce: u64,
+
+    /// Milliseconds the TUI waits for input before checking for file changes (10-1000).
+    /// Bounds how long a reload can lag; lower values wake the CPU more often
+    #[arg(long, value_name = "MS", default_value_t = 100, value_parser = config::parse_tui_poll)]
+    tui_poll: u64,
+
+    /// Drop the TUI panel borders and hide the TOC until Tab focuses it, giving the
+    /// content the full width (automatic below 100 columns)
+    #[arg(long)]
+    compact: bool,
+
+    /// Ease TUI page, heading and search jumps over a few frames instead of jumping at once
+    #[arg(long, alias = "smooth-scroll")]
+    tui_smooth_scroll: bool,
+
+    /// Watch a directory tree and show whichever markdown file in it changed last
+    /// (as a tab in egui and webview). Starts with FILE, or the most recently modified file
+    #[arg(long, value_name = "DIR")]
+    watch_dir: Option<PathBuf>,
+
+    /// With --watch-dir, only follow files whose relative path matches this glob (repeatable), e.g. 'docs/**'
+    #[arg(long, value_name = "GLOB", requires = "watch_dir")]
+    include: Vec<String>,
+
+    /// With --watch-dir, ignore files or directories matching this glob (repeatable), e.g. 'drafts'
+    #[arg(long, value_name = "GLOB", requires = "watch_dir")]
+    exclude: Vec<String>,
+
+    /// Resolve relative image paths against DIR instead of the markdown file's directory
+    /// (with stdin, the default is the current directory)
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<PathBuf>,
+
+    /// Editor that `e` (TUI) or the Edit button (egui, webview) opens the file in, at the
+    /// line being read. Defaults to $VISUAL, then $EDITOR; may include arguments, e.g. 'code --wait'
+    #[arg(long, value_name = "CMD")]
+    editor: Option<String>,
+
+    /// Link #123 to issues, @user to profiles and commit hashes to commits of the repository
+    /// at URL, e.g. https://github.com/owner/repo (also set by `repo_url` in front matter)
+    #[arg(long, value_name = "URL", value_parser = config::parse_repo_url)]
+    repo_url: Option<String>,
+
+    /// Render diagrams the built-in mermaid renderer can't draw with the mermaid CLI
+    /// (`mmdc`, from @mermaid-js/mermaid-cli) when it is installed (egui)
+    #[arg(long)]
+    mermaid_fallback: bool,
+
+    /// Show mermaid blocks as code instead of rendering them as diagrams
+    #[arg(long)]
+    no_mermaid: bool,
+
+    /// Keep the view at the end of the document as the file grows, like `tail -f`
+    #[arg(long, visible_alias = "tail")]
+    follow: bool,
+
+    /// Open scrolled to this 1-based source line (clamped to the document)
+    #[arg(long, value_name = "LINE")]
+    line: Option<usize>,
+
+    /// Open scrolled to the heading with this anchor, e.g. `installation` (as in `file.md#installation`).
+    /// An unknown anchor opens at the top
+    #[arg(long, value_name = "ANCHOR", conflicts_with = "line")]
+    goto: Option<String>,
+
+    /// Print word count, character count and reading time, then exit
+    #[arg(long)]
+    stats: bool,
+
+    /// Show when the file was last modified and, in a git repository, its last
+    /// commit (hash, author and age) in the status bar or footer
+    #[arg(long)]
+    show_meta: bool,
+
+    /// Report local images and links that point at missing files, and `#anchors`
+    /// matching no heading, then exit (non-zero if any were found)
+    #[arg(long)]
+    check: bool,
+
+    /// Print each match of QUERY in the markdown source as `line:col: context`, then exit
+    /// (non-zero if there were none). Case-insensitive unless --case-sensitive
+    #[arg(long, value_name = "QUERY", value_parser = parse_query)]
+    grep: Option<String>,
+
+    /// Match --grep queries case-sensitively
+    #[arg(long, requires = "grep")]
+    case_sensitive: bool,
+
+    /// Leave matches inside fenced and indented code blocks out of --grep
+    #[arg(long, requires = "grep")]
+    skip_code: bool,
+
+    /// Print only the number of --grep matches instead of listing them
+    #[arg(long, requires = "grep")]
+    count_matches: bool,
+
+    /// Write the TUI rendering as ANSI-styled text to PATH ('-' for stdout), then exit (plain text with --no-color).
+    /// A PATH ending in .png gets an image of the rendering instead
+    #[arg(long, value_name = "PATH")]
+    export: Option<PathBuf>,
+
+    /// Exit non-zero from --export if anything was drawn as a fallback: a mermaid diagram
+    /// that failed to render, or a local image that is missing or unreadable. Each is listed on stderr
+    #[arg(long, requires = "export")]
+    strict: bool,
+
+    /// Render each mermaid diagram to an SVG in DIR, named after the heading above it,
+    /// then exit (non-zero if any failed to render)
+    #[arg(long, value_name = "DIR")]
+    export_diagrams: Option<PathBuf>,
+
+    /// Also write a PNG of each diagram exported by --export-diagrams
+    #[arg(long, requires = "export_diagrams")]
+    diagram_png: bool,
+
+    /// Columns of a PNG written by --export (20-400)
+    #[arg(long, value_name = "COLS", default_value_t = 100, value_parser = parse_width)]
+    width: usize,
+
+    /// Open the GUI window at this inner size, e.g. 1200x800 (egui and webview).
+    /// Without it the window reopens where it was last closed
+    #[arg(long, value_name = "WxH", value_parser = parse_window_size)]
+    window_size: Option<(f64, f64)>,
+
+    /// Open the GUI window maximized
+    #[arg(long, conflicts_with = "fullscreen")]
+    maximized: bool,
+
+    /// Open the GUI window fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Keep the GUI window above other windows (toggle with Ctrl+Shift+T; remembered across sessions)
+    #[arg(long)]
+    always_on_top: bool,
 }
 
 fn print_backends() {
@@ -43,6 +291,48 @@ fn parse_backend(s: &str) -> Result<String, String> {
     }
 }
 
+fn parse_width(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if (20..=400).contains(&n) => Ok(n),
+        _ => Err(format!("invalid width '{}', expected a number of columns from 20 to 400", s)),
+    }
+}
+
+fn parse_window_size(s: &str) -> Result<(f64, f64), String> {
+    core::window::parse_size(s).ok_or_else(|| format!("invalid window size '{}', expected WIDTHxHEIGHT such as 1200x800", s))
+}
+
+fn parse_query(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        Err("the search query can't be empty".to_string())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+fn parse_glyph(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        Err("a glyph can't be empty".to_string())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+fn parse_ordered_style(s: &str) -> Result<char, String> {
+    match s {
+        "dot" => Ok('.'),
+        "paren" => Ok(')'),
+        _ => Err(format!("unknown ordered list style '{}', expected 'dot' or 'paren'", s)),
+    }
+}
+
+fn parse_task_glyphs(s: &str) -> Result<[String; 3], String> {
+    let glyphs: Vec<String> = s.split(',').map(parse_glyph).collect::<Result<_, _>>()?;
+    glyphs
+        .try_into()
+        .map_err(|_| format!("invalid task glyphs '{}', expected three separated by commas: DONE,OPEN,PARTIAL", s))
+}
+
 /// Auto-detect the best backend for the current environment.
 fn detect_backend() -> &'static str {
     // If no DISPLAY/WAYLAND and we have a TTY → TUI
@@ -83,13 +373,189 @@ fn detect_backend() -> &'static str {
     }
 }
 
+/// Gather the options that change how documents are drawn: those on the command
+/// line (`cli`, parsed from `matches`) over `settings`, read from the settings file.
+/// A switch on the command line only turns its option on (or off, for `--no-...`);
+/// options with a default are only taken when given.
+fn render_settings(cli: &Cli, matches: &ArgMatches, mut settings: core::RenderSettings) -> core::RenderSettings {
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if given("theme") {
+        settings.theme = cli.theme;
+    }
+    if given("tab_width") {
+        settings.tab_width = cli.tab_width;
+    }
+    if given("toc_depth") {
+        settings.toc_depth = cli.toc_depth;
+    }
+    if given("scroll_behavior") {
+        settings.scroll_behavior = cli.scroll_behavior;
+    }
+    if given("image_protocol") {
+        settings.image_protocol = cli.image_protocol;
+    }
+    if given("tui_poll") {
+        settings.poll_interval = Duration::from_millis(cli.tui_poll);
+    }
+    if given("reload_debounce") {
+        settings.reload_debounce = Duration::from_millis(cli.reload_debounce);
+    }
+    if !cli.enable.is_empty() {
+        settings.diagram_tools = cli.enable.clone();
+    }
+    if cli.repo_url.is_some() {
+        settings.repo_url = cli.repo_url.clone();
+    }
+    if cli.editor.is_some() {
+        settings.editor = cli.editor.clone();
+    }
+    settings.no_color |= cli.no_color;
+    settings.raw = cli.raw;
+    settings.code_line_numbers |= cli.code_line_numbers;
+    settings.toc_heuristic |= cli.toc_heuristic;
+    settings.render_mermaid &= !cli.no_mermaid;
+    settings.mermaid_fallback |= cli.mermaid_fallback;
+    settings.show_meta |= cli.show_meta;
+    settings.disabled_attributes.bold |= cli.no_bold;
+    settings.disabled_attributes.italic |= cli.no_italic;
+    settings.disabled_attributes.underline |= cli.no_underline;
+    settings.focus_mode |= cli.focus;
+    settings.sortable_tables |= cli.sortable_tables;
+    settings.progress_bar &= !cli.no_progress_bar;
+    settings.image_captions &= !cli.no_image_captions;
+    settings.follow |= cli.follow;
+    settings.compact |= cli.compact;
+    settings.tui_smooth_scroll |= cli.tui_smooth_scroll;
+    #[cfg(feature = "tui-backend")]
+    {
+        let mut glyphs = if cli.ascii { core::theme::Glyphs::ascii() } else { core::theme::Glyphs::default() };
+        if let Some(bullet) = &cli.bullet {
+            glyphs.bullet = bullet.clone();
+        }
+        if let Some(suffix) = cli.ordered_style {
+            glyphs.ordered_suffix = suffix;
+        }
+        if let Some(tasks) = &cli.task_glyphs {
+            glyphs.tasks = tasks.clone();
+        }
+        if let Some(quote) = &cli.quote_glyph {
+            glyphs.quote = quote.clone();
+        }
+        settings.glyphs = glyphs;
+    }
+    settings
+}
+
+/// Split an editor-style `path:LINE` argument into the path and line number.
+fn split_line_suffix(arg: &std::path::Path) -> Option<(PathBuf, usize)> {
+    let (path, line) = arg.to_str()?.rsplit_once(':')?;
+    let line = line.parse().ok()?;
+    Some((PathBuf::from(path), line))
+}
+
+/// Split a `path#anchor` argument into the path and the anchor.
+fn split_anchor_suffix(arg: &std::path::Path) -> Option<(PathBuf, String)> {
+    let (path, anchor) = arg.to_str()?.rsplit_once('#')?;
+    Some((PathBuf::from(path), anchor.to_string()))
+}
+
+/// 1-based source line of the heading in `file` whose anchor is `anchor`, or None
+/// (logged) when there is no such heading.
+fn anchor_line(file: &std::path::Path, anchor: &str) -> Option<usize> {
+    let content = core::io::read_markdown(file).ok()?;
+    let entries = core::toc::extract_headings(&content);
+    let line = core::toc::find_anchor(&entries, anchor).map(|i| entries[i].line);
+    if line.is_none() {
+        vlog!("no heading with anchor '#{}' in '{}', opening at the top", anchor.trim_start_matches('#'), file.display());
+    }
+    line
+}
+
+/// Print document statistics for `--stats`.
+fn print_stats(file: &std::path::Path) {
+    let content = core::io::read_markdown(file).unwrap_or_else(|e| {
+        eprintln!("Error: failed to read '{}': {}", file.display(), e);
+        process::exit(1);
+    });
+    let stats = core::stats::document_stats(&content);
+    println!("words: {}", stats.words);
+    println!("chars: {}", stats.chars);
+    println!("reading_minutes: {}", stats.reading_minutes);
+}
+
+/// Print the broken references in each file for `--check`, one per line, and
+/// return whether there were none.
+fn check_links(files: &[PathBuf], from_stdin: bool, settings: &core::RenderSettings) -> bool {
+    let mut clean = true;
+    for (i, file) in files.iter().enumerate() {
+        let content = core::io::read_markdown(file).unwrap_or_else(|e| {
+            eprintln!("Error: failed to read '{}': {}", file.display(), e);
+            process::exit(1);
+        });
+        let name = if from_stdin && i == 0 { "<stdin>".to_string() } else { file.display().to_string() };
+        for problem in core::check::check_document(&content, &settings.base_dir(file)) {
+            println!("{}:{}", name, problem);
+            clean = false;
+        }
+    }
+    clean
+}
+
+/// Print the matches of `query` in each file for `--grep`, prefixed with the file name
+/// when there are several (or just their count, with `count`), and return whether
+/// there were any.
+fn grep_files(files: &[PathBuf], from_stdin: bool, query: &str, case_sensitive: bool, skip_code: bool, count: bool) -> bool {
+    let mut total = 0;
+    for (i, file) in files.iter().enumerate() {
+        let content = core::io::read_markdown(file).unwrap_or_else(|e| {
+            eprintln!("Error: failed to read '{}': {}", file.display(), e);
+            process::exit(1);
+        });
+        let name = if from_stdin && i == 0 { "<stdin>".to_string() } else { file.display().to_string() };
+        let matches = core::search::grep_lines(&content, query, case_sensitive, skip_code);
+        total += matches.len();
+        match (count, files.len() > 1) {
+            (true, true) => println!("{}:{}", name, matches.len()),
+            (true, false) => println!("{}", matches.len()),
+            (false, true) => matches.iter().for_each(|m| println!("{}:{}", name, m)),
+            (false, false) => matches.iter().for_each(|m| println!("{}", m)),
+        }
+    }
+    total > 0
+}
+
+/// Write the diagrams in `file` to `dir` for `--export-diagrams`, listing the files
+/// written and the diagrams that failed, and return whether all of them made it.
+fn export_diagrams(file: &std::path::Path, dir: &std::path::Path, png: bool, settings: &core::RenderSettings) -> bool {
+    let content = core::io::read_markdown(file).unwrap_or_else(|e| {
+        eprintln!("Error: failed to read '{}': {}", file.display(), e);
+        process::exit(1);
+    });
+    let exports = core::diagram_export::export_diagrams(&content, &settings.base_dir(file), dir, png).unwrap_or_else(|e| {
+        eprintln!("Error: failed to create '{}': {}", dir.display(), e);
+        process::exit(1);
+    });
+    let mut ok = true;
+    for export in &exports {
+        for written in &export.files {
+            println!("{}", written.display());
+        }
+        if let Some(error) = &export.error {
+            eprintln!("{}:{}: diagram '{}' {}", file.display(), export.line, export.name, error);
+            ok = false;
+        }
+    }
+    ok
+}
+
 /// Read stdin and write to a temp file, returning its path.
 fn read_stdin_to_tmpfile() -> PathBuf {
-    let mut content = String::new();
-    io::stdin().lock().read_to_string(&mut content).unwrap_or_else(|e| {
+    let mut bytes = Vec::new();
+    io::stdin().lock().read_to_end(&mut bytes).unwrap_or_else(|e| {
         eprintln!("Error: failed to read from stdin: {}", e);
         process::exit(1);
     });
+    let content = core::io::decode_markdown(&bytes, std::path::Path::new("<stdin>"));
     let tmp_dir = std::env::temp_dir().join("mdr");
     std::fs::create_dir_all(&tmp_dir).unwrap_or_else(|e| {
         eprintln!("Error: failed to create temp directory: {}", e);
@@ -104,44 +570,182 @@ fn read_stdin_to_tmpfile() -> PathBuf {
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     core::set_verbose(cli.verbose);
+    let config = config::load().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    let mut settings = render_settings(&cli, &matches, config);
 
     if cli.list_backends {
         print_backends();
         process::exit(0);
     }
 
+    let filter = core::watcher::FileFilter::new(&cli.include, &cli.exclude);
+    if let Some(dir) = &cli.watch_dir {
+        if !dir.is_dir() {
+            eprintln!("Error: '{}' is not a directory", dir.display());
+            process::exit(1);
+        }
+    }
+
+    // Without a file or piped input, the GUI backends ask for a file to open
+    let backend = if cli.backend == "auto" {
+        detect_backend()
+    } else {
+        cli.backend.as_str()
+    };
+    let pick_file = cli.file.is_none()
+        && cli.watch_dir.is_none()
+        && io::stdin().is_terminal()
+        && !cli.stats
+        && !cli.check
+        && cli.grep.is_none()
+        && cli.export.is_none()
+        && cli.export_diagrams.is_none()
+        && cfg!(any(feature = "egui-backend", feature = "webview-backend"))
+        && matches!(backend, "egui" | "webview");
+
+    // Piped markdown lives in a temp file, so its images are relative to where mdr runs
+    let from_stdin = match &cli.file {
+        Some(f) => f.as_os_str() == "-",
+        None => cli.watch_dir.is_none() && !pick_file,
+    };
+    match &cli.base_dir {
+        Some(dir) => match dir.canonicalize() {
+            Ok(dir) if dir.is_dir() => settings.base_dir = Some(dir),
+            _ => {
+                eprintln!("Error: '{}' is not a directory", dir.display());
+                process::exit(1);
+            }
+        },
+        None if from_stdin && cli.more_files.is_empty() => {
+            settings.base_dir = std::env::current_dir().ok();
+        }
+        None => {}
+    }
+
+    let mut start_line = cli.line;
+    let mut goto = cli.goto.clone();
     let file = match cli.file {
         Some(f) if f.as_os_str() == "-" => read_stdin_to_tmpfile(),
         Some(f) => {
-            if !f.exists() {
+            if f.exists() {
+                f
+            } else if let Some((path, line)) = split_line_suffix(&f).filter(|(path, _)| path.exists()) {
+                start_line = start_line.or(Some(line));
+                path
+            } else if let Some((path, anchor)) = split_anchor_suffix(&f).filter(|(path, _)| path.exists()) {
+                goto = goto.or(Some(anchor));
+                path
+            } else {
                 eprintln!("Error: file '{}' not found", f.display());
                 process::exit(1);
             }
-            f
-        }
-        None => {
-            if io::stdin().is_terminal() {
-                eprintln!("Error: missing required argument <FILE>");
-                eprintln!("Usage: mdr <FILE> [OPTIONS]");
-                eprintln!("       cat file.md | mdr [OPTIONS]");
-                eprintln!("Try 'mdr --help' for more information.");
+        }
+        #[cfg(any(feature = "egui-backend", feature = "webview-backend"))]
+        None if pick_file => core::recent::pick_markdown_file().unwrap_or_else(|| process::exit(0)),
+        None => match &cli.watch_dir {
+            Some(dir) => core::watcher::newest_file(dir, &filter).unwrap_or_else(|| {
+                eprintln!("Error: no markdown files to show under '{}'", dir.display());
+                process::exit(1);
+            }),
+            None => {
+                if io::stdin().is_terminal() {
+                    eprintln!("Error: missing required argument <FILE>");
+                    eprintln!("Usage: mdr <FILE> [OPTIONS]");
+                    eprintln!("       cat file.md | mdr [OPTIONS]");
+                    eprintln!("Try 'mdr --help' for more information.");
+                    process::exit(1);
+                }
+                read_stdin_to_tmpfile()
+            }
+        },
+    };
+
+    if let Some(anchor) = &goto {
+        start_line = start_line.or_else(|| anchor_line(&file, anchor));
+    }
+
+    let mut files = vec![file.clone()];
+    for f in &cli.more_files {
+        if !f.exists() {
+            eprintln!("Error: file '{}' not found", f.display());
+            process::exit(1);
+        }
+        files.push(f.clone());
+    }
+
+    if cli.stats {
+        print_stats(&file);
+        process::exit(0);
+    }
+
+    if cli.check {
+        process::exit(if check_links(&files, from_stdin, &settings) { 0 } else { 1 });
+    }
+
+    if let Some(query) = &cli.grep {
+        let found = grep_files(&files, from_stdin, query, cli.case_sensitive, cli.skip_code, cli.count_matches);
+        process::exit(if found { 0 } else { 1 });
+    }
+
+    if let Some(dir) = &cli.export_diagrams {
+        process::exit(if export_diagrams(&file, dir, cli.diagram_png, &settings) { 0 } else { 1 });
+    }
+
+    if let Some(output) = &cli.export {
+        #[cfg(feature = "tui-backend")]
+        match backend::tui::export(file.clone(), &settings, output, cli.width, cli.strict) {
+            Ok(failures) if !failures.is_empty() => {
+                let name = if from_stdin { "<stdin>".to_string() } else { file.display().to_string() };
+                for failure in &failures {
+                    eprintln!("{}: {}", name, failure);
+                }
+                eprintln!("Error: {} render failure(s) in --strict mode", failures.len());
+                process::exit(1);
+            }
+            Ok(_) => process::exit(0),
+            Err(e) => {
+                eprintln!("Error: {}", e);
                 process::exit(1);
             }
-            read_stdin_to_tmpfile()
         }
-    };
+        #[cfg(not(feature = "tui-backend"))]
+        {
+            let _ = output;
+            eprintln!("Error: --export needs the tui backend. Rebuild with --features tui-backend");
+            process::exit(1);
+        }
+    }
 
-    let backend = if cli.backend == "auto" {
-        detect_backend()
-    } else {
-        cli.backend.as_str()
+    if matches!(backend, "egui" | "webview") && !from_stdin {
+        for file in &files {
+            core::recent::add_recent_file(file);
+        }
+    }
+
+    #[cfg(any(feature = "egui-backend", feature = "webview-backend"))]
+    let window = core::window::WindowOptions {
+        size: cli.window_size,
+        maximized: cli.maximized,
+        fullscreen: cli.fullscreen,
+        always_on_top: cli.always_on_top,
     };
 
+    let dir_watch = cli.watch_dir.as_ref().map(|dir| {
+        core::watcher::watch_dir(dir, filter, settings.reload_debounce).unwrap_or_else(|e| {
+            eprintln!("Error: failed to watch '{}': {}", dir.display(), e);
+            process::exit(1);
+        })
+    });
+
     let result = match backend {
         #[cfg(feature = "egui-backend")]
-        "egui" => backend::egui::run(file),
+        "egui" => backend::egui::run(files, &settings, start_line, window, dir_watch),
 
         #[cfg(not(feature = "egui-backend"))]
         "egui" => {
@@ -150,7 +754,7 @@ fn main() {
         }
 
         #[cfg(feature = "webview-backend")]
-        "webview" => backend::webview::run(file),
+        "webview" => backend::webview::run(files, &settings, start_line, window, dir_watch),
 
         #[cfg(not(feature = "webview-backend"))]
         "webview" => {
@@ -159,7 +763,7 @@ fn main() {
         }
 
         #[cfg(feature = "tui-backend")]
-        "tui" => backend::tui::run(file),
+        "tui" => backend::tui::run(files[0].clone(), &settings, start_line, dir_watch),
 
         #[cfg(not(feature = "tui-backend"))]
         "tui" => {
@@ -175,3 +779,77 @@ fn main() {
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settings from the command line `args` over the settings file `config`.
+    fn settings_from(args: &[&str], config: &str) -> core::RenderSettings {
+        let matches = Cli::command().get_matches_from(args);
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        let mut settings = core::RenderSettings::default();
+        settings.apply_config(config).unwrap();
+        render_settings(&cli, &matches, settings)
+    }
+
+    #[test]
+    fn render_settings_follow_the_command_line() {
+        assert_eq!(settings_from(&["mdr"], ""), core::RenderSettings::default());
+
+        let settings = settings_from(&[
+            "mdr", "--theme", "solarized", "--no-color", "--tab-width", "2", "--toc-depth", "3", "--no-mermaid",
+            "--no-italic", "--focus", "--sortable-tables", "--no-progress-bar", "--scroll-behavior", "instant", "--no-image-captions", "--enable", "dot,plantuml", "--ascii", "--bullet", "*", "--repo-url", "https://github.com/o/r/",
+            "--follow", "--tui-poll", "50", "--compact", "--tui-smooth-scroll", "--editor", "vi", "--reload-debounce", "20", "--image-protocol", "sixel",
+            "notes.md",
+        ], "");
+        assert_eq!(settings.tui_theme(), Theme::Monochrome);
+        assert_eq!(settings.theme, Theme::Solarized);
+        assert_eq!((settings.tab_width, settings.toc_depth), (2, 3));
+        assert!(!settings.render_mermaid);
+        assert_eq!(settings.disabled_attributes, config::DisabledAttributes { italic: true, ..config::DisabledAttributes::default() });
+        assert!(settings.focus_mode && settings.sortable_tables && !settings.progress_bar);
+        assert!(settings.scroll_behavior == ScrollBehavior::Instant && !settings.image_captions);
+        assert_eq!(settings.diagram_tools, [core::diagram_tools::DiagramTool::Dot, core::diagram_tools::DiagramTool::PlantUml]);
+        assert_eq!(settings.repo_url.as_deref(), Some("https://github.com/o/r"));
+        assert!(settings.follow && settings.compact && settings.tui_smooth_scroll);
+        assert_eq!((settings.poll_interval, settings.reload_debounce), (Duration::from_millis(50), Duration::from_millis(20)));
+        assert_eq!((settings.editor.as_deref(), settings.image_protocol), (Some("vi"), ImageProtocol::Sixel));
+        #[cfg(feature = "tui-backend")]
+        assert_eq!(settings.glyphs, core::theme::Glyphs { bullet: "*".to_string(), ..core::theme::Glyphs::ascii() });
+    }
+
+    #[test]
+    fn command_line_overrides_the_settings_file() {
+        let config = "theme = \"light\"\ntoc-depth = 3\nmermaid = false\nprogress-bar = false\neditor = \"vi\"\n";
+        let settings = settings_from(&["mdr", "notes.md"], config);
+        assert_eq!((settings.theme, settings.toc_depth), (Theme::Light, 3));
+        assert!(!settings.render_mermaid && !settings.progress_bar);
+        assert_eq!(settings.editor.as_deref(), Some("vi"));
+
+        let settings = settings_from(&["mdr", "--theme", "solarized", "--toc-depth", "6", "--editor", "code --wait", "notes.md"], config);
+        assert_eq!((settings.theme, settings.toc_depth), (Theme::Solarized, 6));
+        assert_eq!(settings.editor.as_deref(), Some("code --wait"));
+        // A default left alone doesn't undo the file's setting
+        assert_eq!(settings_from(&["mdr", "--tab-width", "4"], "tab-width = 2\ntoc-depth = 2\n").toc_depth, 2);
+    }
+
+    #[test]
+    fn grep_succeeds_only_when_something_matched() {
+        let file = std::env::temp_dir().join(format!("mdr_test_grep_{}.md", process::id()));
+        std::fs::write(&file, "# Setup\n\n```sh\nmake setup\n```\n").unwrap();
+        let files = [file.clone()];
+
+        assert!(grep_files(&files, false, "SETUP", false, false, false));
+        assert!(!grep_files(&files, false, "SETUP", true, false, true));
+        assert!(grep_files(&files, false, "make", false, false, true));
+        assert!(!grep_files(&files, false, "make", false, true, false));
+
+        let cli = Cli::try_parse_from(["mdr", "--grep", "setup", "--skip-code", "--count-matches", "notes.md"]).unwrap();
+        assert_eq!(cli.grep.as_deref(), Some("setup"));
+        assert!(Cli::try_parse_from(["mdr", "--skip-code", "notes.md"]).is_err());
+        assert!(Cli::try_parse_from(["mdr", "--grep", "", "notes.md"]).is_err());
+
+        let _ = std::fs::remove_file(&file);
+    }
+}
@@ -120,3 +120,152 @@ fn nonexistent_file_shows_error() {
     );
 }
 
+#[test]
+fn nonexistent_extra_file_shows_error() {
+    let output = Command::new(mdr_bin())
+        .arg("README.md")
+        .arg("this_tab_does_not_exist.md")
+        .arg("--stats")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run mdr");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("this_tab_does_not_exist.md' not found"), "got stderr: {}", stderr);
+}
+
+#[test]
+fn stats_flag_prints_counts_and_exits() {
+    let mut child = Command::new(mdr_bin())
+        .arg("--stats")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(b"# Hello\n\nThree more words\n\n```\nignored code\n```\n").unwrap();
+    }
+
+    let output = child.wait_with_output().expect("failed to wait");
+    assert!(output.status.success(), "mdr --stats should exit successfully");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("words: 4"), "unexpected output: {}", stdout);
+    assert!(stdout.contains("reading_minutes: 1"), "unexpected output: {}", stdout);
+}
+
+#[test]
+fn check_flag_reports_broken_references() {
+    let dir = std::env::temp_dir().join(format!("mdr-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.md");
+    let bad = dir.join("bad.md");
+    std::fs::write(&good, "# Intro\n\nSee [intro](#intro) and [bad](bad.md).\n").unwrap();
+    std::fs::write(&bad, "# Bad\n\n![logo](logo.png)\n\n[up](#top)\n").unwrap();
+
+    let clean = Command::new(mdr_bin()).arg("--check").arg(&good).output().expect("failed to run mdr");
+    let broken = Command::new(mdr_bin()).arg("--check").arg(&good).arg(&bad).output().expect("failed to run mdr");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(clean.status.success(), "stdout: {}", String::from_utf8_lossy(&clean.stdout));
+    assert!(clean.stdout.is_empty());
+    assert_eq!(broken.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&broken.stdout);
+    assert!(stdout.contains("bad.md:3: missing image 'logo.png'"), "unexpected output: {}", stdout);
+    assert!(stdout.contains("bad.md:5: unknown anchor '#top'"), "unexpected output: {}", stdout);
+    assert_eq!(stdout.lines().count(), 2, "unexpected output: {}", stdout);
+}
+
+#[test]
+fn line_suffix_resolves_existing_file() {
+    let dir = std::env::temp_dir().join("mdr");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join(format!("line-suffix-{}.md", std::process::id()));
+    std::fs::write(&file, "one\n\ntwo three\n").unwrap();
+
+    let output = Command::new(mdr_bin())
+        .arg(format!("{}:3", file.display()))
+        .arg("--stats")
+        .output()
+        .expect("failed to run mdr");
+    let _ = std::fs::remove_file(&file);
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("words: 3"));
+}
+
+#[test]
+fn export_writes_rendered_text_to_stdout() {
+    let mut child = Command::new(mdr_bin())
+        .args(["--export", "-", "--no-color"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(b"# Hello\n\n- one two\n").unwrap();
+    }
+
+    let output = child.wait_with_output().expect("failed to wait");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Hello\n"), "unexpected output: {}", stdout);
+    assert!(stdout.contains("• one two"), "unexpected output: {}", stdout);
+    assert!(!stdout.contains('\x1b'), "unexpected escapes: {:?}", stdout);
+}
+
+// --export is only built with the TUI
+#[cfg(feature = "tui-backend")]
+#[test]
+fn strict_export_fails_on_a_broken_diagram() {
+    let dir = std::env::temp_dir().join(format!("mdr_test_strict_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let doc = dir.join("doc.md");
+    std::fs::write(&doc, "# Flow\n\n```mermaid\nnot a diagram\n```\n").unwrap();
+    let export = |strict: bool| {
+        let mut command = Command::new(mdr_bin());
+        command.arg(&doc).arg("--export").arg(dir.join("out.txt"));
+        if strict {
+            command.arg("--strict");
+        }
+        command.output().expect("failed to run mdr")
+    };
+
+    // Lenient by default: the diagram's source is exported instead
+    assert!(export(false).status.success());
+
+    let output = export(true);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "--strict should exit non-zero, got stderr: {}", stderr);
+    assert!(stderr.contains("mermaid diagram not rendered"), "stderr: {}", stderr);
+    assert!(stderr.contains("1 render failure(s)"), "stderr: {}", stderr);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn utf16_stdin_is_transcoded() {
+    let mut child = Command::new(mdr_bin())
+        .args(["--export", "-", "--no-color"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("# Grüße\n\n- één\n".encode_utf16().flat_map(u16::to_le_bytes));
+        stdin.write_all(&bytes).unwrap();
+    }
+
+    let output = child.wait_with_output().expect("failed to wait");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Grüße\n"), "unexpected output: {}", stdout);
+    assert!(stdout.contains("• één"), "unexpected output: {}", stdout);
+}